@@ -0,0 +1,523 @@
+use piston::input::UpdateArgs;
+use na::{Point2, FloatPoint};
+use std::f64;
+use std::str::FromStr;
+use std::time::Instant;
+use renderer::{Color, Renderer};
+use debug;
+use event;
+use export;
+use game;
+use math;
+use poolball;
+use replay;
+
+/**
+ * How well the computer plays: the number of shots it tries and how much its
+ * aim is off from the shot it chose
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+/**
+ * A computer player choosing shots by trying out candidate shots on copies of
+ * the game and keeping the one with the best outcome
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ai {
+    pub difficulty: Difficulty,
+    seed: u32,
+}
+
+/**
+ * The next shot of a computer player being worked out, trying the candidate
+ * shots one after another on a copy of the game taken when planning started
+ */
+#[derive(Clone)]
+pub struct Plan {
+    game: game::Game,
+    candidates: Vec<replay::Shot>,
+    tried: usize,
+    best: Option<(replay::Shot, f64)>,
+}
+
+/**
+ * A computer opponent taking turns with the player in the same game, each
+ * keeping the points scored in their own turns
+ */
+#[derive(Clone)]
+pub struct Opponent {
+    pub ai: Ai,
+    pub player_score: i32,
+    pub computer_score: i32,
+    computer_turn: bool,
+    shots: usize,
+    turn_start: i32,
+    planning: Option<Plan>,
+    aiming: Option<(replay::Shot, f64)>,
+}
+
+// Seconds spent trying candidate shots per update of the opponent, so that
+// the window keeps responding while the computer plans
+const PLAN_TIME: f64 = 0.005;
+
+// Longest time a candidate shot is followed for, in seconds
+const SIMULATION_TIME: f64 = 6.0;
+
+// Angles tried on either side of the line to each poolball, in radians
+const OFFSETS: [f64; 3] = [-0.05, 0.0, 0.05];
+
+// Weights of the outcomes of a shot besides the points scored: losing the
+// white ball or the game, a poolball swallowed by a blackhole and poolballs
+// brought closer to a goalzone accepting them
+const WHITE_LOST: f64 = 50.0;
+const GAME_OVER: f64 = 1000.0;
+const SWALLOWED: f64 = 20.0;
+const PROXIMITY: f64 = 5.0;
+
+// Time in seconds the computer shows its aim before shooting
+const AIM_TIME: f64 = 1.0;
+
+const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+const YELLOW: Color = [1.0, 0.85, 0.2, 1.0];
+
+impl Difficulty {
+    /**
+     * Returns the difficulty following this one, wrapping around after the
+     * hardest
+     */
+    pub fn next(&self) -> Difficulty {
+        match *self {
+            Difficulty::Easy => Difficulty::Medium,
+            Difficulty::Medium => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        }
+    }
+
+    /**
+     * Returns the name of the difficulty as used in files and on screen
+     */
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    /**
+     * Returns the number of directions tried around the white ball and the
+     * speeds tried in each, as shares of the highest speed
+     */
+    fn samples(&self) -> (usize, Vec<f64>) {
+        match *self {
+            Difficulty::Easy => (24, vec![0.5, 1.0]),
+            Difficulty::Medium => (48, vec![0.35, 0.7, 1.0]),
+            Difficulty::Hard => (72, vec![0.25, 0.5, 0.75, 1.0]),
+        }
+    }
+
+    /**
+     * Returns the largest error of the aim in radians, and of the speed as a
+     * share of the chosen speed
+     */
+    fn noise(&self) -> (f64, f64) {
+        match *self {
+            Difficulty::Easy => (0.08, 0.15),
+            Difficulty::Medium => (0.03, 0.05),
+            Difficulty::Hard => (0.0, 0.0),
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Difficulty, String> {
+        match name {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("unknown difficulty '{}'", name)),
+        }
+    }
+}
+
+impl Ai {
+    /**
+     * Creates a computer player of the given difficulty
+     */
+    pub fn new(difficulty: Difficulty) -> Ai {
+        Ai {
+            difficulty: difficulty,
+            seed: 1,
+        }
+    }
+
+    /**
+     * Chooses the next shot in the given game, with the error of the aim of
+     * the difficulty added. Nothing is chosen unless the white ball is ready
+     * to be shot
+     */
+    pub fn plan(&mut self, game: &game::Game) -> Option<replay::Shot> {
+        let mut plan = match self.start_plan(game) {
+            Some(plan) => plan,
+            None => return None,
+        };
+        while !plan.is_done() {
+            plan.try_next();
+        }
+        self.finish(&plan)
+    }
+
+    /**
+     * Starts planning the next shot in the given game, unless the white ball
+     * is not ready to be shot
+     */
+    pub fn start_plan(&self, game: &game::Game) -> Option<Plan> {
+        if !game.can_shoot() {
+            return None;
+        }
+
+        let (directions, speeds) = self.difficulty.samples();
+        Some(Plan {
+            game: game.clone(),
+            candidates: candidate_shots(game, directions, &speeds),
+            tried: 0,
+            best: None,
+        })
+    }
+
+    /**
+     * Returns the best shot found by the given plan, with the error of the aim
+     * of the difficulty added
+     */
+    pub fn finish(&mut self, plan: &Plan) -> Option<replay::Shot> {
+        let max_speed = plan.game.settings().max_shot_speed();
+        plan.best.as_ref().map(|&(ref shot, _)| {
+            let (angle_noise, speed_noise) = self.difficulty.noise();
+            let angle_error = 2.0 * math::random(&mut self.seed) - 1.0;
+            let speed_error = 2.0 * math::random(&mut self.seed) - 1.0;
+            replay::Shot {
+                angle: shot.angle + angle_noise * angle_error,
+                speed: (shot.speed * (1.0 + speed_noise * speed_error)).min(max_speed),
+                time: None,
+            }
+        })
+    }
+}
+
+impl Opponent {
+    /**
+     * Creates an opponent playing the given game with the given computer
+     * player, the player taking the first shot
+     */
+    pub fn new(ai: Ai, game: &game::Game) -> Opponent {
+        Opponent {
+            ai: ai,
+            player_score: 0,
+            computer_score: 0,
+            computer_turn: false,
+            shots: game.shots().len(),
+            turn_start: game.score(),
+            planning: None,
+            aiming: None,
+        }
+    }
+
+    /**
+     * Returns `true` while it is the turn of the computer, during which the
+     * player may not shoot
+     */
+    pub fn is_computer_turn(&self) -> bool {
+        self.computer_turn
+    }
+
+    /**
+     * Follows the game after an update of the given time: hands over the turn
+     * once a shot has come to rest, and aims and takes the shots of the
+     * computer in its turns
+     */
+    pub fn update(&mut self, game: &mut game::Game, delta_time: f64) {
+        if game.shots().len() > self.shots {
+            if !game.is_at_rest() {
+                return;
+            }
+            let scored = game.score() - self.turn_start;
+            if self.computer_turn {
+                self.computer_score += scored;
+            } else {
+                self.player_score += scored;
+            }
+            self.shots = game.shots().len();
+            self.turn_start = game.score();
+            self.computer_turn = !self.computer_turn;
+        }
+
+        if !self.computer_turn || !game.can_shoot() {
+            return;
+        }
+
+        // Work out the shot a little every update, then show the aim for a
+        // while before shooting
+        match self.aiming.take() {
+            None => {
+                if self.planning.is_none() {
+                    self.planning = self.ai.start_plan(game);
+                }
+                let done = match self.planning {
+                    Some(ref mut plan) => {
+                        plan.advance(PLAN_TIME);
+                        plan.is_done()
+                    }
+                    None => false,
+                };
+                if done {
+                    let plan = self.planning.take().unwrap();
+                    if let Some(shot) = self.ai.finish(&plan) {
+                        game.aim(&shot);
+                        self.aiming = Some((shot, AIM_TIME));
+                    }
+                }
+            }
+            Some((shot, left)) => {
+                game.aim(&shot);
+                if left > delta_time {
+                    self.aiming = Some((shot, left - delta_time));
+                } else {
+                    game.shoot(&shot);
+                }
+            }
+        }
+    }
+
+    /**
+     * Renders the scores of both sides and whose turn it is
+     */
+    pub fn render(&self, renderer: &mut Renderer) {
+        let scores = format!("You: {}  Computer: {}", self.player_score, self.computer_score);
+        renderer.text(WHITE, 12, Point2::new(0.02, 0.025), &scores);
+        let turn = if self.computer_turn { "Computer's turn" } else { "Your turn" };
+        renderer.text(YELLOW, 12, Point2::new(0.02, 0.045), turn);
+    }
+}
+
+impl Plan {
+    /**
+     * Returns `true` once every candidate shot has been tried
+     */
+    pub fn is_done(&self) -> bool {
+        self.tried == self.candidates.len()
+    }
+
+    /**
+     * Tries candidate shots until the given number of seconds has passed, at
+     * least one unless every candidate has been tried
+     */
+    pub fn advance(&mut self, seconds: f64) {
+        let started = Instant::now();
+        while !self.is_done() {
+            self.try_next();
+            if debug::seconds(started.elapsed()) >= seconds {
+                break;
+            }
+        }
+    }
+
+    /**
+     * Tries the next candidate shot, keeping it if it is the best so far
+     */
+    fn try_next(&mut self) {
+        let shot = self.candidates[self.tried].clone();
+        let value = evaluate(&self.game, &shot);
+        if self.best.as_ref().map_or(true, |&(_, best_value)| value > best_value) {
+            self.best = Some((shot, value));
+        }
+        self.tried += 1;
+    }
+}
+
+/**
+ * Returns the shots worth trying in the given game: the given number of evenly
+ * spread directions at each of the given speeds, as shares of the highest
+ * speed, and straight at each other poolball or just beside it
+ */
+pub fn candidate_shots(game: &game::Game,
+                       directions: usize,
+                       speeds: &[f64])
+                       -> Vec<replay::Shot> {
+    let white = match game.balls()
+        .iter()
+        .find(|ball| ball.ball_type == poolball::BallType::White) {
+        Some(white) => white.position,
+        None => return Vec::new(),
+    };
+
+    let mut angles: Vec<f64> = (0..directions)
+        .map(|i| i as f64 * 2.0 * f64::consts::PI / directions as f64)
+        .collect();
+    for ball in game.balls() {
+        if ball.ball_type == poolball::BallType::White {
+            continue;
+        }
+        let line = ball.position - white;
+        for offset in &OFFSETS {
+            angles.push(line.y.atan2(line.x) + offset);
+        }
+    }
+
+    let mut shots = Vec::new();
+    for &angle in &angles {
+        for &share in speeds {
+            shots.push(replay::Shot {
+                angle: angle,
+                speed: share * game.settings().max_shot_speed(),
+                time: None,
+            });
+        }
+    }
+    shots
+}
+
+/**
+ * Plays the given shot on a copy of the game until the table comes to rest,
+ * returning how good the outcome is: the points scored, less penalties for
+ * losing the white ball or the game and for poolballs swallowed by
+ * blackholes, plus a little for poolballs brought closer to goalzones
+ */
+pub fn evaluate(game: &game::Game, shot: &replay::Shot) -> f64 {
+    let mut game = game.clone();
+    let start = game.score();
+    let spread_before = spread(&game);
+    if !game.shoot(shot) {
+        return f64::NEG_INFINITY;
+    }
+
+    let args = UpdateArgs { dt: 1.0 / export::UPDATES_PER_SECOND as f64 };
+    let updates = (SIMULATION_TIME * export::UPDATES_PER_SECOND as f64) as usize;
+    let mut value = 0.0;
+    for _ in 0..updates {
+        game.update(&args);
+        for event in game.events() {
+            match *event {
+                event::Event::Potted { ball_type: poolball::BallType::White, .. } |
+                event::Event::Swallowed { ball_type: poolball::BallType::White, .. } => {
+                    value -= WHITE_LOST;
+                }
+                event::Event::Swallowed { .. } => value -= SWALLOWED,
+                _ => {}
+            }
+        }
+        if game.is_over() {
+            return value - GAME_OVER;
+        }
+        if game.is_at_rest() {
+            break;
+        }
+    }
+
+    value + (game.score() - start) as f64 + PROXIMITY * (spread_before - spread(&game))
+}
+
+/**
+ * Returns the summed distance from every poolball but the white one to the
+ * nearest goalzone accepting it
+ */
+pub fn spread(game: &game::Game) -> f64 {
+    let mut total = 0.0;
+    for ball in game.balls() {
+        if ball.ball_type == poolball::BallType::White {
+            continue;
+        }
+        let nearest = game.goalzones()
+            .iter()
+            .filter(|zone| zone.accepts(ball))
+            .map(|zone| zone.position().distance(&ball.position))
+            .fold(f64::INFINITY, |nearest, distance| nearest.min(distance));
+        if nearest.is_finite() {
+            total += nearest;
+        }
+    }
+    total
+}
+
+#[test]
+fn test_plan_pots() {
+    let game = game::test_game("
+        ball white 0.3 0.5
+        ball red 0.6 0.5
+        goalzone 0.9 0.5 accepts=red
+    ");
+
+    // The red ball lies straight ahead of the goalzone
+    let shot = Ai::new(Difficulty::Hard).plan(&game).unwrap();
+    assert!(shot.angle.abs() < 0.2);
+    assert!(evaluate(&game, &shot) >= 10.0);
+}
+
+#[test]
+fn test_evaluate_blackhole() {
+    let game = game::test_game("
+        ball white 0.3 0.5
+        ball red 0.1 0.1
+        blackhole 0.6 0.5 0.001 0.03 0.1
+    ");
+
+    // Shooting the white ball into the blackhole loses the game
+    let into = replay::Shot {
+        angle: 0.0,
+        speed: 0.8,
+        time: None,
+    };
+    let away = replay::Shot {
+        angle: f64::consts::PI,
+        speed: 0.1,
+        time: None,
+    };
+    assert!(evaluate(&game, &into) <= -GAME_OVER);
+    assert!(evaluate(&game, &away) > evaluate(&game, &into));
+}
+
+#[test]
+fn test_noise() {
+    let game = game::test_game("
+        ball white 0.3 0.5
+        ball red 0.6 0.5
+        goalzone 0.9 0.5
+    ");
+
+    // Easier players miss by a little, differently every shot
+    let mut ai = Ai::new(Difficulty::Easy);
+    let first = ai.plan(&game).unwrap();
+    let second = ai.plan(&game).unwrap();
+    assert!(first != second);
+    assert!((first.angle - second.angle).abs() <= 2.0 * Difficulty::Easy.noise().0);
+    assert!(first.speed <= game.settings().max_shot_speed());
+
+    assert_eq!("medium".parse::<Difficulty>(), Ok(Difficulty::Medium));
+    assert!("impossible".parse::<Difficulty>().is_err());
+}
+
+#[test]
+fn test_plan_in_steps() {
+    let game = game::test_game("
+        ball white 0.3 0.5
+        ball red 0.6 0.5
+        goalzone 0.9 0.5 accepts=red
+    ");
+
+    // Trying a candidate at a time finds the same shot as planning at once
+    let mut ai = Ai::new(Difficulty::Hard);
+    let mut plan = ai.start_plan(&game).unwrap();
+    let mut steps = 0;
+    while !plan.is_done() {
+        plan.advance(0.0);
+        steps += 1;
+    }
+    assert_eq!(steps, plan.candidates.len());
+    assert_eq!(ai.finish(&plan), ai.plan(&game));
+}
@@ -1,20 +1,20 @@
-use piston::input::RenderArgs;
-use opengl_graphics::GlGraphics;
-use graphics::Transformed;
-use graphics::line;
 use na::{Norm, Point2, Vector1, Vector2, Rotation2, Rotate};
 use std::f64;
+use renderer;
 
 /**
  * Basic arrow entity which is used for drawing the direction and power in the
  * shooting mode
  */
+#[derive(Clone, Debug, PartialEq)]
 pub struct Arrow {
     pub position: Point2<f64>,
     pub direction: Vector2<f64>,
     pub length: f64,
     pub mode: ShootingMode,
     pub time_passed: f64,
+    pub rotation_speed: f64,
+    pub max_length: f64,
 }
 
 /**
@@ -28,21 +28,22 @@ pub enum ShootingMode {
     Power,
 }
 
-const ROTATION_SPEED: f64 = 1.0;
-const MAX_LENGTH: f64 = 0.2;
 const DEFAULT_LENGTH: f64 = 0.1;
 
 impl Arrow {
     /**
-     * Constructs a new Arrow at the given position
+     * Constructs a new Arrow at the given position, rotating with the given
+     * speed in radians per second and growing up to the given length
      */
-    pub fn new(position: Point2<f64>) -> Arrow {
+    pub fn new(position: Point2<f64>, rotation_speed: f64, max_length: f64) -> Arrow {
         Arrow {
             position: position,
             direction: Vector2::new(1.0, 1.0).normalize(),
             length: DEFAULT_LENGTH,
             mode: ShootingMode::Rotate,
             time_passed: 0.0,
+            rotation_speed: rotation_speed,
+            max_length: max_length,
         }
     }
 
@@ -53,7 +54,7 @@ impl Arrow {
         match self.mode {
             // Rotates the arrow indicator
             ShootingMode::Rotate => {
-                let angle = Vector1::new(ROTATION_SPEED) * delta_time;
+                let angle = Vector1::new(self.rotation_speed) * delta_time;
                 let rotation = Rotation2::new(angle);
                 self.direction = rotation.rotate(&self.direction);
 
@@ -64,30 +65,29 @@ impl Arrow {
             // Changes the size of the arrow indicator
             ShootingMode::Power => {
                 self.time_passed += delta_time;
-                let new_length = MAX_LENGTH * (self.time_passed).sin().abs();
+                let new_length = self.max_length * (self.time_passed).sin().abs();
                 self.length = new_length;
             }
         }
     }
 
+    /**
+     * Returns the power of the shot between 0 and 1, the share of the longest
+     * arrow
+     */
+    pub fn power(&self) -> f64 {
+        self.length / self.max_length
+    }
+
     /**
      * Renders itself using the given graphics
      */
-    pub fn render(&self, args: &RenderArgs, gl: &mut GlGraphics) {
+    pub fn render(&self, renderer: &mut renderer::Renderer) {
 
         const MAGENTA: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
 
-        let line = line::Line::new(MAGENTA, 0.001);
-        gl.draw(args.viewport(), |c, gl| {
-
-            let trans = c.transform
-                .scale(args.width as f64, args.height as f64)
-                .trans(self.position.x, self.position.y);
-
-            // Draw the line
-            let pos_line =
-                [0.0, 0.0, self.direction.x * self.length, self.direction.y * self.length];
-            line.draw_arrow(pos_line, 0.1 * self.length, &c.draw_state, trans, gl);
-        });
+        // Draw the line
+        let tip = self.position + self.direction * self.length;
+        renderer.arrow(MAGENTA, self.position, tip, 0.002, 0.1 * self.length);
     }
 }
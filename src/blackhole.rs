@@ -1,24 +1,41 @@
-use piston::input::RenderArgs;
-use opengl_graphics::GlGraphics;
-use graphics::Transformed;
-use graphics::circle_arc;
-use graphics::ellipse;
-use graphics::radians::Radians;
 use na::Point2;
 use poolball;
+use renderer;
 use std::f64;
 
 /**
  * Blackhole contains information about a single blackhole in the game, and
  * methods for rendering and interacting with other entities
  */
+#[derive(Clone, Debug, PartialEq)]
 pub struct Blackhole {
     pub position: Point2<f64>,
     pub mass: f64,
     pub radius: f64,
     pub reach: f64,
+    pub accretion: Option<Accretion>,
+    initial_mass: f64,
+    initial_radius: f64,
+    initial_reach: f64,
 }
 
+/**
+ * Settings for blackholes growing as they swallow poolballs. The blackhole
+ * gains `growth` times the mass of every swallowed poolball, and loses `decay`
+ * of its gained mass per second. The radius grows linearly with the mass and
+ * the reach with its square root, keeping the pull at the edge of the reach
+ * the same
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Accretion {
+    pub growth: f64,
+    pub decay: f64,
+}
+
+// Smallest radius the blackhole core is drawn with, the actual radius usually
+// being far too small to see
+const CORE_RADIUS: f64 = 0.004;
+
 impl Blackhole {
     /**
      * Constructs a new Blackhole with the given properties
@@ -29,13 +46,90 @@ impl Blackhole {
             mass: mass,
             radius: radius,
             reach: reach,
+            accretion: None,
+            initial_mass: mass,
+            initial_radius: radius,
+            initial_reach: reach,
+        }
+    }
+
+    /**
+     * Makes the blackhole grow when swallowing poolballs
+     */
+    pub fn with_accretion(mut self, growth: f64, decay: f64) -> Blackhole {
+        self.accretion = Some(Accretion {
+            growth: growth,
+            decay: decay,
+        });
+        self
+    }
+
+    /**
+     * Changes the mass, radius and reach of the blackhole, including the ones
+     * it shrinks back to after accretion
+     */
+    pub fn set_size(&mut self, mass: f64, radius: f64, reach: f64) {
+        self.mass = mass;
+        self.radius = radius;
+        self.reach = reach;
+        self.initial_mass = mass;
+        self.initial_radius = radius;
+        self.initial_reach = reach;
+    }
+
+    /**
+     * Multiplies the mass and the reach of the blackhole by the given factors,
+     * keeping any mass gained by accretion in proportion
+     */
+    pub fn scale(&mut self, mass: f64, reach: f64) {
+        self.mass *= mass;
+        self.initial_mass *= mass;
+        self.reach *= reach;
+        self.initial_reach *= reach;
+    }
+
+    /**
+     * Swallows the given poolball, growing the blackhole if accretion is
+     * enabled
+     */
+    pub fn swallow(&mut self, poolball: &poolball::Poolball) {
+        if let Some(ref accretion) = self.accretion {
+            self.mass += accretion.growth * poolball.mass;
         }
+        self.resize();
     }
 
     /**
-     * Returns `true` if the given poolball is in contact with the blackhole
+     * Lets the mass gained by accretion decay for the given time
+     */
+    pub fn update(&mut self, delta_time: f64) {
+        if let Some(ref accretion) = self.accretion {
+            let gained = self.mass - self.initial_mass;
+            self.mass = self.initial_mass + gained * (-accretion.decay * delta_time).exp();
+        }
+        self.resize();
+    }
+
+    /**
+     * Scales the radius and reach to the current mass
+     */
+    fn resize(&mut self) {
+        if self.initial_mass <= 0.0 {
+            return;
+        }
+        let ratio = self.mass / self.initial_mass;
+        self.radius = self.initial_radius * ratio;
+        self.reach = self.initial_reach * ratio.sqrt();
+    }
+
+    /**
+     * Returns `true` if the given poolball is in contact with the blackhole.
+     * Poolballs immune to blackholes are never spagettified
      */
     pub fn is_spagettified(&self, poolball: &poolball::Poolball) -> bool {
+        if poolball.immune {
+            return false;
+        }
         let distance = ((self.position.x - poolball.position.x).powi(2) +
                         (self.position.y - poolball.position.y).powi(2))
             .sqrt();
@@ -45,24 +139,24 @@ impl Blackhole {
     /**
      * Renders itself using the given graphics
      */
-    pub fn render(&self, args: &RenderArgs, gl: &mut GlGraphics) {
+    pub fn render(&self, renderer: &mut renderer::Renderer) {
 
         const YELLOW: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+        const ORANGE: [f32; 4] = [1.0, 0.5, 0.0, 1.0];
 
-        // Piston rs: very strange, rewrite
-        let arc = circle_arc::CircleArc::new(YELLOW, 0.001, 0.001, Radians::_360());
-        gl.draw(args.viewport(), |c, gl| {
+        // The core grows with the mass swallowed by the blackhole
+        let growth = if self.initial_mass > 0.0 {
+            (self.mass / self.initial_mass).sqrt()
+        } else {
+            1.0
+        };
+        let core_radius = self.radius.max(CORE_RADIUS * growth);
 
-            let trans = c.transform
-                .scale(args.width as f64, args.height as f64)
-                .trans(self.position.x, self.position.y);
+        // Draw the reach of the blackhole
+        renderer.ring(YELLOW, self.position, self.reach, 0.002);
 
-            // Draw the cue ball
-            arc.draw(ellipse::circle(0.0, 0.0, self.reach),
-                     &c.draw_state,
-                     trans,
-                     gl);
-        });
+        // Draw the core
+        renderer.circle(ORANGE, self.position, core_radius);
     }
 }
 
@@ -81,4 +175,58 @@ fn test_is_spagettified() {
     assert!(bh.is_spagettified(&pb4));
     assert!(!bh.is_spagettified(&pb5));
     assert!(!bh.is_spagettified(&pb6));
+
+    let ghost = poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Ghost);
+    assert!(!bh.is_spagettified(&ghost));
+}
+
+#[test]
+fn test_accretion() {
+    let ball = poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Red);
+
+    // Without accretion the blackhole stays the same
+    let mut bh = Blackhole::new(Point2::new(0.0, 0.0), 1.0, 1.0, 1.0);
+    bh.swallow(&ball);
+    assert_eq!(bh.mass, 1.0);
+
+    let mut bh = Blackhole::new(Point2::new(0.0, 0.0), 0.1, 1.0, 1.0).with_accretion(3.0, 0.0);
+    bh.swallow(&ball);
+    assert!((bh.mass - 0.4).abs() < 0.0001);
+    assert!((bh.radius - 4.0).abs() < 0.0001);
+    assert!((bh.reach - 2.0).abs() < 0.0001);
+
+    // Without decay the gained mass stays
+    bh.update(10.0);
+    assert!((bh.mass - 0.4).abs() < 0.0001);
+}
+
+#[test]
+fn test_accretion_decay() {
+    let ball = poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Red);
+    let mut bh = Blackhole::new(Point2::new(0.0, 0.0), 0.1, 1.0, 1.0).with_accretion(1.0, 0.5);
+    bh.swallow(&ball);
+    bh.update(2.0_f64.ln() / 0.5);
+
+    // Half of the gained mass is lost after one half-life
+    assert!((bh.mass - 0.15).abs() < 0.0001);
+
+    bh.update(1000.0);
+    assert!((bh.mass - 0.1).abs() < 0.0001);
+    assert!((bh.reach - 1.0).abs() < 0.0001);
+}
+
+#[test]
+fn test_scale() {
+    let ball = poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Red);
+    let mut bh = Blackhole::new(Point2::new(0.0, 0.0), 0.1, 1.0, 1.0).with_accretion(1.0, 0.5);
+    bh.swallow(&ball);
+    bh.scale(2.0, 0.5);
+    assert!((bh.mass - 0.4).abs() < 0.0001);
+    assert!((bh.reach - 0.5 * 2.0_f64.sqrt()).abs() < 0.0001);
+
+    // The scaled blackhole shrinks back to the scaled size
+    bh.update(1000.0);
+    assert!((bh.mass - 0.2).abs() < 0.0001);
+    assert!((bh.radius - 1.0).abs() < 0.0001);
+    assert!((bh.reach - 0.5).abs() < 0.0001);
 }
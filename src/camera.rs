@@ -0,0 +1,160 @@
+use na::{Point2, Vector2};
+
+/**
+ * Maps table coordinates to pixels of a viewport, keeping the aspect ratio of
+ * the table. Without zoom the whole table fits in the middle of the viewport,
+ * leaving bars on the longer sides, and zooming and panning moves the point of
+ * the table in the middle of the viewport.
+ *
+ * Overlays such as the score are drawn in view coordinates, which are placed
+ * like the table without zoom or pan, so they stay put while moving around
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub center: Point2<f64>,
+    pub zoom: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+const MIN_ZOOM: f64 = 0.5;
+const MAX_ZOOM: f64 = 8.0;
+
+impl Camera {
+    /**
+     * Creates a camera showing the whole table in a viewport of the given size
+     * in pixels
+     */
+    pub fn new(width: f64, height: f64) -> Camera {
+        Camera {
+            center: Point2::new(0.5, 0.5),
+            zoom: 1.0,
+            width: width,
+            height: height,
+        }
+    }
+
+    /**
+     * Changes the size of the viewport, keeping the zoom and the point of the
+     * table in the middle
+     */
+    pub fn resize(&mut self, width: f64, height: f64) {
+        self.width = width;
+        self.height = height;
+    }
+
+    /**
+     * Shows the whole table again
+     */
+    pub fn reset(&mut self) {
+        self.center = Point2::new(0.5, 0.5);
+        self.zoom = 1.0;
+    }
+
+    /**
+     * Returns the number of pixels per table unit
+     */
+    pub fn scale(&self) -> f64 {
+        self.width.min(self.height) * self.zoom
+    }
+
+    /**
+     * Converts a position on the table to a position in pixels
+     */
+    pub fn to_screen(&self, point: &Point2<f64>) -> Point2<f64> {
+        let middle = Point2::new(self.width / 2.0, self.height / 2.0);
+        middle + (*point - self.center) * self.scale()
+    }
+
+    /**
+     * Converts a position in pixels, such as the mouse cursor, to a position
+     * on the table
+     */
+    pub fn to_world(&self, pixel: &Point2<f64>) -> Point2<f64> {
+        let middle = Point2::new(self.width / 2.0, self.height / 2.0);
+        self.center + (*pixel - middle) / self.scale()
+    }
+
+    /**
+     * Converts a position in view coordinates to a position in pixels
+     */
+    pub fn view_to_screen(&self, point: &Point2<f64>) -> Point2<f64> {
+        Camera::new(self.width, self.height).to_screen(point)
+    }
+
+    /**
+     * Converts a position in pixels to a position in view coordinates, such as
+     * the mouse cursor over a menu
+     */
+    pub fn to_view(&self, pixel: &Point2<f64>) -> Point2<f64> {
+        Camera::new(self.width, self.height).to_world(pixel)
+    }
+
+    /**
+     * Zooms in by the given factor, or out for factors below one, keeping the
+     * point of the table under the given pixel in place
+     */
+    pub fn zoom_at(&mut self, pixel: &Point2<f64>, factor: f64) {
+        let anchor = self.to_world(pixel);
+        self.zoom = (self.zoom * factor).max(MIN_ZOOM).min(MAX_ZOOM);
+
+        let moved = self.to_world(pixel);
+        self.center = self.center + (anchor - moved);
+        self.clamp();
+    }
+
+    /**
+     * Moves the view of the table along with a drag of the given number of
+     * pixels
+     */
+    pub fn pan(&mut self, pixels: Vector2<f64>) {
+        self.center = self.center - pixels / self.scale();
+        self.clamp();
+    }
+
+    /**
+     * Keeps the middle of the viewport on the table
+     */
+    fn clamp(&mut self) {
+        self.center = Point2::new(self.center.x.max(0.0).min(1.0),
+                                  self.center.y.max(0.0).min(1.0));
+    }
+}
+
+#[test]
+fn test_letterbox() {
+    // Wide viewports keep the table square in the middle
+    let camera = Camera::new(200.0, 100.0);
+    assert_eq!(camera.to_screen(&Point2::new(0.0, 0.0)), Point2::new(50.0, 0.0));
+    assert_eq!(camera.to_screen(&Point2::new(1.0, 1.0)), Point2::new(150.0, 100.0));
+    assert_eq!(camera.to_world(&Point2::new(100.0, 25.0)), Point2::new(0.5, 0.25));
+}
+
+#[test]
+fn test_zoom_and_pan() {
+    let mut camera = Camera::new(100.0, 100.0);
+    let cursor = Point2::new(20.0, 70.0);
+    let anchor = camera.to_world(&cursor);
+
+    // The point under the cursor stays in place
+    camera.zoom_at(&cursor, 2.0);
+    assert_eq!(camera.zoom, 2.0);
+    let moved = camera.to_world(&cursor);
+    assert!((moved.x - anchor.x).abs() < 0.000001 && (moved.y - anchor.y).abs() < 0.000001);
+
+    // View coordinates ignore zoom and pan
+    assert_eq!(camera.view_to_screen(&Point2::new(0.5, 0.5)), Point2::new(50.0, 50.0));
+    assert_eq!(camera.to_view(&Point2::new(50.0, 50.0)), Point2::new(0.5, 0.5));
+
+    camera.pan(Vector2::new(20.0, 0.0));
+    assert!((camera.to_world(&Point2::new(40.0, 70.0)).x - anchor.x).abs() < 0.000001);
+
+    // The middle of the view stays on the table
+    camera.pan(Vector2::new(-10000.0, 10000.0));
+    assert_eq!(camera.center, Point2::new(1.0, 0.0));
+    camera.zoom_at(&cursor, 1000.0);
+    assert_eq!(camera.zoom, MAX_ZOOM);
+
+    camera.reset();
+    assert_eq!(camera, Camera::new(100.0, 100.0));
+}
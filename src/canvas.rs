@@ -0,0 +1,347 @@
+use na::{Point2, Vector2, Dot};
+use std::cmp;
+use std::f64;
+use renderer::{Color, Renderer};
+use camera;
+
+/**
+ * A software renderer drawing into an RGBA buffer in memory, for producing
+ * frames without a GPU. The table is drawn as large as fits in the middle of
+ * the canvas, and text is drawn with a small built-in bitmap font
+ */
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    pub camera: camera::Camera,
+}
+
+// Size in font pixels of a glyph of the built-in font, and of the space it
+// takes up including the gap to the next glyph
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const GLYPH_ADVANCE: usize = 6;
+
+impl Canvas {
+    /**
+     * Creates a transparent canvas of the given size in pixels
+     */
+    pub fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            width: width,
+            height: height,
+            pixels: vec![0; width * height * 4],
+            camera: camera::Camera::new(width as f64, height as f64),
+        }
+    }
+
+    /**
+     * Draws the table as seen by the given camera
+     */
+    pub fn with_camera(mut self, camera: camera::Camera) -> Canvas {
+        self.camera = camera;
+        self.camera.resize(self.width as f64, self.height as f64);
+        self
+    }
+
+    /**
+     * Returns the color of the pixel at the given position
+     */
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let index = (y * self.width + x) * 4;
+        [self.pixels[index],
+         self.pixels[index + 1],
+         self.pixels[index + 2],
+         self.pixels[index + 3]]
+    }
+
+    /**
+     * Converts a position on the table to a position in pixels
+     */
+    fn to_pixels(&self, point: &Point2<f64>) -> Point2<f64> {
+        self.camera.to_screen(point)
+    }
+
+    /**
+     * Blends the given color over the pixel at the given position, with the
+     * given share of the pixel covered
+     */
+    fn blend(&mut self, x: usize, y: usize, color: Color, coverage: f64) {
+        let alpha = color[3] as f64 * coverage.min(1.0);
+        if alpha <= 0.0 {
+            return;
+        }
+
+        let index = (y * self.width + x) * 4;
+        for channel in 0..3 {
+            let old = self.pixels[index + channel] as f64 / 255.0;
+            let new = color[channel] as f64 * alpha + old * (1.0 - alpha);
+            self.pixels[index + channel] = (new * 255.0).round() as u8;
+        }
+        let old = self.pixels[index + 3] as f64 / 255.0;
+        self.pixels[index + 3] = ((alpha + old * (1.0 - alpha)) * 255.0).round() as u8;
+    }
+
+    /**
+     * Blends the given color over every pixel within the given bounds (in
+     * pixels), covered by the share given for the center of each pixel
+     */
+    fn paint<F>(&mut self, color: Color, min: Point2<f64>, max: Point2<f64>, coverage: F)
+        where F: Fn(Point2<f64>) -> f64
+    {
+        let (min_x, max_x) = pixel_range(min.x, max.x, self.width);
+        let (min_y, max_y) = pixel_range(min.y, max.y, self.height);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let covered = coverage(Point2::new(x as f64 + 0.5, y as f64 + 0.5));
+                if covered > 0.0 {
+                    self.blend(x, y, color, covered);
+                }
+            }
+        }
+    }
+
+    /**
+     * Returns half the width in pixels of a line of the given thickness,
+     * keeping thin lines at least a pixel wide
+     */
+    fn half_width(&self, thickness: f64) -> f64 {
+        (thickness * self.camera.scale()).max(1.0) / 2.0
+    }
+}
+
+impl Renderer for Canvas {
+    fn clear(&mut self, color: Color) {
+        for pixel in self.pixels.chunks_mut(4) {
+            for channel in 0..4 {
+                pixel[channel] = (color[channel].max(0.0).min(1.0) * 255.0).round() as u8;
+            }
+        }
+    }
+
+    fn circle(&mut self, color: Color, center: Point2<f64>, radius: f64) {
+        let center = self.to_pixels(&center);
+        let radius = radius * self.camera.scale();
+        let extent = Vector2::new(radius + 1.0, radius + 1.0);
+
+        self.paint(color, center - extent, center + extent, |pixel| {
+            let offset = pixel - center;
+            radius - offset.x.hypot(offset.y) + 0.5
+        });
+    }
+
+    fn arc(&mut self,
+           color: Color,
+           center: Point2<f64>,
+           radius: f64,
+           thickness: f64,
+           start: f64,
+           end: f64) {
+        let center = self.to_pixels(&center);
+        let radius = radius * self.camera.scale();
+        let half_width = self.half_width(thickness);
+        let extent = Vector2::new(radius + half_width + 1.0,
+                                  radius + half_width + 1.0);
+        let full = 2.0 * f64::consts::PI;
+
+        self.paint(color, center - extent, center + extent, |pixel| {
+            let offset = pixel - center;
+            if end - start < full {
+                let angle = ((offset.y.atan2(offset.x) - start) % full + full) % full;
+                if angle > end - start {
+                    return 0.0;
+                }
+            }
+            half_width - (offset.x.hypot(offset.y) - radius).abs() + 0.5
+        });
+    }
+
+    fn line(&mut self, color: Color, from: Point2<f64>, to: Point2<f64>, thickness: f64) {
+        let from = self.to_pixels(&from);
+        let to = self.to_pixels(&to);
+        let half_width = self.half_width(thickness);
+        let min = Point2::new(from.x.min(to.x) - half_width - 1.0,
+                              from.y.min(to.y) - half_width - 1.0);
+        let max = Point2::new(from.x.max(to.x) + half_width + 1.0,
+                              from.y.max(to.y) + half_width + 1.0);
+
+        let direction = to - from;
+        let length_squared = direction.dot(&direction);
+
+        self.paint(color, min, max, |pixel| {
+            // Distance to the closest point on the line
+            let along = if length_squared > 0.0 {
+                ((pixel - from).dot(&direction) / length_squared).max(0.0).min(1.0)
+            } else {
+                0.0
+            };
+            let offset = pixel - (from + direction * along);
+            half_width - offset.x.hypot(offset.y) + 0.5
+        });
+    }
+
+    fn text(&mut self, color: Color, size: u32, position: Point2<f64>, text: &str) {
+        // Every font pixel is drawn as a block of pixels
+        let block = cmp::max(1, size as usize / GLYPH_HEIGHT);
+        let origin = self.camera.view_to_screen(&position);
+        let left = origin.x.round();
+        let top = origin.y.round() - (GLYPH_HEIGHT * block) as f64;
+
+        for (index, character) in text.chars().enumerate() {
+            let rows = glyph(character);
+            let glyph_left = left + (index * GLYPH_ADVANCE * block) as f64;
+            for (row, bits) in rows.iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                        continue;
+                    }
+                    let min = Point2::new(glyph_left + (column * block) as f64,
+                                          top + (row * block) as f64);
+                    let max = min + Vector2::new(block as f64, block as f64);
+                    self.paint(color, min, max, |_| 1.0);
+                }
+            }
+        }
+    }
+
+    fn to_view(&self, position: Point2<f64>) -> Point2<f64> {
+        self.camera.to_view(&self.camera.to_screen(&position))
+    }
+}
+
+/**
+ * Returns the range of pixel indices overlapping the given range, limited to
+ * the given size
+ */
+fn pixel_range(min: f64, max: f64, size: usize) -> (usize, usize) {
+    let limit = |value: f64| value.max(0.0).min(size as f64) as usize;
+    (limit(min.floor()), limit(max.ceil()))
+}
+
+/**
+ * Returns the rows of the glyph for the given character in the built-in font,
+ * the highest bit of each row being the leftmost pixel. Lowercase letters are
+ * drawn as uppercase, and unknown characters as question marks
+ */
+fn glyph(character: char) -> [u8; 7] {
+    match character.to_uppercase().next().unwrap_or(character) {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '\'' => [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '*' => [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        ';' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08],
+        '<' => [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '>' => [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08],
+        '[' => [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E],
+        ']' => [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '|' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+#[test]
+fn test_circle() {
+    let mut canvas = Canvas::new(100, 100);
+    canvas.clear([0.0, 0.0, 0.0, 1.0]);
+    canvas.circle([1.0, 0.0, 0.0, 1.0], Point2::new(0.5, 0.5), 0.1);
+
+    // Filled inside, untouched outside
+    assert_eq!(canvas.pixel(50, 50), [255, 0, 0, 255]);
+    assert_eq!(canvas.pixel(50, 42), [255, 0, 0, 255]);
+    assert_eq!(canvas.pixel(50, 30), [0, 0, 0, 255]);
+    assert_eq!(canvas.pixel(62, 50), [0, 0, 0, 255]);
+}
+
+#[test]
+fn test_blending() {
+    let mut canvas = Canvas::new(10, 10);
+    canvas.clear([0.0, 0.0, 0.0, 1.0]);
+    canvas.circle([1.0, 1.0, 1.0, 0.5], Point2::new(0.5, 0.5), 0.3);
+    assert_eq!(canvas.pixel(5, 5), [128, 128, 128, 255]);
+}
+
+#[test]
+fn test_ring_and_line() {
+    let mut canvas = Canvas::new(100, 100);
+    canvas.ring([0.0, 1.0, 0.0, 1.0], Point2::new(0.5, 0.5), 0.2, 0.02);
+
+    // The stroke spans 19 to 21 pixels from the center, so the pixel just
+    // inside its centreline is fully covered
+    assert_eq!(canvas.pixel(69, 50), [0, 255, 0, 255]);
+    assert_eq!(canvas.pixel(72, 50)[3], 0);
+    assert_eq!(canvas.pixel(50, 50)[3], 0);
+
+    canvas.line([0.0, 0.0, 1.0, 1.0], Point2::new(0.1, 0.1), Point2::new(0.9, 0.1), 0.02);
+    assert_eq!(canvas.pixel(50, 10), [0, 0, 255, 255]);
+    assert_eq!(canvas.pixel(50, 20)[3], 0);
+    assert_eq!(canvas.pixel(95, 10)[3], 0);
+}
+
+#[test]
+fn test_text() {
+    let mut canvas = Canvas::new(100, 100);
+    canvas.text([1.0, 1.0, 1.0, 1.0], 7, Point2::new(0.1, 0.1), "I");
+
+    // The middle column of the I, standing on the baseline
+    assert_eq!(canvas.pixel(12, 5), [255, 255, 255, 255]);
+    assert_eq!(canvas.pixel(12, 9), [255, 255, 255, 255]);
+    assert_eq!(canvas.pixel(12, 10)[3], 0);
+    assert_eq!(canvas.pixel(10, 5)[3], 0);
+}
+
+#[test]
+fn test_letterbox() {
+    // Wide canvases draw the table in the middle
+    let mut canvas = Canvas::new(200, 100);
+    canvas.circle([1.0, 1.0, 1.0, 1.0], Point2::new(0.0, 0.5), 0.05);
+    assert_eq!(canvas.pixel(50, 50), [255, 255, 255, 255]);
+    assert_eq!(canvas.pixel(2, 50)[3], 0);
+}
@@ -0,0 +1,223 @@
+use na::Point2;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use poolball;
+use parse;
+
+/**
+ * The physical properties and behaviour shared by all balls of one type.
+ * Balls with a blast radius push their neighbours away when potted, sticky
+ * balls merge with the balls they touch and immune balls are neither pulled
+ * nor swallowed by blackholes
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BallProperties {
+    pub mass: f64,
+    pub radius: f64,
+    pub restitution: f64,
+    pub value: i32,
+    pub color: [f32; 4],
+    pub blast_radius: f64,
+    pub sticky: bool,
+    pub immune: bool,
+}
+
+/**
+ * Catalogue of the properties of every ball type. Catalogues are stored as
+ * plain text with one ball type per line, in the same style as level files:
+ *
+ * ```text
+ * kind <type> [mass=<m>] [radius=<r>] [restitution=<e>] [value=<n>]
+ *             [color=<r>,<g>,<b>,<a>] [blast_radius=<r>] [sticky=<bool>]
+ *             [immune=<bool>]
+ * ```
+ *
+ * Properties left out keep their built-in defaults
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Catalogue {
+    kinds: HashMap<poolball::BallType, BallProperties>,
+}
+
+// The bundled catalogue, holding the built-in properties of every ball type
+const BUNDLED: &'static str = include_str!("../assets/balls.cat");
+
+// Properties the first line of a ball type has to give, the others being off
+// unless given
+const REQUIRED: [&'static str; 5] = ["mass", "radius", "restitution", "value", "color"];
+
+impl Catalogue {
+    /**
+     * Creates a catalogue holding the built-in properties of every ball type,
+     * as given by the catalogue bundled with the game
+     */
+    pub fn builtin() -> Catalogue {
+        let empty = Catalogue { kinds: HashMap::new() };
+        empty.extended(BUNDLED).expect("the bundled ball catalogue is invalid")
+    }
+
+    /**
+     * Reads and parses the catalogue file at the given path
+     */
+    pub fn load(path: &Path) -> Result<Catalogue, String> {
+        let mut source = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut source))
+            .map_err(|err| format!("could not read {}: {}", path.display(), err)));
+        Catalogue::parse(&source).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /**
+     * Reads and parses the catalogue file at the given path, using the
+     * built-in properties if there is no such file
+     */
+    pub fn load_or_default(path: &Path) -> Result<Catalogue, String> {
+        if !path.exists() {
+            return Ok(Catalogue::builtin());
+        }
+        Catalogue::load(path)
+    }
+
+    /**
+     * Parses a catalogue from its textual representation, on top of the
+     * built-in properties
+     */
+    pub fn parse(source: &str) -> Result<Catalogue, String> {
+        Catalogue::builtin().extended(source)
+    }
+
+    /**
+     * Returns the properties of the given ball type. The bundled catalogue
+     * defines every ball type, which every catalogue starts from
+     */
+    pub fn properties(&self, ball_type: &poolball::BallType) -> BallProperties {
+        self.kinds[ball_type].clone()
+    }
+
+    /**
+     * Creates a new stationary poolball of the given type at the given position
+     */
+    pub fn create(&self,
+                  position: Point2<f64>,
+                  ball_type: poolball::BallType)
+                  -> poolball::Poolball {
+        let properties = self.properties(&ball_type);
+        poolball::Poolball::with_properties(position, ball_type, &properties)
+    }
+
+    /**
+     * Returns this catalogue with the ball types of the given source changed,
+     * keeping the properties left out
+     */
+    fn extended(mut self, source: &str) -> Result<Catalogue, String> {
+        for line in parse::lines(source) {
+            let (ball_type, properties) = try!(self.parse_kind(&line)
+                .map_err(|err| format!("line {}: {}", line.number, err)));
+            self.kinds.insert(ball_type, properties);
+        }
+
+        Ok(self)
+    }
+
+    /**
+     * Parses a single `kind` line into the ball type and its properties
+     */
+    fn parse_kind(&self,
+                  line: &parse::Line)
+                  -> Result<(poolball::BallType, BallProperties), String> {
+        if line.kind != "kind" {
+            return Err(format!("unknown entry '{}'", line.kind));
+        }
+        try!(line.expect_arguments(1));
+
+        let ball_type = try!(line.arguments[0].parse::<poolball::BallType>());
+        let mut properties = match self.kinds.get(&ball_type) {
+            Some(properties) => properties.clone(),
+            None => {
+                for key in &REQUIRED {
+                    if !line.options.iter().any(|&(option, _)| option == *key) {
+                        return Err(format!("the new ball type {} needs a {}",
+                                           line.arguments[0],
+                                           key));
+                    }
+                }
+                BallProperties::default()
+            }
+        };
+
+        for &(key, value) in &line.options {
+            match key {
+                "mass" => properties.mass = try!(parse::parse_number(value)),
+                "radius" => properties.radius = try!(parse::parse_number(value)),
+                "restitution" => properties.restitution = try!(parse::parse_number(value)),
+                "value" => properties.value = try!(parse::parse_integer(value)),
+                "color" => properties.color = try!(parse::parse_color(value)),
+                "blast_radius" => properties.blast_radius = try!(parse::parse_number(value)),
+                "sticky" => properties.sticky = try!(parse::parse_bool(value)),
+                "immune" => properties.immune = try!(parse::parse_bool(value)),
+                _ => return Err(line.unknown_option(key)),
+            }
+        }
+
+        if properties.mass <= 0.0 || properties.radius <= 0.0 {
+            return Err(String::from("mass and radius must be positive"));
+        }
+
+        Ok((ball_type, properties))
+    }
+}
+
+#[test]
+fn test_parse() {
+    let source = "
+        kind iron mass=1.0 restitution=0.5
+        kind red color=0.5,0,0,1 value=12
+    ";
+    let catalogue = Catalogue::parse(source).unwrap();
+
+    let iron = catalogue.properties(&poolball::BallType::Iron);
+    assert_eq!(iron.mass, 1.0);
+    assert_eq!(iron.restitution, 0.5);
+    assert_eq!(iron.radius, Catalogue::builtin().properties(&poolball::BallType::Iron).radius);
+
+    let red = catalogue.create(Point2::new(0.0, 0.0), poolball::BallType::Red);
+    assert_eq!(red.get_value(), 12);
+    assert_eq!(red.color, [0.5, 0.0, 0.0, 1.0]);
+
+    // Types not in the file keep their defaults
+    assert_eq!(catalogue.properties(&poolball::BallType::Blue),
+               Catalogue::builtin().properties(&poolball::BallType::Blue));
+}
+
+#[test]
+fn test_builtin() {
+    // The built-in properties are those of the bundled catalogue, which
+    // defines every ball type
+    let catalogue = Catalogue::builtin();
+    for name in &["white", "red", "blue", "iron", "light", "large", "bomb", "sticky", "ghost"] {
+        assert!(catalogue.kinds.contains_key(&name.parse::<poolball::BallType>().unwrap()));
+    }
+    let white = catalogue.properties(&poolball::BallType::White);
+    assert_eq!(white.value, -100);
+    assert_eq!(white.color, [1.0, 1.0, 1.0, 1.0]);
+    assert!(catalogue.properties(&poolball::BallType::Sticky).sticky);
+    assert_eq!(catalogue.properties(&poolball::BallType::Bomb).blast_radius, 0.15);
+    assert_eq!(Catalogue::parse(BUNDLED).unwrap(), catalogue);
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(Catalogue::parse("kind purple mass=1.0").is_err());
+    assert!(Catalogue::parse("kind red weight=1.0").is_err());
+    assert!(Catalogue::parse("kind red mass=0").is_err());
+    assert!(Catalogue::parse("ball red").is_err());
+
+    // Ball types start out with every property not a flag given
+    let empty = Catalogue { kinds: HashMap::new() };
+    assert_eq!(empty.clone().extended("kind red mass=1 radius=0.1").unwrap_err(),
+               "line 1: the new ball type red needs a restitution");
+    assert!(empty.extended("kind red mass=1 radius=1 restitution=1 value=1 color=1,1,1,1")
+        .is_ok());
+}
@@ -0,0 +1,447 @@
+use opengl_graphics::OpenGL;
+use piston::input::UpdateArgs;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use catalogue;
+use debug;
+use export;
+use game;
+use level;
+use menu;
+use parse;
+use replay;
+use settings;
+use trace;
+#[cfg(test)]
+use std::f64;
+
+/**
+ * Settings of the game window given on the command line
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Window {
+    pub width: u32,
+    pub height: u32,
+    pub opengl: OpenGL,
+    pub fullscreen: bool,
+    pub vsync: bool,
+}
+
+// Game updates timed per level by the bench command, ten seconds of play
+const BENCH_UPDATES: usize = 1200;
+
+pub const USAGE: &'static str = "usage: rymdbiljard [flags] [command]
+
+commands:
+    [play] <level>                       play a level without the title screen
+    edit <level>                         edit a level, starting it if there is no such file
+    simulate <level> [--shots <replay>]  take the shots of a replay, printing the end as JSON
+    render <replay> --out <directory>    write the frames of a replay as PNG images
+    validate <level>                     check a level for mistakes
+    bench [--updates <updates>]          time the updates of every level
+    export, svg, terminal, solve, generate
+
+flags:
+    --width <pixels>, --height <pixels>  size of the window, 800x800 by default
+    --opengl <version>                   OpenGL version to use, 3.2 by default
+    --fullscreen                         fill the screen
+    --vsync                              wait for the display between frames";
+
+const SIMULATE_USAGE: &'static str = "usage: simulate <level> [--shots <replay>]";
+const RENDER_USAGE: &'static str = "usage: render <replay> --out <directory>";
+const VALIDATE_USAGE: &'static str = "usage: validate <level>";
+const BENCH_USAGE: &'static str = "usage: bench [--updates <updates>]";
+
+impl Window {
+    /**
+     * Creates the default settings: an 800x800 window using OpenGL 3.2
+     */
+    pub fn new() -> Window {
+        Window {
+            width: 800,
+            height: 800,
+            opengl: OpenGL::V3_2,
+            fullscreen: false,
+            vsync: false,
+        }
+    }
+}
+
+/**
+ * Takes the window flags out of the given arguments, wherever they are,
+ * returning the window settings and the arguments left for the command
+ */
+pub fn parse_window(arguments: &[String]) -> Result<(Window, Vec<String>), String> {
+    let mut window = Window::new();
+    let mut rest = Vec::new();
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--width" => {
+                window.width = try!(parse_pixels(try!(flag_value(argument, &mut arguments))))
+            }
+            "--height" => {
+                window.height = try!(parse_pixels(try!(flag_value(argument, &mut arguments))))
+            }
+            "--opengl" => {
+                window.opengl = try!(parse_opengl(try!(flag_value(argument, &mut arguments))))
+            }
+            "--fullscreen" => window.fullscreen = true,
+            "--vsync" => window.vsync = true,
+            _ => rest.push(argument.clone()),
+        }
+    }
+    Ok((window, rest))
+}
+
+/**
+ * Runs the simulate command with the given arguments, taking the shots of a
+ * replay on a level without drawing it and returning the final state of the
+ * game as JSON
+ */
+pub fn run_simulate(arguments: &[String],
+                    catalogue: catalogue::Catalogue,
+                    settings: &settings::Settings)
+                    -> Result<String, String> {
+    let (inputs, flags) = try!(split_flags(arguments, SIMULATE_USAGE));
+    let mut shots_path = None;
+    for &(flag, value) in &flags {
+        match flag {
+            "--shots" => shots_path = Some(value),
+            _ => return Err(format!("unknown flag '{}'\n{}", flag, SIMULATE_USAGE)),
+        }
+    }
+    if inputs.len() != 1 {
+        return Err(String::from(SIMULATE_USAGE));
+    }
+
+    let level = try!(level::Level::load(Path::new(inputs[0]), &catalogue));
+    let mut game = game::Game::new(level, catalogue);
+    game.set_settings(settings);
+    let shots = match shots_path {
+        Some(path) => {
+            let replay = try!(replay::Replay::load(Path::new(path)));
+            game.set_integrator(replay.integrator);
+            replay.shots
+        }
+        None => Vec::new(),
+    };
+    try!(export::play(&mut game, shots));
+    Ok(to_json(&game, inputs[0]))
+}
+
+/**
+ * Runs the render command with the given arguments, writing every frame of a
+ * replay to the given directory and returning the number of frames written
+ */
+pub fn run_render(arguments: &[String],
+                  catalogue: catalogue::Catalogue,
+                  game_settings: &settings::Settings)
+                  -> Result<usize, String> {
+    let (inputs, flags) = try!(split_flags(arguments, RENDER_USAGE));
+    let mut directory = None;
+    for &(flag, value) in &flags {
+        match flag {
+            "--out" => directory = Some(value),
+            _ => return Err(format!("unknown flag '{}'\n{}", flag, RENDER_USAGE)),
+        }
+    }
+    let directory = match (inputs.len(), directory) {
+        (1, Some(directory)) => Path::new(directory),
+        _ => return Err(String::from(RENDER_USAGE)),
+    };
+
+    let (game, shots) = try!(export::load_game(Path::new(inputs[0]), catalogue, game_settings));
+    try!(fs::create_dir_all(directory)
+        .map_err(|err| format!("could not create {}: {}", directory.display(), err)));
+
+    // Follow the replay until the table comes to rest after the last shot
+    let last = shots.iter()
+        .filter_map(|shot| shot.time)
+        .fold(0.0, |last: f64, time| last.max(time));
+    let settings = export::Settings {
+        duration: last + trace::MAX_TIME,
+        ..export::Settings::new()
+    };
+    export::export(game, shots, &directory.join("frame.png"), &settings)
+}
+
+/**
+ * Runs the validate command with the given arguments, returning every problem
+ * found in the level
+ */
+pub fn run_validate(arguments: &[String],
+                    catalogue: catalogue::Catalogue)
+                    -> Result<(), String> {
+    if arguments.len() != 1 {
+        return Err(String::from(VALIDATE_USAGE));
+    }
+    let level = try!(level::Level::load(Path::new(&arguments[0]), &catalogue));
+    let problems = level.problems();
+    if !problems.is_empty() {
+        return Err(format!("{} is invalid:\n{}", arguments[0], problems.join("\n")));
+    }
+    Ok(())
+}
+
+/**
+ * Runs the bench command with the given arguments, timing the same number of
+ * updates of every level in the levels directory and returning the number of
+ * levels timed
+ */
+pub fn run_bench(arguments: &[String],
+                 catalogue: catalogue::Catalogue,
+                 settings: &settings::Settings)
+                 -> Result<usize, String> {
+    let (inputs, flags) = try!(split_flags(arguments, BENCH_USAGE));
+    let mut updates = BENCH_UPDATES;
+    for &(flag, value) in &flags {
+        match flag {
+            "--updates" => updates = try!(parse_count(value)),
+            _ => return Err(format!("unknown flag '{}'\n{}", flag, BENCH_USAGE)),
+        }
+    }
+    if !inputs.is_empty() {
+        return Err(String::from(BENCH_USAGE));
+    }
+
+    let levels = try!(menu::find_levels(Path::new("levels")));
+    let args = UpdateArgs { dt: 1.0 / export::UPDATES_PER_SECOND as f64 };
+    for entry in &levels {
+        let level = try!(level::Level::load(Path::new(&entry.path), &catalogue));
+        let mut game = game::Game::new(level, catalogue.clone());
+        game.set_settings(settings);
+
+        let started = Instant::now();
+        for _ in 0..updates {
+            game.update(&args);
+        }
+        let seconds = debug::seconds(started.elapsed());
+        println!("{}: {} updates in {:.3} s, {:.0} updates per second",
+                 entry.name,
+                 updates,
+                 seconds,
+                 updates as f64 / seconds);
+    }
+    Ok(levels.len())
+}
+
+/**
+ * Returns the state of the given game of the level at the given path as a
+ * JSON object
+ */
+pub fn to_json(game: &game::Game, level: &str) -> String {
+    let balls: Vec<String> = game.balls()
+        .iter()
+        .map(|ball| {
+            format!("{{\"type\": \"{}\", \"x\": {}, \"y\": {}, \"vx\": {}, \"vy\": {}}}",
+                    ball.ball_type.name(),
+                    json_number(ball.position.x),
+                    json_number(ball.position.y),
+                    json_number(ball.velocity.x),
+                    json_number(ball.velocity.y))
+        })
+        .collect();
+    let blackholes: Vec<String> = game.blackholes()
+        .iter()
+        .map(|hole| {
+            format!("{{\"x\": {}, \"y\": {}, \"mass\": {}, \"radius\": {}, \"reach\": {}}}",
+                    json_number(hole.position.x),
+                    json_number(hole.position.y),
+                    json_number(hole.mass),
+                    json_number(hole.radius),
+                    json_number(hole.reach))
+        })
+        .collect();
+
+    let fields = vec![format!("\"level\": {}", json_string(level)),
+                      format!("\"time\": {}", json_number(game.time())),
+                      format!("\"score\": {}", game.score()),
+                      format!("\"shots\": {}", game.shots().len()),
+                      format!("\"over\": {}", game.is_over()),
+                      format!("\"cleared\": {}", game.is_cleared()),
+                      format!("\"balls\": [{}]", json_list(&balls)),
+                      format!("\"blackholes\": [{}]", json_list(&blackholes))];
+    format!("{{\n  {}\n}}", fields.join(",\n  "))
+}
+
+/**
+ * Returns the value following the given flag
+ */
+fn flag_value<'a, I>(flag: &str, arguments: &mut I) -> Result<&'a str, String>
+    where I: Iterator<Item = &'a String>
+{
+    arguments.next().map(|value| value.as_str()).ok_or(format!("{} needs a value", flag))
+}
+
+/**
+ * Splits the given arguments of a command into its inputs and its flags, each
+ * flag taking the argument after it as its value
+ */
+fn split_flags<'a>(arguments: &'a [String],
+                   usage: &str)
+                   -> Result<(Vec<&'a str>, Vec<(&'a str, &'a str)>), String> {
+    let mut inputs = Vec::new();
+    let mut flags = Vec::new();
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        if argument.starts_with("--") {
+            let value = try!(flag_value(argument, &mut arguments)
+                .map_err(|err| format!("{}\n{}", err, usage)));
+            flags.push((argument.as_str(), value));
+        } else {
+            inputs.push(argument.as_str());
+        }
+    }
+    Ok((inputs, flags))
+}
+
+/**
+ * Parses an OpenGL version such as `3.2`
+ */
+fn parse_opengl(version: &str) -> Result<OpenGL, String> {
+    match version {
+        "2.0" => Ok(OpenGL::V2_0),
+        "2.1" => Ok(OpenGL::V2_1),
+        "3.0" => Ok(OpenGL::V3_0),
+        "3.1" => Ok(OpenGL::V3_1),
+        "3.2" => Ok(OpenGL::V3_2),
+        "3.3" => Ok(OpenGL::V3_3),
+        "4.0" => Ok(OpenGL::V4_0),
+        "4.1" => Ok(OpenGL::V4_1),
+        "4.2" => Ok(OpenGL::V4_2),
+        "4.3" => Ok(OpenGL::V4_3),
+        "4.4" => Ok(OpenGL::V4_4),
+        "4.5" => Ok(OpenGL::V4_5),
+        _ => Err(format!("unknown OpenGL version '{}'", version)),
+    }
+}
+
+/**
+ * Parses a positive window size in pixels
+ */
+fn parse_pixels(value: &str) -> Result<u32, String> {
+    let pixels = try!(parse::parse_integer(value));
+    if pixels <= 0 || pixels > 65535 {
+        return Err(format!("'{}' is not a valid size", value));
+    }
+    Ok(pixels as u32)
+}
+
+/**
+ * Parses a positive whole number of a flag
+ */
+fn parse_count(value: &str) -> Result<usize, String> {
+    let count = try!(parse::parse_integer(value));
+    if count < 1 {
+        return Err(format!("'{}' is not a positive number", value));
+    }
+    Ok(count as usize)
+}
+
+/**
+ * Returns the given number as JSON, which has no infinities or NaN
+ */
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{}", value)
+    } else {
+        String::from("null")
+    }
+}
+
+/**
+ * Returns the given text as a quoted JSON string
+ */
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for character in text.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            character if (character as u32) < 0x20 => {
+                quoted.push_str(&format!("\\u{:04x}", character as u32))
+            }
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/**
+ * Returns the given JSON values as the inside of a list, one per line
+ */
+fn json_list(values: &[String]) -> String {
+    if values.is_empty() {
+        return String::new();
+    }
+    format!("\n    {}\n  ", values.join(",\n    "))
+}
+
+#[cfg(test)]
+fn strings(arguments: &[&str]) -> Vec<String> {
+    arguments.iter().map(|argument| String::from(*argument)).collect()
+}
+
+#[test]
+fn test_parse_window() {
+    let arguments = strings(&["--width", "1024", "play", "levels/zones.lvl", "--opengl", "2.1",
+                              "--fullscreen"]);
+    let (window, rest) = parse_window(&arguments).unwrap();
+    assert_eq!(window,
+               Window {
+                   width: 1024,
+                   opengl: OpenGL::V2_1,
+                   fullscreen: true,
+                   ..Window::new()
+               });
+    assert_eq!(rest, strings(&["play", "levels/zones.lvl"]));
+
+    // Command flags are left to the command
+    let (window, rest) = parse_window(&strings(&["simulate", "a.lvl", "--shots", "a.rpl"]))
+        .unwrap();
+    assert_eq!(window, Window::new());
+    assert_eq!(rest.len(), 4);
+
+    assert!(parse_window(&strings(&["--height"])).is_err());
+    assert!(parse_window(&strings(&["--width", "0"])).is_err());
+    assert!(parse_window(&strings(&["--opengl", "5.0"])).is_err());
+}
+
+#[test]
+fn test_split_flags() {
+    let arguments = strings(&["a.lvl", "--shots", "a.rpl"]);
+    assert_eq!(split_flags(&arguments, "").unwrap(),
+               (vec!["a.lvl"], vec![("--shots", "a.rpl")]));
+    assert!(split_flags(&strings(&["--out"]), "").is_err());
+    assert!(run_simulate(&strings(&["a.lvl", "--speed", "2"]),
+                         catalogue::Catalogue::builtin(),
+                         &settings::Settings::new())
+        .unwrap_err()
+        .starts_with("unknown flag '--speed'"));
+}
+
+#[test]
+fn test_json() {
+    let game = game::test_game("ball white 0.5 0.25
+                                blackhole 0.5 0.75 0.01 0.001 0.1");
+    assert_eq!(to_json(&game, "levels/\"test\".lvl"),
+               "{
+  \"level\": \"levels/\\\"test\\\".lvl\",
+  \"time\": 0,
+  \"score\": 0,
+  \"shots\": 0,
+  \"over\": false,
+  \"cleared\": true,
+  \"balls\": [
+    {\"type\": \"white\", \"x\": 0.5, \"y\": 0.25, \"vx\": 0, \"vy\": 0}
+  ],
+  \"blackholes\": [
+    {\"x\": 0.5, \"y\": 0.75, \"mass\": 0.01, \"radius\": 0.001, \"reach\": 0.1}
+  ]
+}");
+    assert_eq!(json_number(f64::INFINITY), "null");
+}
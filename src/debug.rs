@@ -0,0 +1,111 @@
+use na::{Norm, Point2, Vector2};
+use std::time::Duration;
+use renderer::{Color, Renderer};
+use physics;
+use poolball;
+
+/**
+ * Measurements of the latest game update: the collisions solved, the passes
+ * through wormholes and the time the update took in seconds
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct StepStats {
+    pub collisions: u32,
+    pub teleports: u32,
+    pub step_time: f64,
+}
+
+/**
+ * The next collision expected with the current velocities: where the first
+ * poolball and the thing it hits will be, and how long until then
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prediction {
+    pub from: Point2<f64>,
+    pub to: Point2<f64>,
+    pub kind: &'static str,
+    pub time: f64,
+}
+
+// Time in seconds the drawn velocity vectors reach ahead
+const VELOCITY_SCALE: f64 = 0.5;
+
+const GREEN: Color = [0.2, 1.0, 0.2, 1.0];
+const GRAY: Color = [0.6, 0.6, 0.6, 0.6];
+const RED: Color = [1.0, 0.2, 0.2, 1.0];
+const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+
+impl StepStats {
+    /**
+     * Creates the measurements of an update that has not happened yet
+     */
+    pub fn new() -> StepStats {
+        StepStats {
+            collisions: 0,
+            teleports: 0,
+            step_time: 0.0,
+        }
+    }
+}
+
+/**
+ * Returns the given duration in seconds
+ */
+pub fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1000000000.0
+}
+
+/**
+ * Draws the velocity of every poolball moving faster than the given
+ * stationary speed and where it will hit a wall, labelled with the distance
+ * and time until then, the next predicted collision and the measurements of
+ * the latest update
+ */
+pub fn render(balls: &[poolball::Poolball],
+              stationary_speed: f64,
+              prediction: Option<&Prediction>,
+              stats: &StepStats,
+              renderer: &mut Renderer) {
+    for ball in balls {
+        if ball.is_slower_than(stationary_speed) {
+            continue;
+        }
+        let tip = ball.position + ball.velocity * VELOCITY_SCALE;
+        renderer.arrow(GREEN, ball.position, tip, 0.0015, 0.01);
+
+        // Path to the wall the poolball is heading for, labelled next to
+        // where it hits in view coordinates so the label keeps its size
+        let time = physics::time_to_wall_collision(ball);
+        if time.is_finite() {
+            let hit = ball.position + ball.velocity * time;
+            renderer.line(GRAY, ball.position, hit, 0.001);
+            renderer.ring(GRAY, hit, 0.004, 0.001);
+            let label = format!("{:.3} in {:.2}s", (hit - ball.position).norm(), time);
+            let position = renderer.to_view(hit) + Vector2::new(0.006, -0.006);
+            renderer.text(GRAY, 10, position, &label);
+        }
+    }
+
+    let mut lines = vec![format!("Collisions: {}", stats.collisions),
+                         format!("Teleports: {}", stats.teleports),
+                         format!("Step: {:.3} ms", stats.step_time * 1000.0)];
+
+    match prediction {
+        Some(prediction) => {
+            renderer.line(RED, prediction.from, prediction.to, 0.002);
+            renderer.ring(RED, prediction.from, 0.006, 0.002);
+            lines.push(format!("Next: {} in {:.3}s", prediction.kind, prediction.time));
+        }
+        None => lines.push(String::from("Next: none")),
+    }
+
+    for (i, line) in lines.iter().enumerate() {
+        renderer.text(WHITE, 12, Point2::new(0.75, 0.8 + 0.0175 * i as f64), line);
+    }
+}
+
+#[test]
+fn test_seconds() {
+    assert_eq!(seconds(Duration::from_millis(1500)), 1.5);
+    assert_eq!(seconds(Duration::new(2, 500000000)), 2.5);
+}
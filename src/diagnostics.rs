@@ -0,0 +1,240 @@
+use na::{Point2, Vector2};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use poolball;
+use blackhole;
+use nbody;
+use physics;
+use renderer;
+
+/**
+ * Physical quantities of the whole table after a game update, used for
+ * catching regressions in the physics. Losses are the kinetic energy lost to
+ * friction and inelastic collisions during the update
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostics {
+    pub time: f64,
+    pub kinetic_energy: f64,
+    pub potential_energy: f64,
+    pub momentum: Vector2<f64>,
+    pub friction_loss: f64,
+    pub collision_loss: f64,
+}
+
+/**
+ * Writes diagnostics to a CSV file, one row per game update
+ */
+pub struct CsvLog {
+    writer: BufWriter<File>,
+}
+
+const HEADER: &'static str = "time,kinetic_energy,potential_energy,total_energy,momentum_x,\
+                              momentum_y,friction_loss,collision_loss";
+
+impl Diagnostics {
+    /**
+     * Measures the given table with the given gravity constant at the given
+     * time, including the given losses
+     */
+    pub fn measure(time: f64,
+                   balls: &Vec<poolball::Poolball>,
+                   blackholes: &Vec<blackhole::Blackhole>,
+                   gravity: f64,
+                   n_body: Option<&nbody::NBody>,
+                   friction_loss: f64,
+                   collision_loss: f64)
+                   -> Diagnostics {
+        Diagnostics {
+            time: time,
+            kinetic_energy: kinetic_energy(balls),
+            potential_energy: potential_energy(balls, blackholes, gravity, n_body),
+            momentum: momentum(balls),
+            friction_loss: friction_loss,
+            collision_loss: collision_loss,
+        }
+    }
+
+    /**
+     * Returns the sum of kinetic and potential energy
+     */
+    pub fn total_energy(&self) -> f64 {
+        self.kinetic_energy + self.potential_energy
+    }
+
+    /**
+     * Returns the diagnostics as a row of comma separated values, in the
+     * order of the CSV header
+     */
+    pub fn to_csv(&self) -> String {
+        format!("{},{},{},{},{},{},{},{}",
+                self.time,
+                self.kinetic_energy,
+                self.potential_energy,
+                self.total_energy(),
+                self.momentum.x,
+                self.momentum.y,
+                self.friction_loss,
+                self.collision_loss)
+    }
+
+    /**
+     * Renders the diagnostics as text in the top left corner
+     */
+    pub fn render(&self, renderer: &mut renderer::Renderer) {
+
+        const GRAY: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+
+        let lines = vec![format!("Kinetic energy: {:.6}", self.kinetic_energy),
+                         format!("Potential energy: {:.6}", self.potential_energy),
+                         format!("Total energy: {:.6}", self.total_energy()),
+                         format!("Momentum: ({:.6}, {:.6})", self.momentum.x, self.momentum.y),
+                         format!("Friction loss: {:.6}", self.friction_loss),
+                         format!("Collision loss: {:.6}", self.collision_loss)];
+
+        for (i, line) in lines.iter().enumerate() {
+            let position = Point2::new(0.0125, 0.025 + 0.0175 * i as f64);
+            renderer.text(GRAY, 12, position, line.as_str());
+        }
+    }
+}
+
+impl CsvLog {
+    /**
+     * Creates the CSV file at the given path, writing the header
+     */
+    pub fn create(path: &Path) -> io::Result<CsvLog> {
+        let mut writer = BufWriter::new(try!(File::create(path)));
+        try!(writeln!(writer, "{}", HEADER));
+        Ok(CsvLog { writer: writer })
+    }
+
+    /**
+     * Appends a row with the given diagnostics
+     */
+    pub fn write(&mut self, diagnostics: &Diagnostics) -> io::Result<()> {
+        writeln!(self.writer, "{}", diagnostics.to_csv())
+    }
+
+    /**
+     * Writes the rows still buffered to the file
+     */
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/**
+ * Returns the total kinetic energy of the given poolballs
+ */
+pub fn kinetic_energy(balls: &Vec<poolball::Poolball>) -> f64 {
+    balls.iter().fold(0.0, |sum, ball| {
+        let speed_squared = ball.velocity.x * ball.velocity.x + ball.velocity.y * ball.velocity.y;
+        sum + 0.5 * ball.mass * speed_squared
+    })
+}
+
+/**
+ * Returns the total linear momentum of the given poolballs
+ */
+pub fn momentum(balls: &Vec<poolball::Poolball>) -> Vector2<f64> {
+    balls.iter().fold(Vector2::new(0.0, 0.0), |sum, ball| sum + ball.velocity * ball.mass)
+}
+
+/**
+ * Returns the potential energy of the given poolballs in the gravity of the
+ * given blackholes with the given gravity constant, and of each other if the
+ * poolballs attract each other. The potentials are those drawn by the field
+ * overlay, so a blackhole adds nothing at the edge of its reach and beyond
+ */
+pub fn potential_energy(balls: &Vec<poolball::Poolball>,
+                        blackholes: &Vec<blackhole::Blackhole>,
+                        gravity: f64,
+                        n_body: Option<&nbody::NBody>)
+                        -> f64 {
+    let mut energy = 0.0;
+
+    for ball in balls {
+        if ball.immune {
+            continue;
+        }
+        energy += ball.mass * physics::gravity_potential(blackholes, &ball.position, gravity);
+    }
+
+    // Softened potential between every pair of poolballs
+    if let Some(n_body) = n_body {
+        for (i, first) in balls.iter().enumerate() {
+            for second in balls.iter().skip(i + 1) {
+                energy += first.mass *
+                          n_body.potential(&first.position, &second.position, second.mass);
+            }
+        }
+    }
+
+    energy
+}
+
+#[test]
+fn test_kinetic_energy_and_momentum() {
+    let mut ball1 = poolball::Poolball::new(Point2::new(0.2, 0.2), poolball::BallType::Red);
+    ball1.mass = 2.0;
+    ball1.velocity = Vector2::new(1.0, 0.0);
+    let mut ball2 = poolball::Poolball::new(Point2::new(0.8, 0.8), poolball::BallType::Red);
+    ball2.mass = 1.0;
+    ball2.velocity = Vector2::new(0.0, -2.0);
+
+    let balls = vec![ball1, ball2];
+    assert_eq!(kinetic_energy(&balls), 3.0);
+    assert_eq!(momentum(&balls), Vector2::new(2.0, -2.0));
+}
+
+#[test]
+fn test_potential_energy() {
+    let blackholes = vec![blackhole::Blackhole::new(Point2::new(0.0, 0.0), 1.0, 0.0, 2.0)];
+    let mut ball = poolball::Poolball::new(Point2::new(1.0, 0.0), poolball::BallType::Red);
+    ball.mass = 1.0;
+
+    // -M m (1 / r - 1 / reach)
+    let energy = potential_energy(&vec![ball.clone()], &blackholes, 1.0, None);
+    assert!((energy + 0.5).abs() < 0.0001);
+    let energy = potential_energy(&vec![ball.clone()], &blackholes, 2.0, None);
+    assert!((energy + 1.0).abs() < 0.0001);
+
+    // Zero at and beyond the reach
+    ball.position = Point2::new(2.0, 0.0);
+    assert_eq!(potential_energy(&vec![ball.clone()], &blackholes, 1.0, None), 0.0);
+    ball.position = Point2::new(3.0, 0.0);
+    assert_eq!(potential_energy(&vec![ball.clone()], &blackholes, 1.0, None), 0.0);
+}
+
+#[test]
+fn test_elastic_collision_conserves_energy_and_momentum() {
+    let mut ball1 = poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Red);
+    ball1.velocity = Vector2::new(1.0, 0.5);
+    let mut ball2 = poolball::Poolball::new(Point2::new(0.02, 0.005), poolball::BallType::Iron);
+    ball2.restitution = 1.0;
+    ball2.velocity = Vector2::new(-0.5, 0.0);
+
+    let before = vec![ball1.clone(), ball2.clone()];
+    physics::ball_ball_collision(&mut ball1, &mut ball2);
+    let after = vec![ball1, ball2];
+
+    assert!((kinetic_energy(&before) - kinetic_energy(&after)).abs() < 0.000001);
+    let difference = momentum(&before) - momentum(&after);
+    assert!(difference.x.abs() < 0.000001 && difference.y.abs() < 0.000001);
+}
+
+#[test]
+fn test_to_csv() {
+    let diagnostics = Diagnostics {
+        time: 1.5,
+        kinetic_energy: 2.0,
+        potential_energy: -0.5,
+        momentum: Vector2::new(0.25, 0.0),
+        friction_loss: 0.125,
+        collision_loss: 0.0,
+    };
+    assert_eq!(diagnostics.to_csv(), "1.5,2,-0.5,1.5,0.25,0,0.125,0");
+    assert_eq!(HEADER.split(',').count(), diagnostics.to_csv().split(',').count());
+}
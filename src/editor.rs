@@ -0,0 +1,505 @@
+use na::{FloatPoint, Point2};
+use std::path::Path;
+use renderer::{Color, Renderer};
+use blackhole;
+use catalogue;
+use goalzone;
+use level;
+use poolball::BallType;
+
+/**
+ * What a click on an empty part of the table places
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tool {
+    Ball(BallType),
+    Blackhole,
+    Goalzone,
+}
+
+/**
+ * An entity of the level being edited, by its index in the level
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Selection {
+    Ball(usize),
+    Blackhole(usize),
+    Goalzone(usize),
+}
+
+/**
+ * Input for editing a level, from the keyboard or the mouse. Positions are
+ * given in table coordinates, and resizing scales the selected blackhole by
+ * the given factor
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Press(Point2<f64>),
+    Drag(Point2<f64>),
+    Release,
+    NextTool,
+    ToggleSnap,
+    Delete,
+    Reach(f64),
+    Mass(f64),
+    Horizon(f64),
+    TestPlay,
+    Save,
+    Quit,
+}
+
+/**
+ * What the editor asks of the game after some input
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    TestPlay,
+    Quit,
+}
+
+/**
+ * Edits a level with the mouse: clicking an entity selects it and dragging
+ * moves it, while clicking the empty table places a new entity of the current
+ * tool. Positions snap to a grid unless snapping is turned off
+ */
+#[derive(Clone)]
+pub struct Editor {
+    pub path: String,
+    pub level: level::Level,
+    pub tool: Tool,
+    pub snap: bool,
+    pub selected: Option<Selection>,
+    pub message: Option<String>,
+    catalogue: catalogue::Catalogue,
+    dragging: bool,
+}
+
+// Number of grid cells along each side of the table positions snap to
+const GRID_CELLS: f64 = 40.0;
+
+// Distance from the center within which blackholes and goalzones are picked
+const BLACKHOLE_PICK: f64 = 0.02;
+const GOALZONE_PICK: f64 = 0.05;
+
+// Blackhole placed by the blackhole tool, and the limits it can be resized to
+const DEFAULT_MASS: f64 = 0.01;
+const DEFAULT_RADIUS: f64 = 0.005;
+const DEFAULT_REACH: f64 = 0.1;
+const MIN_REACH: f64 = 0.01;
+const MAX_REACH: f64 = 0.5;
+const MAX_RADIUS: f64 = 0.05;
+
+const BACKGROUND: Color = [0.0, 0.0, 0.0, 1.0];
+const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+const GRAY: Color = [0.6, 0.6, 0.6, 1.0];
+const DIM: Color = [0.3, 0.3, 0.3, 1.0];
+const YELLOW: Color = [1.0, 0.85, 0.2, 1.0];
+
+impl Tool {
+    /**
+     * Returns the tool after this one, going through every ball type before
+     * the blackhole and the goalzone
+     */
+    pub fn next(&self) -> Tool {
+        let tools = tools();
+        let index = tools.iter().position(|tool| tool == self).unwrap_or(0);
+        tools[(index + 1) % tools.len()].clone()
+    }
+
+    /**
+     * Returns the name of the tool as shown in the editor
+     */
+    pub fn name(&self) -> String {
+        match *self {
+            Tool::Ball(ref ball_type) => format!("{} ball", ball_type.name()),
+            Tool::Blackhole => String::from("blackhole"),
+            Tool::Goalzone => String::from("goalzone"),
+        }
+    }
+}
+
+impl Editor {
+    /**
+     * Opens the level file at the given path for editing, or starts an empty
+     * level to be saved there if there is no such file
+     */
+    pub fn open(path: &str, catalogue: &catalogue::Catalogue) -> Result<Editor, String> {
+        let (level, message) = if Path::new(path).exists() {
+            (try!(level::Level::load(Path::new(path), catalogue)), None)
+        } else {
+            (try!(level::Level::parse("", catalogue)), Some(format!("New level {}", path)))
+        };
+        Ok(Editor {
+            path: String::from(path),
+            level: level,
+            tool: Tool::Ball(BallType::White),
+            snap: true,
+            selected: None,
+            message: message,
+            catalogue: catalogue.clone(),
+            dragging: false,
+        })
+    }
+
+    /**
+     * Handles the given input, returning what the game should do about it
+     */
+    pub fn input(&mut self, input: Input) -> Option<Action> {
+        match input {
+            Input::Press(point) => {
+                self.selected = self.entity_at(&point);
+                if self.selected.is_none() {
+                    self.place(point);
+                }
+                self.dragging = true;
+            }
+            Input::Drag(point) => {
+                if self.dragging {
+                    self.move_selected(point);
+                }
+            }
+            Input::Release => self.dragging = false,
+            Input::NextTool => self.tool = self.tool.next(),
+            Input::ToggleSnap => self.snap = !self.snap,
+            Input::Delete => self.delete(),
+            Input::Reach(factor) => self.resize(1.0, 1.0, factor),
+            Input::Mass(factor) => self.resize(factor, 1.0, 1.0),
+            Input::Horizon(factor) => self.resize(1.0, factor, 1.0),
+            Input::TestPlay => {
+                if self.level.balls.iter().any(|ball| ball.ball_type == BallType::White) {
+                    return Some(Action::TestPlay);
+                }
+                self.message = Some(String::from("Place a white ball before test playing"));
+            }
+            Input::Save => {
+                self.message = Some(match self.level.save(Path::new(&self.path)) {
+                    Ok(()) => format!("Saved {}", self.path),
+                    Err(err) => err,
+                });
+            }
+            Input::Quit => return Some(Action::Quit),
+        }
+        None
+    }
+
+    /**
+     * Renders the level being edited with the grid, the selected entity and
+     * the current tool
+     */
+    pub fn render(&self, renderer: &mut Renderer) {
+        renderer.clear(BACKGROUND);
+
+        // Draw every fourth line of the grid while snapping, and the walls
+        if self.snap {
+            for i in 1..10 {
+                let at = i as f64 * 4.0 / GRID_CELLS;
+                renderer.line(DIM, Point2::new(at, 0.0), Point2::new(at, 1.0), 0.001);
+                renderer.line(DIM, Point2::new(0.0, at), Point2::new(1.0, at), 0.001);
+            }
+        }
+        let corners = [Point2::new(0.0, 0.0),
+                       Point2::new(1.0, 0.0),
+                       Point2::new(1.0, 1.0),
+                       Point2::new(0.0, 1.0),
+                       Point2::new(0.0, 0.0)];
+        renderer.path(GRAY, &corners, 0.004);
+
+        for zone in &self.level.goalzones {
+            zone.render(renderer, &self.catalogue);
+        }
+        for hole in &self.level.blackholes {
+            hole.render(renderer);
+        }
+        for wormhole in &self.level.wormholes {
+            wormhole.render(renderer);
+        }
+        for ball in &self.level.balls {
+            ball.render(renderer);
+        }
+
+        let mut lines = vec![format!("Editing {}", self.path),
+                             format!("Tool: {}    Snap: {}",
+                                     self.tool.name(),
+                                     if self.snap { "on" } else { "off" })];
+        if let Some(selected) = self.selected {
+            let (position, radius) = self.extent(selected);
+            renderer.ring(YELLOW, position, radius + 0.01, 0.003);
+            lines.push(self.describe(selected));
+        }
+        for (i, line) in lines.iter().enumerate() {
+            renderer.text(WHITE, 14, Point2::new(0.02, 0.03 + 0.025 * i as f64), line);
+        }
+        if let Some(ref message) = self.message {
+            let y = 0.03 + 0.025 * lines.len() as f64;
+            renderer.text(YELLOW, 14, Point2::new(0.02, y), message);
+        }
+
+        renderer.text(GRAY,
+                      12,
+                      Point2::new(0.02, 0.97),
+                      "Tab tool, G snap, Delete remove, [ ] reach, - = mass, , . horizon, \
+                       Enter test, S save, Escape quit");
+    }
+
+    /**
+     * Returns the entity at the given position, preferring poolballs over
+     * blackholes and blackholes over goalzones
+     */
+    fn entity_at(&self, point: &Point2<f64>) -> Option<Selection> {
+        if let Some(i) = self.level
+            .balls
+            .iter()
+            .position(|ball| ball.position.distance(point) <= ball.radius) {
+            return Some(Selection::Ball(i));
+        }
+        if let Some(i) = self.level
+            .blackholes
+            .iter()
+            .position(|hole| hole.position.distance(point) <= BLACKHOLE_PICK) {
+            return Some(Selection::Blackhole(i));
+        }
+        self.level
+            .goalzones
+            .iter()
+            .position(|zone| zone.position().distance(point) <= GOALZONE_PICK)
+            .map(Selection::Goalzone)
+    }
+
+    /**
+     * Places a new entity of the current tool at the given position and
+     * selects it. As there may only be one white ball, placing another one
+     * moves the existing one instead
+     */
+    fn place(&mut self, point: Point2<f64>) {
+        let position = self.snapped(point);
+        self.selected = Some(match self.tool {
+            Tool::Ball(ref ball_type) => {
+                let white = self.level
+                    .balls
+                    .iter()
+                    .position(|ball| ball.ball_type == BallType::White);
+                match white {
+                    Some(i) if *ball_type == BallType::White => {
+                        self.level.balls[i].position = position;
+                        Selection::Ball(i)
+                    }
+                    _ => {
+                        let ball = self.catalogue.create(position, ball_type.clone());
+                        self.level.balls.push(ball);
+                        Selection::Ball(self.level.balls.len() - 1)
+                    }
+                }
+            }
+            Tool::Blackhole => {
+                self.level.blackholes.push(blackhole::Blackhole::new(position,
+                                                                     DEFAULT_MASS,
+                                                                     DEFAULT_RADIUS,
+                                                                     DEFAULT_REACH));
+                Selection::Blackhole(self.level.blackholes.len() - 1)
+            }
+            Tool::Goalzone => {
+                self.level.goalzones.push(goalzone::Goalzone::new(position));
+                Selection::Goalzone(self.level.goalzones.len() - 1)
+            }
+        });
+    }
+
+    /**
+     * Moves the selected entity to the given position
+     */
+    fn move_selected(&mut self, point: Point2<f64>) {
+        let position = self.snapped(point);
+        match self.selected {
+            Some(Selection::Ball(i)) => self.level.balls[i].position = position,
+            Some(Selection::Blackhole(i)) => self.level.blackholes[i].position = position,
+            Some(Selection::Goalzone(i)) => {
+                let zone = &mut self.level.goalzones[i];
+                let offset = position - zone.position();
+                zone.translate(offset);
+            }
+            None => {}
+        }
+    }
+
+    /**
+     * Removes the selected entity from the level
+     */
+    fn delete(&mut self) {
+        match self.selected.take() {
+            Some(Selection::Ball(i)) => {
+                self.level.balls.remove(i);
+            }
+            Some(Selection::Blackhole(i)) => {
+                self.level.blackholes.remove(i);
+            }
+            Some(Selection::Goalzone(i)) => {
+                self.level.goalzones.remove(i);
+            }
+            None => {}
+        }
+        self.dragging = false;
+    }
+
+    /**
+     * Scales the mass, radius and reach of the selected blackhole by the given
+     * factors
+     */
+    fn resize(&mut self, mass: f64, radius: f64, reach: f64) {
+        match self.selected {
+            Some(Selection::Blackhole(i)) => {
+                let hole = &mut self.level.blackholes[i];
+                let (new_mass, new_radius, new_reach) =
+                    (hole.mass * mass,
+                     (hole.radius * radius).min(MAX_RADIUS),
+                     (hole.reach * reach).max(MIN_REACH).min(MAX_REACH));
+                hole.set_size(new_mass, new_radius, new_reach);
+            }
+            _ => self.message = Some(String::from("Select a blackhole to resize")),
+        }
+    }
+
+    /**
+     * Returns the given position kept on the table, on the nearest point of
+     * the grid while snapping
+     */
+    fn snapped(&self, point: Point2<f64>) -> Point2<f64> {
+        let snap = |value: f64| {
+            let value = if self.snap {
+                (value * GRID_CELLS).round() / GRID_CELLS
+            } else {
+                value
+            };
+            value.max(0.0).min(1.0)
+        };
+        Point2::new(snap(point.x), snap(point.y))
+    }
+
+    /**
+     * Returns the position and size of the given entity
+     */
+    fn extent(&self, selected: Selection) -> (Point2<f64>, f64) {
+        match selected {
+            Selection::Ball(i) => (self.level.balls[i].position, self.level.balls[i].radius),
+            Selection::Blackhole(i) => (self.level.blackholes[i].position, BLACKHOLE_PICK),
+            Selection::Goalzone(i) => (self.level.goalzones[i].position(), GOALZONE_PICK),
+        }
+    }
+
+    /**
+     * Returns a line describing the given entity
+     */
+    fn describe(&self, selected: Selection) -> String {
+        match selected {
+            Selection::Ball(i) => {
+                let ball = &self.level.balls[i];
+                format!("{} ball at {:.3}, {:.3}",
+                        ball.ball_type.name(),
+                        ball.position.x,
+                        ball.position.y)
+            }
+            Selection::Blackhole(i) => {
+                let hole = &self.level.blackholes[i];
+                format!("Blackhole at {:.3}, {:.3}: mass {:.4}, horizon {:.4}, reach {:.3}",
+                        hole.position.x,
+                        hole.position.y,
+                        hole.mass,
+                        hole.radius,
+                        hole.reach)
+            }
+            Selection::Goalzone(i) => {
+                let position = self.level.goalzones[i].position();
+                format!("Goalzone at {:.3}, {:.3}", position.x, position.y)
+            }
+        }
+    }
+}
+
+/**
+ * Returns every tool in the order they are cycled through
+ */
+fn tools() -> Vec<Tool> {
+    vec![Tool::Ball(BallType::White),
+         Tool::Ball(BallType::Red),
+         Tool::Ball(BallType::Blue),
+         Tool::Ball(BallType::Iron),
+         Tool::Ball(BallType::Light),
+         Tool::Ball(BallType::Large),
+         Tool::Ball(BallType::Bomb),
+         Tool::Ball(BallType::Sticky),
+         Tool::Ball(BallType::Ghost),
+         Tool::Blackhole,
+         Tool::Goalzone]
+}
+
+#[cfg(test)]
+fn test_editor() -> Editor {
+    Editor::open("levels/does-not-exist.lvl", &catalogue::Catalogue::builtin()).unwrap()
+}
+
+#[test]
+fn test_place_and_drag() {
+    let mut editor = test_editor();
+    assert!(editor.level.balls.is_empty());
+
+    // Clicking the empty table places the white ball on the grid
+    editor.input(Input::Press(Point2::new(0.51, 0.49)));
+    assert_eq!(editor.selected, Some(Selection::Ball(0)));
+    assert_eq!(editor.level.balls[0].position, Point2::new(0.5, 0.5));
+
+    // Dragging moves it, and another white ball moves it rather than adding one
+    editor.input(Input::Drag(Point2::new(0.3, 0.26)));
+    editor.input(Input::Release);
+    assert_eq!(editor.level.balls[0].position, Point2::new(0.3, 0.25));
+    editor.input(Input::Press(Point2::new(0.8, 0.8)));
+    assert_eq!(editor.level.balls.len(), 1);
+
+    // Without snapping positions are kept as they are, but on the table
+    editor.input(Input::ToggleSnap);
+    editor.input(Input::Drag(Point2::new(0.81, 1.2)));
+    assert_eq!(editor.level.balls[0].position, Point2::new(0.81, 1.0));
+
+    // Goalzones move along with their paths
+    editor.tool = Tool::Goalzone;
+    editor.input(Input::Press(Point2::new(0.1, 0.1)));
+    editor.input(Input::Drag(Point2::new(0.2, 0.1)));
+    assert_eq!(editor.selected, Some(Selection::Goalzone(0)));
+    assert_eq!(editor.level.goalzones[0].position(), Point2::new(0.2, 0.1));
+}
+
+#[test]
+fn test_tools_and_delete() {
+    let mut editor = test_editor();
+    assert_eq!(editor.tool.next(), Tool::Ball(BallType::Red));
+    assert_eq!(Tool::Goalzone.next(), Tool::Ball(BallType::White));
+
+    // Test playing needs a white ball
+    editor.tool = Tool::Ball(BallType::Red);
+    editor.input(Input::Press(Point2::new(0.5, 0.5)));
+    assert_eq!(editor.input(Input::TestPlay), None);
+    editor.input(Input::Delete);
+    assert!(editor.level.balls.is_empty());
+    assert_eq!(editor.selected, None);
+
+    editor.tool = Tool::Ball(BallType::White);
+    editor.input(Input::Press(Point2::new(0.5, 0.5)));
+    assert_eq!(editor.input(Input::TestPlay), Some(Action::TestPlay));
+    assert_eq!(editor.input(Input::Quit), Some(Action::Quit));
+}
+
+#[test]
+fn test_resize() {
+    let mut editor = test_editor();
+    editor.tool = Tool::Blackhole;
+    editor.input(Input::Press(Point2::new(0.5, 0.5)));
+    editor.input(Input::Reach(2.0));
+    editor.input(Input::Mass(0.5));
+    editor.input(Input::Horizon(2.0));
+
+    let hole = &editor.level.blackholes[0];
+    assert_eq!((hole.mass, hole.radius, hole.reach),
+               (DEFAULT_MASS * 0.5, DEFAULT_RADIUS * 2.0, DEFAULT_REACH * 2.0));
+
+    // The reach stays within its limits
+    editor.input(Input::Reach(100.0));
+    assert_eq!(editor.level.blackholes[0].reach, MAX_REACH);
+}
@@ -0,0 +1,292 @@
+use na::{Point2, Vector2, FloatPoint};
+use std::collections::{HashMap, VecDeque};
+use std::f64;
+use renderer::{Color, Renderer};
+use catalogue;
+use event;
+use math;
+use poolball;
+
+/**
+ * Visual effects following the simulation: fading trails behind moving
+ * poolballs and short bursts of particles where something happened. Effects
+ * only look at the table and never change it
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Effects {
+    pub trails_enabled: bool,
+    pub particles_enabled: bool,
+    trails: HashMap<usize, Trail>,
+    particles: Vec<Particle>,
+    seed: u32,
+}
+
+/**
+ * The latest positions of a poolball, each with the time since it was there
+ */
+#[derive(Clone, Debug, PartialEq)]
+struct Trail {
+    color: Color,
+    width: f64,
+    points: VecDeque<(Point2<f64>, f64)>,
+}
+
+/**
+ * A spark flying away from where something happened, fading with age
+ */
+#[derive(Clone, Debug, PartialEq)]
+struct Particle {
+    position: Point2<f64>,
+    velocity: Vector2<f64>,
+    color: Color,
+    age: f64,
+    lifetime: f64,
+}
+
+// Time in seconds a position stays in a trail, and the shortest distance
+// between two positions of a trail
+const TRAIL_TIME: f64 = 0.4;
+const TRAIL_STEP: f64 = 0.002;
+
+// Share of their speed particles lose every second, and their size
+const PARTICLE_DRAG: f64 = 3.0;
+const PARTICLE_RADIUS: f64 = 0.003;
+
+const SPARK: Color = [1.0, 1.0, 0.8, 1.0];
+const DUST: Color = [0.6, 0.6, 0.6, 1.0];
+const PORTAL: Color = [0.3, 0.8, 1.0, 1.0];
+
+impl Effects {
+    /**
+     * Creates the effects with both trails and particles shown
+     */
+    pub fn new() -> Effects {
+        Effects {
+            trails_enabled: true,
+            particles_enabled: true,
+            trails: HashMap::new(),
+            particles: Vec::new(),
+            seed: 1,
+        }
+    }
+
+    /**
+     * Shows or hides the trails
+     */
+    pub fn toggle_trails(&mut self) {
+        self.trails_enabled = !self.trails_enabled;
+        self.trails.clear();
+    }
+
+    /**
+     * Shows or hides the particles
+     */
+    pub fn toggle_particles(&mut self) {
+        self.particles_enabled = !self.particles_enabled;
+        self.particles.clear();
+    }
+
+    /**
+     * Ages the effects by the given time, extends the trails of the poolballs
+     * moving faster than the given stationary speed and bursts particles for
+     * the events of the latest update
+     */
+    pub fn update(&mut self,
+                  delta_time: f64,
+                  balls: &[poolball::Poolball],
+                  stationary_speed: f64,
+                  events: &[event::Event],
+                  catalogue: &catalogue::Catalogue) {
+        if self.trails_enabled {
+            self.update_trails(delta_time, balls, stationary_speed);
+        }
+
+        if self.particles_enabled {
+            for particle in &mut self.particles {
+                particle.position = particle.position + particle.velocity * delta_time;
+                particle.velocity = particle.velocity * (1.0 - PARTICLE_DRAG * delta_time).max(0.0);
+                particle.age += delta_time;
+            }
+            self.particles.retain(|particle| particle.age < particle.lifetime);
+
+            for event in events {
+                self.burst(event, catalogue);
+            }
+        }
+    }
+
+    /**
+     * Renders the trails and particles using the given renderer
+     */
+    pub fn render(&self, renderer: &mut Renderer) {
+        for trail in self.trails.values() {
+            for pair in trail.points.iter().collect::<Vec<_>>().windows(2) {
+                let (from, age) = *pair[0];
+                let (to, _) = *pair[1];
+                renderer.line(fade(trail.color, 0.5 * (1.0 - age / TRAIL_TIME)),
+                              from,
+                              to,
+                              trail.width);
+            }
+        }
+
+        for particle in &self.particles {
+            renderer.circle(fade(particle.color, 1.0 - particle.age / particle.lifetime),
+                            particle.position,
+                            PARTICLE_RADIUS);
+        }
+    }
+
+    /**
+     * Ages the trails, adding the positions of the moving poolballs and
+     * dropping the trails of poolballs gone from the table once faded
+     */
+    fn update_trails(&mut self,
+                     delta_time: f64,
+                     balls: &[poolball::Poolball],
+                     stationary_speed: f64) {
+        for trail in self.trails.values_mut() {
+            for point in &mut trail.points {
+                point.1 += delta_time;
+            }
+            while trail.points.front().map_or(false, |&(_, age)| age >= TRAIL_TIME) {
+                trail.points.pop_front();
+            }
+        }
+
+        for ball in balls {
+            if ball.is_slower_than(stationary_speed) {
+                continue;
+            }
+            let trail = self.trails.entry(ball.id).or_insert(Trail {
+                color: ball.color,
+                width: ball.radius,
+                points: VecDeque::new(),
+            });
+            let moved = match trail.points.back() {
+                Some(&(last, _)) => last.distance(&ball.position) >= TRAIL_STEP,
+                None => true,
+            };
+            if moved {
+                trail.points.push_back((ball.position, 0.0));
+            }
+        }
+
+        let faded: Vec<usize> = self.trails
+            .iter()
+            .filter(|&(_, trail)| trail.points.is_empty())
+            .map(|(&id, _)| id)
+            .collect();
+        for id in faded {
+            self.trails.remove(&id);
+        }
+    }
+
+    /**
+     * Adds the burst of particles of the given event
+     */
+    fn burst(&mut self, event: &event::Event, catalogue: &catalogue::Catalogue) {
+        match *event {
+            event::Event::Collided { position, speed } => {
+                self.spray(position, 8, 0.1 + 0.5 * speed, SPARK, 0.3);
+            }
+            event::Event::HitWall { position, speed, .. } => {
+                self.spray(position, 5, 0.05 + 0.3 * speed, DUST, 0.3);
+            }
+            event::Event::Potted { ref ball_type, position, .. } => {
+                let color = catalogue.properties(ball_type).color;
+                self.spray(position, 16, 0.3, color, 0.6);
+            }
+            event::Event::Swallowed { ref ball_type, position } => {
+                let color = catalogue.properties(ball_type).color;
+                self.spray(position, 24, 0.15, color, 1.0);
+            }
+            event::Event::Teleported { to, .. } => {
+                self.spray(to, 10, 0.2, PORTAL, 0.4);
+            }
+        }
+    }
+
+    /**
+     * Sends the given number of particles in every direction from the given
+     * position, with speeds up to the given one
+     */
+    fn spray(&mut self,
+             position: Point2<f64>,
+             count: u32,
+             speed: f64,
+             color: Color,
+             lifetime: f64) {
+        for i in 0..count {
+            let share = (i as f64 + math::random(&mut self.seed)) / count as f64;
+            let angle = share * 2.0 * f64::consts::PI;
+            let speed = speed * (0.5 + 0.5 * math::random(&mut self.seed));
+            self.particles.push(Particle {
+                position: position,
+                velocity: Vector2::new(angle.cos(), angle.sin()) * speed,
+                color: color,
+                age: 0.0,
+                lifetime: lifetime * (0.5 + 0.5 * math::random(&mut self.seed)),
+            });
+        }
+    }
+}
+
+/**
+ * Returns the given color with its opacity scaled by the given share
+ */
+fn fade(color: Color, share: f64) -> Color {
+    [color[0], color[1], color[2], color[3] * share.max(0.0).min(1.0) as f32]
+}
+
+#[test]
+fn test_trails() {
+    let catalogue = catalogue::Catalogue::builtin();
+    let mut ball = poolball::Poolball::new(Point2::new(0.5, 0.5), poolball::BallType::Red);
+    ball.set_velocity(Vector2::new(0.5, 0.0));
+    let mut effects = Effects::new();
+
+    // Moving balls leave a trail, which fades once they stop
+    for _ in 0..10 {
+        ball.update(0.01);
+        effects.update(0.01, &[ball.clone()], 0.005, &[], &catalogue);
+    }
+    assert_eq!(effects.trails[&ball.id].points.len(), 10);
+
+    ball.set_velocity(Vector2::new(0.0, 0.0));
+    for _ in 0..100 {
+        effects.update(0.01, &[ball.clone()], 0.005, &[], &catalogue);
+    }
+    assert!(effects.trails.is_empty());
+}
+
+#[test]
+fn test_particles() {
+    let catalogue = catalogue::Catalogue::builtin();
+    let mut effects = Effects::new();
+    let events = vec![event::Event::Collided {
+                          position: Point2::new(0.5, 0.5),
+                          speed: 1.0,
+                      },
+                      event::Event::Potted {
+                          ball_type: poolball::BallType::Red,
+                          position: Point2::new(0.1, 0.1),
+                          points: 1,
+                      }];
+
+    effects.update(0.01, &[], 0.005, &events, &catalogue);
+    assert_eq!(effects.particles.len(), 8 + 16);
+
+    // Particles spread out and die out
+    effects.update(0.1, &[], 0.005, &[], &catalogue);
+    assert!(effects.particles[0].position.distance(&Point2::new(0.5, 0.5)) > 0.0);
+    for _ in 0..100 {
+        effects.update(0.01, &[], 0.005, &[], &catalogue);
+    }
+    assert_eq!(effects.particles.len(), 0);
+
+    // Hidden particles are not created
+    effects.toggle_particles();
+    effects.update(0.01, &[], 0.005, &events, &catalogue);
+    assert_eq!(effects.particles.len(), 0);
+}
@@ -0,0 +1,33 @@
+use na::Point2;
+use poolball;
+
+/**
+ * Notable things happening during a single game update, collected so that
+ * other parts of the game can react to them after the update is done
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Collided {
+        position: Point2<f64>,
+        speed: f64,
+    },
+    HitWall {
+        ball_type: poolball::BallType,
+        position: Point2<f64>,
+        speed: f64,
+    },
+    Potted {
+        ball_type: poolball::BallType,
+        position: Point2<f64>,
+        points: i32,
+    },
+    Teleported {
+        ball_type: poolball::BallType,
+        from: Point2<f64>,
+        to: Point2<f64>,
+    },
+    Swallowed {
+        ball_type: poolball::BallType,
+        position: Point2<f64>,
+    },
+}
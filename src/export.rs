@@ -0,0 +1,379 @@
+use piston::input::UpdateArgs;
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use canvas;
+use catalogue;
+use game;
+use gif;
+use level;
+use parse;
+use png;
+use replay;
+use settings;
+use svg;
+use trace;
+
+/**
+ * Size in pixels, frame rate and length in seconds of an export
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub width: usize,
+    pub height: usize,
+    pub fps: f64,
+    pub duration: f64,
+}
+
+// Game updates per second, the same as in the game window so that replays
+// play out the same way
+pub const UPDATES_PER_SECOND: u64 = 120;
+
+const USAGE: &'static str = "usage: export <level or replay> <output> [width=<pixels>] \
+                             [height=<pixels>] [fps=<frames>] [duration=<seconds>]";
+
+const SVG_USAGE: &'static str = "usage: svg <level or replay> <output> [angle=<radians>] \
+                                 [speed=<speed>] [width=<pixels>] [height=<pixels>]";
+
+impl Settings {
+    /**
+     * Creates the default settings: 800x800 pixels at 30 frames per second
+     * for at most 10 seconds
+     */
+    pub fn new() -> Settings {
+        Settings {
+            width: 800,
+            height: 800,
+            fps: 30.0,
+            duration: 10.0,
+        }
+    }
+}
+
+/**
+ * Runs the export command with the given arguments, returning the number of
+ * frames written. The input is a level, or a replay if it ends in `.rpl`. An
+ * output ending in `.gif` is written as an animation, any other output as one
+ * PNG image per frame, numbered after the output name
+ */
+pub fn run(arguments: &[String],
+           catalogue: catalogue::Catalogue,
+           game_settings: &settings::Settings)
+           -> Result<usize, String> {
+    if arguments.len() < 2 {
+        return Err(String::from(USAGE));
+    }
+    let input = Path::new(&arguments[0]);
+    let output = Path::new(&arguments[1]);
+
+    let mut settings = Settings::new();
+    for option in &arguments[2..] {
+        let mut parts = option.splitn(2, '=');
+        let (key, value) = (parts.next().unwrap(), parts.next().unwrap_or(""));
+        match key {
+            "width" => settings.width = try!(parse_size(value)),
+            "height" => settings.height = try!(parse_size(value)),
+            "fps" => settings.fps = try!(parse::parse_number(value)),
+            "duration" => settings.duration = try!(parse::parse_number(value)),
+            _ => return Err(format!("unknown option '{}'\n{}", key, USAGE)),
+        }
+    }
+    if settings.fps <= 0.0 || settings.duration < 0.0 {
+        return Err(String::from("fps must be positive and duration not negative"));
+    }
+
+    let (game, shots) = try!(load_game(input, catalogue, game_settings));
+    export(game, shots, output, &settings)
+}
+
+/**
+ * Runs the svg command with the given arguments, writing the table and the
+ * traced paths of a shot to an SVG image. The shot is given by its angle and
+ * speed, or for a replay is its last shot, played after all the others
+ */
+pub fn run_svg(arguments: &[String],
+               catalogue: catalogue::Catalogue,
+               settings: &settings::Settings)
+               -> Result<(), String> {
+    if arguments.len() < 2 {
+        return Err(String::from(SVG_USAGE));
+    }
+    let input = Path::new(&arguments[0]);
+    let output = Path::new(&arguments[1]);
+
+    let (mut width, mut height) = (800, 800);
+    let (mut angle, mut speed) = (None, None);
+    for option in &arguments[2..] {
+        let mut parts = option.splitn(2, '=');
+        let (key, value) = (parts.next().unwrap(), parts.next().unwrap_or(""));
+        match key {
+            "angle" => angle = Some(try!(parse::parse_number(value))),
+            "speed" => speed = Some(try!(parse::parse_number(value))),
+            "width" => width = try!(parse_size(value)),
+            "height" => height = try!(parse_size(value)),
+            _ => return Err(format!("unknown option '{}'\n{}", key, SVG_USAGE)),
+        }
+    }
+
+    let (mut game, mut shots) = try!(load_game(input, catalogue, settings));
+    let shot = match (angle, speed) {
+        (Some(angle), Some(speed)) => {
+            Some(replay::Shot {
+                angle: angle,
+                speed: speed,
+                time: None,
+            })
+        }
+        (None, None) => shots.pop(),
+        _ => return Err(String::from("both the angle and the speed of the shot are needed")),
+    };
+    if let Some(ref shot) = shot {
+        try!(play_until(&mut game, shots, shot));
+    }
+
+    svg::save(&game, shot.as_ref(), output, width, height)
+}
+
+/**
+ * Loads the level or replay at the given path, returning the game with the
+ * given settings and the shots of the replay
+ */
+pub fn load_game(input: &Path,
+                 catalogue: catalogue::Catalogue,
+                 settings: &settings::Settings)
+                 -> Result<(game::Game, Vec<replay::Shot>), String> {
+    // Replays name the level they were played on
+    let (level_path, replay) = if input.extension() == Some(OsStr::new("rpl")) {
+        let replay = try!(replay::Replay::load(input));
+        (PathBuf::from(&replay.level), Some(replay))
+    } else {
+        (input.to_path_buf(), None)
+    };
+
+    let level = try!(level::Level::load(&level_path, &catalogue));
+    let mut game = game::Game::new(level, catalogue);
+    game.set_settings(settings);
+    let shots = match replay {
+        Some(replay) => {
+            game.set_integrator(replay.integrator);
+            replay.shots
+        }
+        None => Vec::new(),
+    };
+    Ok((game, shots))
+}
+
+/**
+ * Plays the given game headlessly, taking the given shots, until the given
+ * next shot is due and can be taken
+ */
+fn play_until(game: &mut game::Game,
+              shots: Vec<replay::Shot>,
+              next: &replay::Shot)
+              -> Result<(), String> {
+    let args = UpdateArgs { dt: 1.0 / UPDATES_PER_SECOND as f64 };
+    let mut pending: VecDeque<replay::Shot> = shots.into_iter().collect();
+    let latest = next.time.unwrap_or(0.0) + trace::MAX_TIME;
+
+    while !(pending.is_empty() && is_due(game, next) && game.can_shoot()) {
+        if game.is_over() || game.time() > latest {
+            return Err(String::from("the shot can never be taken"));
+        }
+        take_due_shot(game, &mut pending);
+        game.update(&args);
+    }
+    Ok(())
+}
+
+/**
+ * Plays the given game headlessly, taking the given shots, until every shot
+ * has been taken and the table has come to rest or the game is over. Tables
+ * that never come to rest are played for a while after the last shot
+ */
+pub fn play(game: &mut game::Game, shots: Vec<replay::Shot>) -> Result<(), String> {
+    let args = UpdateArgs { dt: 1.0 / UPDATES_PER_SECOND as f64 };
+    let total = shots.len();
+    let mut pending: VecDeque<replay::Shot> = shots.into_iter().collect();
+    let mut last_shot = game.time();
+
+    while !game.is_over() && !(pending.is_empty() && game.is_at_rest()) {
+        let due = pending.front().and_then(|shot| shot.time).unwrap_or(0.0).max(last_shot);
+        if game.time() > due + trace::MAX_TIME {
+            if pending.is_empty() {
+                break;
+            }
+            return Err(format!("shot {} can never be taken", total - pending.len() + 1));
+        }
+
+        let left = pending.len();
+        take_due_shot(game, &mut pending);
+        if pending.len() < left {
+            last_shot = game.time();
+        }
+        game.update(&args);
+    }
+    Ok(())
+}
+
+/**
+ * Plays the given game headlessly, taking the given shots, and writes its
+ * frames to the given output. Replays end early once every shot has been taken
+ * and the table has come to rest
+ */
+pub fn export(mut game: game::Game,
+              shots: Vec<replay::Shot>,
+              output: &Path,
+              settings: &Settings)
+              -> Result<usize, String> {
+    let mut animation = if output.extension() == Some(OsStr::new("gif")) {
+        let delay = (100.0 / settings.fps).round().max(1.0) as u16;
+        Some(try!(gif::GifWriter::create(output, settings.width, settings.height, delay)
+            .map_err(|err| format!("could not write {}: {}", output.display(), err))))
+    } else {
+        None
+    };
+
+    let args = UpdateArgs { dt: 1.0 / UPDATES_PER_SECOND as f64 };
+    let replaying = !shots.is_empty();
+    let mut pending: VecDeque<replay::Shot> = shots.into_iter().collect();
+    let frames = ((settings.duration * settings.fps).ceil() as usize).max(1);
+    let mut updates = 0;
+    let mut written = 0;
+
+    for frame in 0..frames {
+        // Catch up with the time of the frame
+        let target = (frame as f64 * UPDATES_PER_SECOND as f64 / settings.fps).round() as u64;
+        while updates < target && !game.is_over() {
+            take_due_shot(&mut game, &mut pending);
+            game.update(&args);
+            updates += 1;
+        }
+
+        let mut canvas = canvas::Canvas::new(settings.width, settings.height);
+        game.render(&mut canvas);
+
+        let written_frame = match animation {
+            Some(ref mut animation) => animation.add_frame(&canvas),
+            None => png::save(&canvas, &frame_path(output, frame)),
+        };
+        try!(written_frame.map_err(|err| format!("could not write frame {}: {}", frame, err)));
+        written += 1;
+
+        if game.is_over() || (replaying && pending.is_empty() && game.is_at_rest()) {
+            break;
+        }
+    }
+
+    if let Some(animation) = animation {
+        try!(animation.finish()
+            .map_err(|err| format!("could not write {}: {}", output.display(), err)));
+    }
+    Ok(written)
+}
+
+/**
+ * Takes the next shot if it is due and the white ball is still
+ */
+fn take_due_shot(game: &mut game::Game, pending: &mut VecDeque<replay::Shot>) {
+    let due = match pending.front() {
+        Some(shot) => is_due(game, shot),
+        None => false,
+    };
+    if due && game.shoot(pending.front().unwrap()) {
+        pending.pop_front();
+    }
+}
+
+/**
+ * Returns `true` if the time of the given shot has come
+ */
+fn is_due(game: &game::Game, shot: &replay::Shot) -> bool {
+    match shot.time {
+        Some(time) => game.time() >= time,
+        None => true,
+    }
+}
+
+/**
+ * Returns the path of the PNG image of the given frame, numbered after the
+ * given output name
+ */
+fn frame_path(output: &Path, frame: usize) -> PathBuf {
+    let name = output.file_stem().and_then(|stem| stem.to_str()).unwrap_or("frame");
+    output.with_file_name(format!("{}{:04}.png", name, frame))
+}
+
+/**
+ * Parses a positive image size in pixels
+ */
+fn parse_size(word: &str) -> Result<usize, String> {
+    let size = try!(parse::parse_integer(word));
+    if size <= 0 || size > 65535 {
+        return Err(format!("'{}' is not a valid size", word));
+    }
+    Ok(size as usize)
+}
+
+#[test]
+fn test_frame_path() {
+    assert_eq!(frame_path(Path::new("out/shot.png"), 7),
+               PathBuf::from("out/shot0007.png"));
+    assert_eq!(frame_path(Path::new("frames"), 12), PathBuf::from("frames0012.png"));
+}
+
+#[test]
+fn test_replay_is_deterministic() {
+    let source = "
+        ball white 0.3 0.5
+        ball red 0.5 0.5
+        ball red 0.52 0.51
+        goalzone 0.9 0.5
+    ";
+    let shot = replay::Shot {
+        angle: 0.0,
+        speed: 0.8,
+        time: Some(0.5),
+    };
+    let args = UpdateArgs { dt: 1.0 / UPDATES_PER_SECOND as f64 };
+
+    // Playing the same shots twice ends with the same table
+    let mut results = Vec::new();
+    for _ in 0..2 {
+        let mut game = game::test_game(source);
+        let mut pending: VecDeque<replay::Shot> = vec![shot.clone()].into_iter().collect();
+        for _ in 0..(2 * UPDATES_PER_SECOND) {
+            take_due_shot(&mut game, &mut pending);
+            game.update(&args);
+        }
+        assert!(pending.is_empty());
+        assert_eq!(game.shots().len(), 1);
+        results.push(game.diagnostics().clone());
+    }
+    assert_eq!(results[0].kinetic_energy, results[1].kinetic_energy);
+    assert_eq!(results[0].momentum, results[1].momentum);
+}
+
+#[test]
+fn test_play() {
+    let source = "
+        ball white 0.3 0.5
+        ball red 0.5 0.5
+    ";
+    let mut game = game::test_game(source);
+    let shots = vec![replay::Shot {
+                         angle: 0.0,
+                         speed: 0.5,
+                         time: None,
+                     },
+                     replay::Shot {
+                         angle: 3.0,
+                         speed: 0.3,
+                         time: Some(1.0),
+                     }];
+
+    // Both shots are taken, and the game ends with the table at rest
+    play(&mut game, shots).unwrap();
+    assert_eq!(game.shots().len(), 2);
+    assert!(game.time() >= 1.0);
+    assert!(game.is_at_rest());
+}
@@ -0,0 +1,295 @@
+use na::{Point2, Vector2, Norm};
+use std::str::FromStr;
+use renderer::{Color, Renderer};
+use blackhole;
+use nbody;
+use physics;
+use poolball;
+
+/**
+ * The ways the gravity field can be shown on top of the table
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldView {
+    Hidden,
+    Arrows,
+    Contours,
+}
+
+/**
+ * The gravity field of the blackholes and of the poolballs pulling on each
+ * other, sampled on a regular grid of points spanning the table
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub size: usize,
+    pub accelerations: Vec<Vector2<f64>>,
+    pub potentials: Vec<f64>,
+}
+
+// Points per side of the grids the field is drawn from
+const ARROW_GRID: usize = 24;
+const CONTOUR_GRID: usize = 64;
+
+// Accelerations drawn with the shortest and the longest arrows, the arrow
+// length growing with the logarithm of the acceleration in between
+const MIN_ACCELERATION: f64 = 0.01;
+const MAX_ACCELERATION: f64 = 10.0;
+
+// Number of equipotential contours, each at half the potential of the
+// previous one starting from the deepest point of the table
+const CONTOURS: usize = 6;
+
+const CONTOUR_COLOR: Color = [0.4, 0.6, 1.0, 0.5];
+
+impl FieldView {
+    /**
+     * Returns the view following this one, for cycling through the views
+     */
+    pub fn next(self) -> FieldView {
+        match self {
+            FieldView::Hidden => FieldView::Arrows,
+            FieldView::Arrows => FieldView::Contours,
+            FieldView::Contours => FieldView::Hidden,
+        }
+    }
+
+    /**
+     * Returns the name of the view
+     */
+    pub fn name(&self) -> &'static str {
+        match *self {
+            FieldView::Hidden => "hidden",
+            FieldView::Arrows => "arrows",
+            FieldView::Contours => "contours",
+        }
+    }
+}
+
+impl FromStr for FieldView {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<FieldView, String> {
+        match name {
+            "hidden" => Ok(FieldView::Hidden),
+            "arrows" => Ok(FieldView::Arrows),
+            "contours" => Ok(FieldView::Contours),
+            _ => Err(format!("unknown field view '{}'", name)),
+        }
+    }
+}
+
+impl Field {
+    /**
+     * Samples the field on a grid of the given number of points per side,
+     * from the corners of the table to the opposite ones, with the given
+     * gravity constant for the blackholes
+     */
+    pub fn sample(size: usize,
+                  blackholes: &Vec<blackhole::Blackhole>,
+                  gravity: f64,
+                  n_body: Option<&nbody::NBody>,
+                  balls: &Vec<poolball::Poolball>)
+                  -> Field {
+        let mut accelerations = Vec::with_capacity(size * size);
+        let mut potentials = Vec::with_capacity(size * size);
+        for row in 0..size {
+            for column in 0..size {
+                let point = grid_point(size, column, row);
+                let mut acceleration = physics::gravity_at(blackholes, &point, gravity);
+                let mut potential = physics::gravity_potential(blackholes, &point, gravity);
+                if let Some(n_body) = n_body {
+                    acceleration += n_body.acceleration_at(balls, &point);
+                    potential += n_body.potential_at(balls, &point);
+                }
+                accelerations.push(acceleration);
+                potentials.push(potential);
+            }
+        }
+
+        Field {
+            size: size,
+            accelerations: accelerations,
+            potentials: potentials,
+        }
+    }
+
+    /**
+     * Returns the position on the table of the given grid point
+     */
+    pub fn point(&self, column: usize, row: usize) -> Point2<f64> {
+        grid_point(self.size, column, row)
+    }
+
+    /**
+     * Draws an arrow along the field at every grid point, longer and hotter in
+     * colour where the pull is stronger
+     */
+    pub fn render_arrows(&self, renderer: &mut Renderer) {
+        let spacing = 1.0 / self.size as f64;
+        for row in 0..self.size {
+            for column in 0..self.size {
+                let acceleration = self.accelerations[row * self.size + column];
+                let magnitude = acceleration.norm();
+                if magnitude < MIN_ACCELERATION {
+                    continue;
+                }
+
+                let strength = ((magnitude / MIN_ACCELERATION).ln() /
+                                (MAX_ACCELERATION / MIN_ACCELERATION).ln())
+                    .min(1.0);
+                let length = spacing * (0.3 + 0.6 * strength);
+                let from = self.point(column, row);
+                let to = from + acceleration / magnitude * length;
+                renderer.arrow(heat(strength), from, to, 0.0015, 0.3 * length);
+            }
+        }
+    }
+
+    /**
+     * Draws equipotential contours, closer together where the pull is
+     * stronger
+     */
+    pub fn render_contours(&self, renderer: &mut Renderer) {
+        for level in self.contour_levels() {
+            for (from, to) in self.contour(level) {
+                renderer.line(CONTOUR_COLOR, from, to, 0.0015);
+            }
+        }
+    }
+
+    /**
+     * Returns the potentials of the contours, halving from the deepest point
+     * of the table
+     */
+    fn contour_levels(&self) -> Vec<f64> {
+        let deepest = self.potentials.iter().fold(0.0_f64, |deepest, &value| deepest.min(value));
+        if deepest >= 0.0 {
+            return Vec::new();
+        }
+        (1..CONTOURS + 1).map(|index| deepest / 2.0_f64.powi(index as i32)).collect()
+    }
+
+    /**
+     * Returns the line segments along which the potential equals the given
+     * level, found cell by cell with marching squares
+     */
+    fn contour(&self, level: f64) -> Vec<(Point2<f64>, Point2<f64>)> {
+        let mut segments = Vec::new();
+        for row in 0..self.size - 1 {
+            for column in 0..self.size - 1 {
+                // Corners clockwise from the top left
+                let corners = [(column, row), (column + 1, row), (column + 1, row + 1),
+                               (column, row + 1)];
+                let mut crossings = Vec::new();
+                for i in 0..4 {
+                    let (a, b) = (corners[i], corners[(i + 1) % 4]);
+                    let value_a = self.potentials[a.1 * self.size + a.0] - level;
+                    let value_b = self.potentials[b.1 * self.size + b.0] - level;
+                    if (value_a < 0.0) != (value_b < 0.0) {
+                        let share = value_a / (value_a - value_b);
+                        let (point_a, point_b) = (self.point(a.0, a.1), self.point(b.0, b.1));
+                        crossings.push(point_a + (point_b - point_a) * share);
+                    }
+                }
+                for pair in crossings.chunks(2) {
+                    if pair.len() == 2 {
+                        segments.push((pair[0], pair[1]));
+                    }
+                }
+            }
+        }
+        segments
+    }
+}
+
+/**
+ * Draws the gravity field of the given blackholes, with the given gravity
+ * constant, and poolballs in the given view
+ */
+pub fn render(view: FieldView,
+              blackholes: &Vec<blackhole::Blackhole>,
+              gravity: f64,
+              n_body: Option<&nbody::NBody>,
+              balls: &Vec<poolball::Poolball>,
+              renderer: &mut Renderer) {
+    match view {
+        FieldView::Hidden => {}
+        FieldView::Arrows => {
+            Field::sample(ARROW_GRID, blackholes, gravity, n_body, balls).render_arrows(renderer);
+        }
+        FieldView::Contours => {
+            Field::sample(CONTOUR_GRID, blackholes, gravity, n_body, balls)
+                .render_contours(renderer);
+        }
+    }
+}
+
+/**
+ * Returns the position on the table of a point of a grid with the given
+ * number of points per side
+ */
+fn grid_point(size: usize, column: usize, row: usize) -> Point2<f64> {
+    let spacing = 1.0 / (size - 1) as f64;
+    Point2::new(column as f64 * spacing, row as f64 * spacing)
+}
+
+/**
+ * Returns a colour going from blue through yellow to red as the given share
+ * goes from 0 to 1
+ */
+fn heat(share: f64) -> Color {
+    let share = share.max(0.0).min(1.0) as f32;
+    if share < 0.5 {
+        [2.0 * share, 2.0 * share, 1.0 - 2.0 * share, 0.7]
+    } else {
+        [1.0, 2.0 - 2.0 * share, 0.0, 0.7]
+    }
+}
+
+#[test]
+fn test_arrows_point_inwards() {
+    let blackholes = vec![blackhole::Blackhole::new(Point2::new(0.5, 0.5), 0.01, 0.01, 0.3)];
+    let field = Field::sample(11, &blackholes, 1.0, None, &Vec::new());
+
+    // Left of the blackhole the pull is to the right, outside the reach none
+    let left = field.accelerations[5 * 11 + 3];
+    assert!(left.x > 0.0 && left.y.abs() < 0.000001);
+    assert_eq!(field.accelerations[0], Vector2::new(0.0, 0.0));
+    assert!(field.accelerations[5 * 11 + 4].norm() > left.norm());
+}
+
+#[test]
+fn test_contours() {
+    let blackholes = vec![blackhole::Blackhole::new(Point2::new(0.5, 0.5), 0.01, 0.01, 0.4)];
+    let field = Field::sample(CONTOUR_GRID, &blackholes, 1.0, None, &Vec::new());
+
+    // Every contour is a circle around the blackhole
+    let levels = field.contour_levels();
+    assert_eq!(levels.len(), CONTOURS);
+    for &level in &levels {
+        let segments = field.contour(level);
+        assert!(!segments.is_empty());
+        let distance = |point: &Point2<f64>| (*point - Point2::new(0.5, 0.5)).norm();
+        let radius = distance(&segments[0].0);
+        for &(from, to) in &segments {
+            assert!((distance(&from) - radius).abs() < 0.02);
+            assert!((distance(&to) - radius).abs() < 0.02);
+        }
+    }
+
+    // No blackholes, no contours
+    let empty = Field::sample(CONTOUR_GRID, &Vec::new(), 1.0, None, &Vec::new());
+    assert!(empty.contour_levels().is_empty());
+}
+
+#[test]
+fn test_n_body_field() {
+    let n_body = nbody::NBody::new(1.0, 0.01, 0.0);
+    let balls = vec![poolball::Poolball::new(Point2::new(0.5, 0.5), poolball::BallType::Red)];
+    let field = Field::sample(3, &Vec::new(), 1.0, Some(&n_body), &balls);
+
+    // The corner is pulled towards the poolball in the middle
+    assert!(field.accelerations[0].x > 0.0 && field.accelerations[0].y > 0.0);
+    assert!(field.potentials[4] < field.potentials[0]);
+}
@@ -1,103 +1,514 @@
 use piston::input::*;
-use opengl_graphics::GlGraphics;
-use opengl_graphics::glyph_cache::GlyphCache;
-use na::Point2;
+use na::{Point2, Vector2, Dot, Norm};
 use std::f64;
-use std::process::exit;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::time::Instant;
 
 use poolball;
 use goalzone;
 use blackhole;
 use physics;
 use arrow;
+use wormhole;
+use event;
+use level;
+use catalogue;
+use nbody;
+use integrator;
+use diagnostics;
+use renderer;
+use replay;
+use effects;
+use field;
+use debug;
+use hud;
+use settings;
 
 /**
- * Struct used for holding information about a ball-ball collision or a
- * ball-wall collision.
- * A ball-wall collision is signified by Second being none.
+ * Struct used for holding information about a ball-ball collision, a
+ * ball-wall collision or a poolball entering a wormhole portal.
  */
 struct CollisionPair {
     first: poolball::Poolball,
-    second: Option<poolball::Poolball>,
+    second: Collider,
     time: f64,
 }
 
+/**
+ * The thing the first poolball in a collision pair collides with. Portals are
+ * identified by the index of their wormhole and the index of the portal
+ */
+enum Collider {
+    Wall,
+    Ball(poolball::Poolball),
+    Portal(usize, usize),
+}
+
+// Maximum number of times poolballs may pass through wormholes during a single
+// update, guarding against balls bouncing between overlapping portals forever
+const MAX_TELEPORTS: u32 = 8;
+
+// Collisions solved in a single update before the rest are left to the
+// separation of overlapping balls, so that clusters of touching balls can not
+// stall the game
+const MAX_COLLISIONS: u32 = 256;
+
 /**
  * Contains information about the global game state as well as methods for
  * handling the overarching game mechanics including the update loop and
  * the collision handling algorithm.
  */
+#[derive(Clone)]
 pub struct Game {
     balls: Vec<poolball::Poolball>,
     blackholes: Vec<blackhole::Blackhole>,
     goalzones: Vec<goalzone::Goalzone>,
+    wormholes: Vec<wormhole::Wormhole>,
+    catalogue: catalogue::Catalogue,
+    n_body: Option<nbody::NBody>,
+    integrator: integrator::Method,
+    time: f64,
+    diagnostics: diagnostics::Diagnostics,
+    show_diagnostics: bool,
     score: i32,
+    over: bool,
+    shots: Vec<replay::Shot>,
     arrow: arrow::Arrow,
+    events: Vec<event::Event>,
+    effects: effects::Effects,
+    field_view: field::FieldView,
+    show_debug: bool,
+    step_stats: debug::StepStats,
+    hud: hud::Hud,
+    settings: settings::Settings,
+    overrides: Vec<(String, f64)>,
 }
 
 impl Game {
     /**
-     * Creates a new game given the specified parameters
+     * Creates a new game from the given level, using the given catalogue for
+     * any poolballs created during the game. The game starts out with the
+     * default settings overridden by the level
      */
-    pub fn new(balls: Vec<poolball::Poolball>,
-               blackholes: Vec<blackhole::Blackhole>,
-               goalzones: Vec<goalzone::Goalzone>)
-               -> Self {
-        Game {
-            balls: balls,
-            blackholes: blackholes,
-            goalzones: goalzones,
+    pub fn new(level: level::Level, catalogue: catalogue::Catalogue) -> Self {
+        let settings = settings::Settings::new();
+        let diagnostics = diagnostics::Diagnostics::measure(0.0,
+                                                            &level.balls,
+                                                            &level.blackholes,
+                                                            settings.gravity,
+                                                            level.n_body.as_ref(),
+                                                            0.0,
+                                                            0.0);
+        let mut game = Game {
+            balls: level.balls,
+            blackholes: level.blackholes,
+            goalzones: level.goalzones,
+            wormholes: level.wormholes,
+            catalogue: catalogue,
+            n_body: level.n_body,
+            integrator: integrator::Method::ExplicitEuler,
+            time: 0.0,
+            diagnostics: diagnostics,
+            show_diagnostics: false,
             score: 0,
-            arrow: arrow::Arrow::new(Point2::new(0.0, 0.0)),
+            over: false,
+            shots: Vec::new(),
+            arrow: arrow::Arrow::new(Point2::new(0.0, 0.0),
+                                     settings.aim_speed,
+                                     settings.arrow_length),
+            events: Vec::new(),
+            effects: effects::Effects::new(),
+            field_view: field::FieldView::Hidden,
+            show_debug: false,
+            step_stats: debug::StepStats::new(),
+            hud: hud::Hud::new(),
+            settings: settings.clone(),
+            overrides: level.settings,
+        };
+        game.set_settings(&settings);
+        game
+    }
+
+    /**
+     * Returns the settings in use, including the overrides of the level
+     */
+    pub fn settings(&self) -> &settings::Settings {
+        &self.settings
+    }
+
+    /**
+     * Returns the settings overridden by the level
+     */
+    pub fn overrides(&self) -> &[(String, f64)] {
+        &self.overrides
+    }
+
+    /**
+     * Changes the settings of the game, keeping the overrides of the level.
+     * The blackholes are scaled from the settings used before, so that mass
+     * gained by accretion is kept
+     */
+    pub fn set_settings(&mut self, settings: &settings::Settings) {
+        let previous = mem::replace(&mut self.settings, settings.overridden(&self.overrides));
+        self.arrow.rotation_speed = self.settings.aim_speed;
+        self.arrow.max_length = self.settings.arrow_length;
+        for goalzone in &mut self.goalzones {
+            goalzone.set_radius(self.settings.goalzone_radius);
+        }
+        for ball in &mut self.balls {
+            ball.restitution = restitution(&self.catalogue, &ball.ball_type, &self.settings);
+        }
+
+        let mass = self.settings.blackhole_mass_scale / previous.blackhole_mass_scale;
+        let reach = self.settings.blackhole_reach_scale / previous.blackhole_reach_scale;
+        for hole in &mut self.blackholes {
+            hole.scale(mass, reach);
+        }
+
+        // The pull of the blackholes changes straight away
+        self.diagnostics.potential_energy =
+            diagnostics::potential_energy(&self.balls,
+                                          &self.blackholes,
+                                          self.settings.gravity,
+                                          self.n_body.as_ref());
+    }
+
+    /**
+     * Returns the integrator used for the forces acting on the poolballs
+     */
+    pub fn integrator(&self) -> integrator::Method {
+        self.integrator
+    }
+
+    /**
+     * Selects the integrator used for the forces acting on the poolballs
+     */
+    pub fn set_integrator(&mut self, method: integrator::Method) {
+        self.integrator = method;
+    }
+
+    /**
+     * Returns the energy and momentum diagnostics measured after the latest
+     * update
+     */
+    pub fn diagnostics(&self) -> &diagnostics::Diagnostics {
+        &self.diagnostics
+    }
+
+    /**
+     * Shows or hides the diagnostics overlay
+     */
+    pub fn toggle_diagnostics(&mut self) {
+        self.show_diagnostics = !self.show_diagnostics;
+    }
+
+    /**
+     * Returns the time passed in the game, in seconds
+     */
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /**
+     * Returns `true` once the white ball is lost without enough score left to
+     * spawn a new one
+     */
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    /**
+     * Returns `true` once every poolball but the white one is gone from the
+     * table and nothing is moving any more
+     */
+    pub fn is_cleared(&self) -> bool {
+        self.is_at_rest() &&
+        self.balls.iter().all(|ball| ball.ball_type == poolball::BallType::White)
+    }
+
+    /**
+     * Returns `true` if no poolball is moving
+     */
+    pub fn is_at_rest(&self) -> bool {
+        let speed = self.settings.stationary_speed;
+        self.balls.iter().all(|ball| ball.is_slower_than(speed))
+    }
+
+    /**
+     * Returns the way the gravity field is shown
+     */
+    pub fn field_view(&self) -> field::FieldView {
+        self.field_view
+    }
+
+    /**
+     * Shows the gravity field in the given way
+     */
+    pub fn set_field_view(&mut self, view: field::FieldView) {
+        self.field_view = view;
+    }
+
+    /**
+     * Shows or hides the physics debug view
+     */
+    pub fn toggle_debug(&mut self) {
+        self.show_debug = !self.show_debug;
+    }
+
+    /**
+     * Shows or hides the trails behind moving poolballs
+     */
+    pub fn toggle_trails(&mut self) {
+        self.effects.toggle_trails();
+    }
+
+    /**
+     * Shows or hides the particles bursting from impacts and pots
+     */
+    pub fn toggle_particles(&mut self) {
+        self.effects.toggle_particles();
+    }
+
+    /**
+     * Returns `true` if the diagnostics overlay is shown
+     */
+    pub fn diagnostics_shown(&self) -> bool {
+        self.show_diagnostics
+    }
+
+    /**
+     * Returns `true` if the physics debug view is shown
+     */
+    pub fn debug_shown(&self) -> bool {
+        self.show_debug
+    }
+
+    /**
+     * Returns `true` if trails are left behind moving poolballs
+     */
+    pub fn trails_shown(&self) -> bool {
+        self.effects.trails_enabled
+    }
+
+    /**
+     * Returns `true` if particles burst from impacts and pots
+     */
+    pub fn particles_shown(&self) -> bool {
+        self.effects.particles_enabled
+    }
+
+    /**
+     * Returns the current score
+     */
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+
+    /**
+     * Returns the number of white balls left, counting the one on the table.
+     * A lost white ball is replaced as long as the score stays positive after
+     * the penalty for potting it, so there is no limit without a penalty
+     */
+    pub fn lives(&self) -> Option<u32> {
+        if self.over {
+            return Some(0);
+        }
+        let penalty = -self.catalogue.properties(&poolball::BallType::White).value;
+        if self.score <= 0 {
+            Some(1)
+        } else if penalty <= 0 {
+            None
+        } else {
+            Some(((self.score + penalty - 1) / penalty) as u32)
         }
     }
 
+    /**
+     * Returns the poolballs on the table
+     */
+    pub fn balls(&self) -> &Vec<poolball::Poolball> {
+        &self.balls
+    }
+
+    /**
+     * Returns the blackholes of the table
+     */
+    pub fn blackholes(&self) -> &Vec<blackhole::Blackhole> {
+        &self.blackholes
+    }
+
+    /**
+     * Returns the goalzones of the table
+     */
+    pub fn goalzones(&self) -> &Vec<goalzone::Goalzone> {
+        &self.goalzones
+    }
+
+    /**
+     * Returns the shots taken so far, each with the time it was taken
+     */
+    pub fn shots(&self) -> &Vec<replay::Shot> {
+        &self.shots
+    }
+
+    /**
+     * Returns `true` if the white ball is on the table and still, ready to be
+     * shot
+     */
+    pub fn can_shoot(&self) -> bool {
+        match white_ball_position(&self.balls) {
+            Some(pos) => self.balls[pos].is_slower_than(self.settings.stationary_speed),
+            None => false,
+        }
+    }
+
+    /**
+     * Returns the shot the aiming arrow currently points out
+     */
+    pub fn aimed_shot(&self) -> replay::Shot {
+        replay::Shot::new(self.arrow.direction,
+                          self.arrow.length * self.settings.shot_speed)
+    }
+
+    /**
+     * Points the aiming arrow along the given shot from the white ball
+     */
+    pub fn aim(&mut self, shot: &replay::Shot) {
+        if let Some(pos) = white_ball_position(&self.balls) {
+            self.arrow.position = self.balls[pos].position;
+        }
+        self.arrow.direction = Vector2::new(shot.angle.cos(), shot.angle.sin());
+        self.arrow.length = shot.speed / self.settings.shot_speed;
+    }
+
+    /**
+     * Shoots the white ball with the velocity of the given shot if the white
+     * ball is still, returning `true` if the shot was taken
+     */
+    pub fn shoot(&mut self, shot: &replay::Shot) -> bool {
+        let time = self.time;
+        let speed = self.settings.stationary_speed;
+        match self.balls.iter_mut().find(|ball| ball.ball_type == poolball::BallType::White) {
+            Some(white_ball) => {
+                if !white_ball.is_slower_than(speed) {
+                    return false;
+                }
+                white_ball.set_velocity(shot.velocity());
+            }
+            None => return false,
+        }
+
+        self.shots.push(shot.clone().at(time));
+        self.arrow.mode = arrow::ShootingMode::Rotate;
+        true
+    }
+
+    /**
+     * Returns the events which occured during the latest update
+     */
+    pub fn events(&self) -> &Vec<event::Event> {
+        &self.events
+    }
+
     /**
      * Renders the current game state including the poolballs, current score,
-     * blackholes, goalzones and the arrow indicator using GlGraphics
+     * blackholes, goalzones, wormholes and the arrow indicator using the given
+     * renderer
      */
-    pub fn render(&mut self, gl: &mut GlGraphics, args: &RenderArgs, cache: &mut GlyphCache) {
-        use graphics::*;
+    pub fn render(&self, renderer: &mut renderer::Renderer) {
 
         const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
-        gl.draw(args.viewport(), |_, g| {
-            // Clear the screen.
-            clear(BLACK, g);
-        });
-
-        // Draw score
         const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
-        gl.draw(args.viewport(), |c, gl| {
+        const GRAY: [f32; 4] = [0.3, 0.3, 0.3, 1.0];
+
+        // Clear the screen.
+        renderer.clear(BLACK);
+
+        // Draw the walls, which set the table apart from the rest of the
+        // window when zoomed out or letterboxed
+        let corners = [Point2::new(0.0, 0.0),
+                       Point2::new(1.0, 0.0),
+                       Point2::new(1.0, 1.0),
+                       Point2::new(0.0, 1.0)];
+        for i in 0..corners.len() {
+            renderer.line(GRAY, corners[i], corners[(i + 1) % corners.len()], 0.002);
+        }
 
-            let trans = c.transform
-                .trans(0.46 * (args.width as f64), 0.02 * (args.height as f64));
-            let score_str = format!("Score: {}", self.score);
-            text::Text::new_color(WHITE, 18)
-                .draw(score_str.as_str(), cache, &c.draw_state, trans, gl);
-        });
+        // Draw the gravity field underneath everything else
+        field::render(self.field_view,
+                      &self.blackholes,
+                      self.settings.gravity,
+                      self.n_body.as_ref(),
+                      &self.balls,
+                      renderer);
 
         // Draw goalzones
         for goalzone in &self.goalzones {
-            goalzone.render(args, gl);
+            goalzone.render(renderer, &self.catalogue);
+        }
+
+        // Draw wormholes
+        for wormhole in &self.wormholes {
+            wormhole.render(renderer);
         }
 
         // Draw all poolballs
         for ball in &self.balls {
-            ball.render(args, gl);
+            ball.render(renderer);
         }
 
         // Draw all blackholes
         for blackhole in &self.blackholes {
-            blackhole.render(args, gl);
+            blackhole.render(renderer);
+        }
+
+        // Draw trails and particles on top of what they follow
+        self.effects.render(renderer);
+
+        if self.show_debug {
+            let prediction = self.predict_collision();
+            debug::render(&self.balls,
+                          self.settings.stationary_speed,
+                          prediction.as_ref(),
+                          &self.step_stats,
+                          renderer);
         }
 
         // Draw a line for the shooting if white ball exists
         if let Some(pos) = white_ball_position(&self.balls) {
             let white_ball = self.balls.get(pos).unwrap();
-            if white_ball.is_stationary() {
-                self.arrow.render(args, gl);
+            if white_ball.is_slower_than(self.settings.stationary_speed) {
+                self.arrow.render(renderer);
             }
         }
+
+        // Draw the score, shots, lives, remaining balls and messages
+        let power = match self.arrow.mode {
+            arrow::ShootingMode::Power if self.can_shoot() => Some(self.arrow.power()),
+            _ => None,
+        };
+        let status = hud::Status {
+            score: self.score,
+            shots: self.shots.len(),
+            lives: self.lives(),
+            remaining: hud::remaining(&self.balls),
+            power: power,
+        };
+        self.hud.render(&status, renderer);
+
+        // Draw the integrator in use
+        let integrator_str = format!("Integrator: {}", self.integrator.name());
+        renderer.text(WHITE, 12, Point2::new(0.01, 0.99), integrator_str.as_str());
+
+        if self.field_view != field::FieldView::Hidden {
+            let field_str = format!("Field: {}", self.field_view.name());
+            renderer.text(WHITE, 12, Point2::new(0.8, 0.99), field_str.as_str());
+        }
+
+        if self.show_diagnostics {
+            self.diagnostics.render(renderer);
+        }
     }
 
     /**
@@ -107,21 +518,17 @@ impl Game {
     pub fn try_switch_mode(&mut self) {
 
         if let Some(pos) = white_ball_position(&self.balls) {
-            let white_ball = self.balls.get_mut(pos).unwrap();
-            if !white_ball.is_stationary() {
+            if !self.balls[pos].is_slower_than(self.settings.stationary_speed) {
                 return;
             }
 
-            let mut arrow = &mut self.arrow;
-            match arrow.mode {
+            match self.arrow.mode {
                 arrow::ShootingMode::Rotate => {
-                    arrow.mode = arrow::ShootingMode::Power;
+                    self.arrow.mode = arrow::ShootingMode::Power;
                 }
                 arrow::ShootingMode::Power => {
-                    const SPEED_MULT: f64 = 5.0;
-                    let velocity = arrow.direction.clone() * arrow.length * SPEED_MULT;
-                    white_ball.set_velocity(velocity);
-                    arrow.mode = arrow::ShootingMode::Rotate;
+                    let shot = self.aimed_shot();
+                    self.shoot(&shot);
                 }
             }
         }
@@ -134,29 +541,37 @@ impl Game {
     */
     pub fn update(&mut self, args: &UpdateArgs) {
 
+        let started = Instant::now();
+        self.events.clear();
+
         // Update the arrow positon and orientation
         if let Some(pos) = white_ball_position(&self.balls) {
             let white_ball = self.balls.get(pos).unwrap();
-            if white_ball.is_stationary() {
+            if white_ball.is_slower_than(self.settings.stationary_speed) {
                 self.arrow.position = white_ball.position.clone();
                 self.arrow.update(args.dt);
             }
         }
 
+        // Integrate the forces acting on the balls over the time step. The
+        // balls travel in straight lines with the average velocity of their
+        // integrated paths while colliding, and the rest of the change in
+        // velocity is added afterwards to the balls that did not collide
+        let (corrections, friction_loss) = self.integrate_forces(args.dt);
+        let mut collision_loss = 0.0;
+        let mut collided = HashSet::new();
+
         // Save tatal time budget
         let mut time_left = args.dt;
-        let CollisionPair { mut first, mut second, mut time } = self.get_first_collision_pair();
+        let mut teleports = 0;
+        let mut collisions = 0;
+        let mut next = self.get_first_collision_pair(true);
 
         // While there exists a collision within this time step
-        while time < time_left {
-
-            // Remove the collision pair form the list of poolballs
-            self.balls.retain(|elem| {
-                match second {
-                    Some(ref mut second) => *elem != first && *elem != *second,
-                    None => *elem != first,
-                }
-            });
+        while let Some(CollisionPair { first, second, time }) = next {
+            if time >= time_left || collisions >= MAX_COLLISIONS {
+                break;
+            }
 
             // No collisions can occure before the first one. Move all balls
             // using their current velocities
@@ -164,37 +579,85 @@ impl Game {
                 ball.update(time);
             }
 
-            first.update(time);
-            if let Some(ref mut second) = second {
-                second.update(time);
-            }
-
             // Reduce time left
             time_left -= time;
+            collisions += 1;
 
-            // Solve the collision: either ball-wall or ball-ball
-            match second {
-                Some(mut second) => {
-                    physics::ball_ball_collision(&mut first, &mut second);
+            if let Collider::Ball(ref second) = second {
+                if let Some(impact) = impact(&self.balls, first.id, second.id) {
+                    self.events.push(impact);
+                }
+            }
 
-                    // Add updated first and second back
-                    self.balls.push(first);
-                    self.balls.push(second);
+            // Solve the collision: either ball-wall, ball-ball or ball-portal
+            match second {
+                Collider::Ball(ref second) if first.sticky || second.sticky => {
+                    // Sticky balls merge with whatever they touch
+                    let (mut pair, rest) = self.balls
+                        .drain(..)
+                        .partition::<Vec<_>, _>(|ball| ball.id == first.id || ball.id == second.id);
+                    self.balls = rest;
+                    if pair.len() == 2 {
+                        let second = pair.pop().unwrap();
+                        let first = pair.pop().unwrap();
+                        let merged = physics::merge(&first, &second);
+                        collided.insert(first.id);
+                        collided.insert(second.id);
+                        collided.insert(merged.id);
+                        collision_loss += diagnostics::kinetic_energy(&vec![first, second]) -
+                                          diagnostics::kinetic_energy(&vec![merged.clone()]);
+                        self.balls.push(merged);
+                    } else {
+                        self.balls.extend(pair);
+                    }
+                }
+                Collider::Ball(_) => {
+                    // Every impact at this moment is solved together, so that
+                    // e.g. a ball hitting a cluster is handled consistently
+                    let before = diagnostics::kinetic_energy(&self.balls);
+                    let velocities: Vec<Vector2<f64>> =
+                        self.balls.iter().map(|ball| ball.velocity).collect();
+                    physics::solve_impacts(&mut self.balls);
+                    collision_loss += before - diagnostics::kinetic_energy(&self.balls);
+                    for (ball, velocity) in self.balls.iter().zip(velocities) {
+                        if ball.velocity != velocity {
+                            collided.insert(ball.id);
+                        }
+                    }
                 }
-                None => {
-                    physics::ball_wall_collision(&mut first);
+                Collider::Wall => {
+                    if let Some(ball) = self.balls.iter_mut().find(|ball| ball.id == first.id) {
+                        self.events.push(event::Event::HitWall {
+                            ball_type: ball.ball_type.clone(),
+                            position: ball.position.clone(),
+                            speed: ball.velocity.norm(),
+                        });
+                        let before = vec![ball.clone()];
+                        physics::ball_wall_collision(ball);
+                        collided.insert(ball.id);
+                        collision_loss += diagnostics::kinetic_energy(&before) -
+                                          diagnostics::kinetic_energy(&vec![ball.clone()]);
+                    }
+                }
+                Collider::Portal(index, entrance) => {
+                    let wormhole = &self.wormholes[index];
+                    if let Some(ball) = self.balls.iter_mut().find(|ball| ball.id == first.id) {
+                        let from = ball.position.clone();
+                        wormhole.teleport(ball, entrance);
+                        teleports += 1;
+                        collided.insert(ball.id);
 
-                    // Add updated first and second back
-                    self.balls.push(first);
+                        self.events.push(event::Event::Teleported {
+                            ball_type: ball.ball_type.clone(),
+                            from: from,
+                            to: ball.position.clone(),
+                        });
+                    }
                 }
             }
 
             // Get the next collision pair
-            let pair = self.get_first_collision_pair();
-            first = pair.first;
-            second = pair.second;
-            time = pair.time;
-
+            next = self.get_first_collision_pair(teleports < MAX_TELEPORTS);
         }
 
         // If there is time left, advance the rest of the time step
@@ -202,10 +665,16 @@ impl Game {
             ball.update(time_left);
         }
 
-        // Add friction for this time step
+        // Complete the change in velocity from the forces in this time step.
+        // The corrections point along the velocities before any bounce or
+        // teleport, so they are dropped for the balls that collided
         for ball in &mut self.balls {
-            let friction = physics::calculate_friction(ball);
-            ball.update_velocity(friction, args.dt);
+            if collided.contains(&ball.id) {
+                continue;
+            }
+            if let Some(correction) = corrections.get(&ball.id) {
+                ball.velocity += *correction;
+            }
         }
 
         // Check if white ball exists, spawn new if not as long as the score is
@@ -214,59 +683,206 @@ impl Game {
         match pos {
             // White ball is dead but we have enough score to spawn a new one
             None if self.score > 0 => {
-                let new_white_ball = poolball::Poolball::new(Point2::new(0.1, 0.1),
-                                                             poolball::BallType::White);
+                let mut new_white_ball = self.catalogue
+                    .create(Point2::new(0.1, 0.1), poolball::BallType::White);
+                new_white_ball.restitution =
+                    restitution(&self.catalogue, &poolball::BallType::White, &self.settings);
                 self.balls.push(new_white_ball);
             }
             // No score left to respawn, game over
             None => {
-                exit(0); // Game over kills processs as of now
+                self.over = true;
             }
             _ => {}
         }
 
-        // Add accelerations for all balls within blackholes
-        let blackholes = &self.blackholes;
+        // Push apart balls left overlapping, e.g. by being spawned on top of
+        // each other, as the respawned white ball may be, or squeezed together
+        // by gravity
+        physics::separate_overlaps(&mut self.balls);
 
-        for ball in &mut self.balls {
-            let acceleration = physics::calculate_gravity(blackholes, ball);
-            ball.update_velocity(acceleration, args.dt)
-        }
-        let balls = &mut self.balls;
+        // The borrows of the fields end with the block, before the table is
+        // measured
+        {
+            let balls = &mut self.balls;
+            let blackholes = &mut self.blackholes;
+            let events = &mut self.events;
+
+            // Remove spagettified balls, letting the blackholes grow
+            balls.retain(|ball| {
+                match blackholes.iter_mut().find(|hole| hole.is_spagettified(ball)) {
+                    Some(hole) => {
+                        hole.swallow(ball);
+                        events.push(event::Event::Swallowed {
+                            ball_type: ball.ball_type.clone(),
+                            position: ball.position.clone(),
+                        });
+                        false
+                    }
+                    None => true,
+                }
+            });
 
-        balls.retain(|ball| !blackholes.iter().any(|hole| hole.is_spagettified(ball)));
+            for blackhole in blackholes.iter_mut() {
+                blackhole.update(args.dt);
+            }
+
+            // Move goalzones along their paths
+            for goalzone in &mut self.goalzones {
+                goalzone.update(args.dt);
+            }
 
-        // Check if any balls are in the goalzones, removing and adding score
-        // accordingly
-        let goalzones = &self.goalzones;
-        let mut score = self.score;
+            // Check if any balls are in the goalzones, removing and adding score
+            // accordingly
+            let goalzones = &mut self.goalzones;
+            let mut score = self.score;
+            let mut exploding = Vec::new();
 
-        balls.retain(|ball| {
-            match goalzones.iter().any(|zone| zone.reached_goal(ball)) {
-                true => {
-                    score += ball.get_value();
-                    false
+            balls.retain(|ball| {
+                match goalzones.iter_mut().find(|zone| zone.reached_goal(ball)) {
+                    Some(zone) => {
+                        let points = zone.pot(ball);
+                        score += points;
+                        events.push(event::Event::Potted {
+                            ball_type: ball.ball_type.clone(),
+                            position: ball.position.clone(),
+                            points: points,
+                        });
+                        if ball.blast_radius > 0.0 {
+                            exploding.push(ball.clone());
+                        }
+                        false
+                    }
+                    None => true,
                 }
-                false => true,
+            });
+
+            // Potted bombs push the remaining balls away
+            for bomb in &exploding {
+                physics::explode(bomb, balls);
             }
-        });
 
-        self.score = score;
+            self.score = score;
+        }
+
+        // Measure the table after the update
+        self.time += args.dt;
+        self.diagnostics = diagnostics::Diagnostics::measure(self.time,
+                                                             &self.balls,
+                                                             &self.blackholes,
+                                                             self.settings.gravity,
+                                                             self.n_body.as_ref(),
+                                                             friction_loss,
+                                                             collision_loss);
+
+        self.effects.update(args.dt,
+                            &self.balls,
+                            self.settings.stationary_speed,
+                            &self.events,
+                            &self.catalogue);
+
+        self.hud.update(args.dt, &self.events);
+
+        self.step_stats = debug::StepStats {
+            collisions: collisions,
+            teleports: teleports,
+            step_time: debug::seconds(started.elapsed()),
+        };
     }
 
     /**
-     * Returns a collision pair for the earlies collision by going throguh all
-     * poolballs searching for the ball-wall or ball-ball pair with the earlies
-     * collision time
+     * Predicts the next collision from the current velocities, for the debug
+     * view
      */
-    fn get_first_collision_pair(&self) -> CollisionPair {
+    fn predict_collision(&self) -> Option<debug::Prediction> {
+        let pair = match self.get_first_collision_pair(true) {
+            Some(pair) => pair,
+            None => return None,
+        };
 
-        let mut earliest_collision_pair = CollisionPair {
-            first: poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Red),
-            second: None,
-            time: f64::INFINITY,
+        let from = pair.first.position + pair.first.velocity * pair.time;
+        let (to, kind) = match pair.second {
+            Collider::Ball(ref other) => (other.position + other.velocity * pair.time, "ball"),
+            Collider::Wall => (from, "wall"),
+            Collider::Portal(index, entrance) => {
+                (self.wormholes[index].portals[entrance].position, "portal")
+            }
         };
 
+        Some(debug::Prediction {
+            from: from,
+            to: to,
+            kind: kind,
+            time: pair.time,
+        })
+    }
+
+    /**
+     * Integrates the gravity from blackholes, the gravity from other balls and
+     * the friction acting on every ball over the given time using the selected
+     * integrator. Every ball is given the average velocity of its integrated
+     * path, and the difference to the velocity at the end of the path is
+     * returned by ball id together with the energy lost to friction
+     */
+    fn integrate_forces(&mut self, delta_time: f64) -> (HashMap<usize, Vector2<f64>>, f64) {
+        let mut corrections = HashMap::new();
+        let mut friction_loss = 0.0;
+        if delta_time <= 0.0 {
+            return (corrections, friction_loss);
+        }
+
+        // The other balls pull from where they are at the start of the step,
+        // while the pull on each ball follows it through the integrator stages
+        let starts = self.balls.clone();
+        let attraction = self.n_body.as_ref().map(|n_body| n_body.attraction(&starts));
+
+        let integrator = self.integrator.integrator();
+        let blackholes = &self.blackholes;
+        let (gravity, friction) = (self.settings.gravity, self.settings.friction);
+
+        for (index, ball) in self.balls.iter_mut().enumerate() {
+            // Power of the friction force at the start of the step
+            let slowing = physics::calculate_friction(ball, friction);
+            friction_loss -= ball.mass * slowing.dot(&ball.velocity) * delta_time;
+
+            let template = ball.clone();
+            let acceleration = |state: &integrator::State| {
+                let mut probe = template.clone();
+                probe.position = state.position;
+                probe.velocity = state.velocity;
+                let pull = match attraction {
+                    Some(ref attraction) => attraction.acceleration(index, &state.position),
+                    None => Vector2::new(0.0, 0.0),
+                };
+                physics::calculate_gravity(blackholes, &probe, gravity) + pull +
+                physics::calculate_friction(&probe, friction)
+            };
+
+            let start = integrator::State {
+                position: ball.position,
+                velocity: ball.velocity,
+            };
+            let end = integrator.step(start, delta_time, &acceleration);
+
+            let average = (end.position - start.position) / delta_time;
+            corrections.insert(ball.id, end.velocity - average);
+            ball.velocity = average;
+        }
+
+        (corrections, friction_loss)
+    }
+
+    /**
+     * Returns a collision pair for the earlies collision by going throguh all
+     * poolballs searching for the ball-wall, ball-ball or ball-portal pair with
+     * the earlies collision time, if any. Portals are only considered if
+     * `include_portals` is `true`
+     */
+    fn get_first_collision_pair(&self, include_portals: bool) -> Option<CollisionPair> {
+
+        let mut earliest_collision_pair = None;
+        let mut earliest_time = f64::INFINITY;
+
         // Go throguh all poolball
         let mut iter = self.balls.iter();
         while let Some(first) = iter.next() {
@@ -274,24 +890,43 @@ impl Game {
             // Check collision time for ball-wall
             let time_wall = physics::time_to_wall_collision(first);
 
-            if time_wall < earliest_collision_pair.time {
-                earliest_collision_pair = CollisionPair {
+            if time_wall < earliest_time {
+                earliest_time = time_wall;
+                earliest_collision_pair = Some(CollisionPair {
                     first: first.clone(),
-                    second: None,
+                    second: Collider::Wall,
                     time: time_wall,
-                };
+                });
+            }
+
+            // Check collision time for entering each wormhole portal
+            if include_portals {
+                for (index, wormhole) in self.wormholes.iter().enumerate() {
+                    for (entrance, portal) in wormhole.portals.iter().enumerate() {
+                        let time_portal = physics::time_to_portal_entry(first, portal);
+                        if time_portal < earliest_time {
+                            earliest_time = time_portal;
+                            earliest_collision_pair = Some(CollisionPair {
+                                first: first.clone(),
+                                second: Collider::Portal(index, entrance),
+                                time: time_portal,
+                            });
+                        }
+                    }
+                }
             }
 
             // Go through the rest of the cue balls and check the pairs for
             // the collision time
             for second in iter.clone().by_ref() {
                 let time_ball = physics::time_to_ball_ball_collision(first, second);
-                if time_ball < earliest_collision_pair.time {
-                    earliest_collision_pair = CollisionPair {
+                if time_ball < earliest_time {
+                    earliest_time = time_ball;
+                    earliest_collision_pair = Some(CollisionPair {
                         first: first.clone(),
-                        second: Some(second.clone()),
+                        second: Collider::Ball(second.clone()),
                         time: time_ball,
-                    };
+                    });
                 }
             }
 
@@ -306,3 +941,98 @@ impl Game {
 fn white_ball_position(balls: &Vec<poolball::Poolball>) -> Option<usize> {
     balls.iter().position(|elem| elem.ball_type == poolball::BallType::White)
 }
+
+/**
+ * Returns the restitution of poolballs of the given type with the given
+ * settings, at most fully elastic
+ */
+fn restitution(catalogue: &catalogue::Catalogue,
+               ball_type: &poolball::BallType,
+               settings: &settings::Settings)
+               -> f64 {
+    (catalogue.properties(ball_type).restitution * settings.restitution_scale).min(1.0)
+}
+
+/**
+ * Returns the impact of the two balls with the given ids, at the point where
+ * they touch
+ */
+fn impact(balls: &Vec<poolball::Poolball>, first: usize, second: usize) -> Option<event::Event> {
+    let a = balls.iter().find(|ball| ball.id == first);
+    let b = balls.iter().find(|ball| ball.id == second);
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let share = a.radius / (a.radius + b.radius);
+            Some(event::Event::Collided {
+                position: a.position + (b.position - a.position) * share,
+                speed: (a.velocity - b.velocity).norm(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/**
+ * Starts a game of the level with the given source and the built-in ball
+ * catalogue, for tests
+ */
+#[cfg(test)]
+pub fn test_game(source: &str) -> Game {
+    let catalogue = catalogue::Catalogue::builtin();
+    let level = level::Level::parse(source, &catalogue).unwrap();
+    Game::new(level, catalogue)
+}
+
+#[test]
+fn test_predict_wall() {
+    let game = test_game("ball white 0.5 0.5 velocity=1,0");
+    let prediction = game.predict_collision().unwrap();
+    assert_eq!(prediction.kind, "wall");
+    assert!((prediction.time - 0.49).abs() < 1e-9);
+    assert!((prediction.from - Point2::new(0.99, 0.5)).norm() < 1e-9);
+    assert_eq!(prediction.to, prediction.from);
+
+    // Poolballs at rest never collide
+    assert_eq!(test_game("ball white 0.5 0.5").predict_collision(), None);
+}
+
+#[test]
+fn test_predict_ball() {
+    let game = test_game("
+        ball white 0.2 0.5 velocity=1,0
+        ball red 0.6 0.5
+    ");
+    let prediction = game.predict_collision().unwrap();
+    assert_eq!(prediction.kind, "ball");
+    assert!((prediction.time - 0.38).abs() < 1e-9);
+    assert!((prediction.from - Point2::new(0.58, 0.5)).norm() < 1e-9);
+    assert_eq!(prediction.to, Point2::new(0.6, 0.5));
+}
+
+#[test]
+fn test_predict_portal() {
+    let game = test_game("
+        ball white 0.2 0.5 velocity=1,0
+        wormhole 0.5 0.5 0 0.8 0.2 0
+    ");
+
+    // The poolball enters once its center reaches the edge of the portal
+    let prediction = game.predict_collision().unwrap();
+    assert_eq!(prediction.kind, "portal");
+    let radius = game.wormholes[0].portals[0].radius;
+    assert!((prediction.time - (0.3 - radius)).abs() < 1e-9);
+    assert_eq!(prediction.to, Point2::new(0.5, 0.5));
+}
+
+#[test]
+fn test_respawn_clear() {
+    let mut game = test_game("ball red 0.1 0.1");
+    game.score = 10;
+    game.update(&UpdateArgs { dt: 0.01 });
+
+    // The white ball respawns where the red ball lies, and is pushed off it
+    let find = |ball_type| game.balls.iter().find(|ball| ball.ball_type == ball_type).unwrap();
+    let white = find(poolball::BallType::White);
+    let red = find(poolball::BallType::Red);
+    assert!((white.position - red.position).norm() > white.radius + red.radius - 0.0001);
+}
@@ -0,0 +1,390 @@
+use na::{Point2, FloatPoint};
+use std::path::Path;
+use std::u32;
+use blackhole;
+use catalogue;
+use game;
+use goalzone;
+use level;
+use math;
+use parse;
+use poolball;
+use replay;
+use settings;
+use solver;
+
+/**
+ * Settings for generating random levels from a seed: the number of poolballs
+ * racked besides the white one and their types, the number of blackholes and
+ * goalzones, and the most shots a level may take to clear. Every generated
+ * level is checked to be clearable by the solver, trying new layouts up to
+ * the given number of attempts
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Generator {
+    pub seed: u32,
+    pub balls: usize,
+    pub ball_types: Vec<poolball::BallType>,
+    pub blackholes: usize,
+    pub goalzones: usize,
+    pub max_shots: usize,
+    pub attempts: usize,
+}
+
+/**
+ * A generated level and the shots found to clear it
+ */
+pub struct Generated {
+    pub level: level::Level,
+    pub solution: Vec<replay::Shot>,
+    pub seed: u32,
+}
+
+// Places goalzones may be put in: the corners first, then the middles of the
+// sides
+const GOALZONE_SLOTS: [(f64, f64); 8] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0),
+                                         (0.5, 0.0), (0.5, 1.0), (0.0, 0.5), (1.0, 0.5)];
+
+// Range of the reach of blackholes, the mass per squared reach keeping the
+// pull at the edge of the reach the same, and the size of the horizon
+const MIN_REACH: f64 = 0.08;
+const MAX_REACH: f64 = 0.15;
+const MASS_PER_REACH: f64 = 0.8;
+const HORIZON: f64 = 0.005;
+
+// Distances kept from the sides of the table, from goalzones and between
+// racked poolballs
+const SIDE_MARGIN: f64 = 0.05;
+const GOALZONE_MARGIN: f64 = 0.1;
+const RACK_SPACING: f64 = 0.025;
+
+// Tries at placing a single thing before giving up on a layout
+const PLACEMENT_TRIES: usize = 50;
+
+const USAGE: &'static str = "usage: generate <output> [seed=<n>] [balls=<n>] \
+                             [blackholes=<n>] [goalzones=<n>] [shots=<n>] [attempts=<n>]";
+
+impl Generator {
+    /**
+     * Creates a generator of levels with six red and blue poolballs, two
+     * blackholes and the four corner goalzones, clearable in four shots
+     */
+    pub fn new(seed: u32) -> Generator {
+        Generator {
+            seed: seed,
+            balls: 6,
+            ball_types: vec![poolball::BallType::Red, poolball::BallType::Blue],
+            blackholes: 2,
+            goalzones: 4,
+            max_shots: 4,
+            attempts: 20,
+        }
+    }
+
+    /**
+     * Racks the given number of poolballs
+     */
+    pub fn with_balls(mut self, balls: usize) -> Generator {
+        self.balls = balls;
+        self
+    }
+
+    /**
+     * Places the given number of blackholes
+     */
+    pub fn with_blackholes(mut self, blackholes: usize) -> Generator {
+        self.blackholes = blackholes;
+        self
+    }
+
+    /**
+     * Places the given number of goalzones, at most eight
+     */
+    pub fn with_goalzones(mut self, goalzones: usize) -> Generator {
+        self.goalzones = goalzones;
+        self
+    }
+
+    /**
+     * Only accepts levels clearable in the given number of shots
+     */
+    pub fn with_max_shots(mut self, max_shots: usize) -> Generator {
+        self.max_shots = max_shots;
+        self
+    }
+
+    /**
+     * Generates layouts until one is found that the solver can clear with the
+     * given settings, using the given catalogue for the poolballs
+     */
+    pub fn generate(&self,
+                    catalogue: &catalogue::Catalogue,
+                    settings: &settings::Settings)
+                    -> Result<Generated, String> {
+        if self.balls == 0 || self.ball_types.is_empty() {
+            return Err(String::from("levels need poolballs to pot"));
+        }
+        if self.goalzones == 0 || self.goalzones > GOALZONE_SLOTS.len() {
+            return Err(format!("levels take 1 to {} goalzones", GOALZONE_SLOTS.len()));
+        }
+
+        let solver = solver::Solver::new()
+            .with_beam_width(4)
+            .with_max_shots(self.max_shots)
+            .with_directions(36);
+        let mut seed = self.seed;
+        for _ in 0..self.attempts {
+            let level = match self.layout(&mut seed, catalogue) {
+                Some(level) => level,
+                None => continue,
+            };
+            let mut game = game::Game::new(level.clone(), catalogue.clone());
+            game.set_settings(settings);
+            if let Ok(solution) = solver.solve(&game) {
+                return Ok(Generated {
+                    level: level,
+                    solution: solution,
+                    seed: self.seed,
+                });
+            }
+        }
+
+        Err(format!("no playable level found in {} attempts", self.attempts))
+    }
+
+    /**
+     * Lays out a random level: goalzones in some of the corners and sides,
+     * blackholes whose reaches do not overlap each other or the goalzones, the
+     * white ball and a triangular rack of poolballs clear of both. Returns
+     * nothing if something could not be placed
+     */
+    fn layout(&self, seed: &mut u32, catalogue: &catalogue::Catalogue) -> Option<level::Level> {
+        let mut level = level::Level {
+            balls: Vec::new(),
+            blackholes: Vec::new(),
+            goalzones: Vec::new(),
+            wormholes: Vec::new(),
+            n_body: None,
+            settings: Vec::new(),
+        };
+
+        let mut slots = GOALZONE_SLOTS.to_vec();
+        for _ in 0..self.goalzones {
+            let (x, y) = slots.remove(random_index(seed, slots.len()));
+            level.goalzones.push(goalzone::Goalzone::new(Point2::new(x, y)));
+        }
+
+        for _ in 0..self.blackholes {
+            match place_blackhole(&level, seed) {
+                Some(hole) => level.blackholes.push(hole),
+                None => return None,
+            }
+        }
+
+        let white = match (0..PLACEMENT_TRIES)
+            .map(|_| Point2::new(math::random(seed), math::random(seed)))
+            .find(|position| is_clear(&level, position)) {
+            Some(white) => white,
+            None => return None,
+        };
+        level.balls.push(catalogue.create(white, poolball::BallType::White));
+
+        // Rows of the rack grow by one ball, pointing left or right
+        let rack = match (0..PLACEMENT_TRIES)
+            .map(|_| {
+                let (x, y) = (math::random(seed), math::random(seed));
+                let apex = Point2::new(0.2 + 0.6 * x, 0.2 + 0.6 * y);
+                let direction = if math::random(seed) < 0.5 { -1.0 } else { 1.0 };
+                rack_positions(apex, direction, self.balls)
+            })
+            .find(|positions| positions.iter().all(|position| is_clear(&level, position))) {
+            Some(rack) => rack,
+            None => return None,
+        };
+        for position in rack {
+            let ball_type = self.ball_types[random_index(seed, self.ball_types.len())].clone();
+            level.balls.push(catalogue.create(position, ball_type));
+        }
+
+        Some(level)
+    }
+}
+
+impl Generated {
+    /**
+     * Returns the level file of the generated level, noting how it was made
+     */
+    pub fn to_source(&self) -> String {
+        format!("# Generated from seed {}, cleared in {} shots\n\n{}",
+                self.seed,
+                self.solution.len(),
+                self.level.to_source())
+    }
+
+    /**
+     * Writes the level file of the generated level to the given path
+     */
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        level::write_source(path, &self.to_source())
+    }
+}
+
+/**
+ * Runs the generate command with the given arguments, writing the generated
+ * level and returning the number of shots it takes
+ */
+pub fn run(arguments: &[String],
+           catalogue: catalogue::Catalogue,
+           settings: &settings::Settings)
+           -> Result<usize, String> {
+    if arguments.is_empty() {
+        return Err(String::from(USAGE));
+    }
+    let output = Path::new(&arguments[0]);
+
+    let mut generator = Generator::new(1);
+    for option in &arguments[1..] {
+        let mut parts = option.splitn(2, '=');
+        let (key, value) = (parts.next().unwrap(), parts.next().unwrap_or(""));
+        if key == "seed" {
+            generator.seed = try!(value.parse()
+                .map_err(|_| format!("'{}' is not a seed from 0 to {}", value, u32::MAX)));
+            continue;
+        }
+        let number = try!(parse::parse_integer(value));
+        if number < 0 {
+            return Err(format!("'{}' is not a positive integer", value));
+        }
+        let number = number as usize;
+        match key {
+            "balls" => generator = generator.with_balls(number),
+            "blackholes" => generator = generator.with_blackholes(number),
+            "goalzones" => generator = generator.with_goalzones(number),
+            "shots" => generator = generator.with_max_shots(number),
+            "attempts" => generator.attempts = number,
+            _ => return Err(format!("unknown option '{}'\n{}", key, USAGE)),
+        }
+    }
+
+    let generated = try!(generator.generate(&catalogue, settings));
+    try!(generated.save(output));
+    Ok(generated.solution.len())
+}
+
+/**
+ * Places a blackhole at a random position of the level, with a random reach
+ * overlapping neither the reach of the other blackholes nor the goalzones
+ */
+fn place_blackhole(level: &level::Level, seed: &mut u32) -> Option<blackhole::Blackhole> {
+    for _ in 0..PLACEMENT_TRIES {
+        let position = Point2::new(0.2 + 0.6 * math::random(seed), 0.2 + 0.6 * math::random(seed));
+        let reach = MIN_REACH + (MAX_REACH - MIN_REACH) * math::random(seed);
+        let apart = level.blackholes
+            .iter()
+            .all(|other| other.position.distance(&position) > other.reach + reach);
+        let clear = level.goalzones
+            .iter()
+            .all(|zone| zone.position().distance(&position) > reach + GOALZONE_MARGIN);
+        if apart && clear {
+            let mass = MASS_PER_REACH * reach * reach;
+            return Some(blackhole::Blackhole::new(position, mass, HORIZON, reach));
+        }
+    }
+    None
+}
+
+/**
+ * Returns the positions of a triangular rack of the given number of poolballs
+ * with its tip at the given apex, the rows following in the given direction
+ * along the x axis
+ */
+fn rack_positions(apex: Point2<f64>, direction: f64, count: usize) -> Vec<Point2<f64>> {
+    let row_distance = RACK_SPACING * 3.0_f64.sqrt() / 2.0;
+    let mut positions = Vec::new();
+    let mut row = 0;
+    while positions.len() < count {
+        for column in 0..row + 1 {
+            if positions.len() == count {
+                break;
+            }
+            let offset = (column as f64 - row as f64 / 2.0) * RACK_SPACING;
+            positions.push(Point2::new(apex.x + direction * row as f64 * row_distance,
+                                       apex.y + offset));
+        }
+        row += 1;
+    }
+    positions
+}
+
+/**
+ * Returns `true` if a poolball may be put at the given position of the level:
+ * on the table, outside the reach of every blackhole, away from the goalzones
+ * and clear of the poolballs already placed
+ */
+fn is_clear(level: &level::Level, position: &Point2<f64>) -> bool {
+    let on_table = position.x >= SIDE_MARGIN && position.x <= 1.0 - SIDE_MARGIN &&
+                   position.y >= SIDE_MARGIN && position.y <= 1.0 - SIDE_MARGIN;
+    on_table &&
+    level.blackholes.iter().all(|hole| hole.position.distance(position) > hole.reach) &&
+    level.goalzones.iter().all(|zone| zone.position().distance(position) > GOALZONE_MARGIN) &&
+    level.balls.iter().all(|ball| ball.position.distance(position) >= RACK_SPACING)
+}
+
+/**
+ * Returns a random index into a list of the given length
+ */
+fn random_index(seed: &mut u32, length: usize) -> usize {
+    ((math::random(seed) * length as f64) as usize).min(length - 1)
+}
+
+#[test]
+fn test_layout() {
+    let catalogue = catalogue::Catalogue::builtin();
+    let generator = Generator::new(7).with_blackholes(3);
+    let mut seed = generator.seed;
+    let level = generator.layout(&mut seed, &catalogue).unwrap();
+
+    assert_eq!(level.balls.len(), 7);
+    assert_eq!(level.goalzones.len(), 4);
+    assert_eq!(level.blackholes.len(), 3);
+    for (i, hole) in level.blackholes.iter().enumerate() {
+        for other in &level.blackholes[i + 1..] {
+            assert!(hole.position.distance(&other.position) > hole.reach + other.reach);
+        }
+    }
+
+    // The same seed gives the same level, which is written as a level file
+    let mut again = generator.seed;
+    let source = level.to_source();
+    assert_eq!(generator.layout(&mut again, &catalogue).unwrap().to_source(), source);
+    assert_eq!(level::Level::parse(&source, &catalogue).unwrap().to_source(), source);
+}
+
+#[test]
+fn test_rack_positions() {
+    let positions = rack_positions(Point2::new(0.5, 0.5), 1.0, 4);
+    assert_eq!(positions.len(), 4);
+    assert_eq!(positions[0], Point2::new(0.5, 0.5));
+    assert!(positions[1].x > 0.5 && positions[1].y < 0.5 && positions[2].y > 0.5);
+    assert!((positions[1].distance(&positions[2]) - RACK_SPACING).abs() < 0.000001);
+}
+
+#[test]
+fn test_generate() {
+    let catalogue = catalogue::Catalogue::builtin();
+    let generated = Generator::new(3)
+        .with_balls(1)
+        .with_blackholes(1)
+        .with_max_shots(2)
+        .generate(&catalogue, &settings::Settings::new())
+        .unwrap();
+    assert!(!generated.solution.is_empty() && generated.solution.len() <= 2);
+    assert!(generated.to_source().starts_with("# Generated from seed 3"));
+}
+
+#[test]
+fn test_seed_range() {
+    let arguments = vec![String::from("levels/unused.lvl"), String::from("seed=4294967296")];
+    let result = run(&arguments, catalogue::Catalogue::builtin(), &settings::Settings::new());
+    assert_eq!(result, Err(String::from("'4294967296' is not a seed from 0 to 4294967295")));
+}
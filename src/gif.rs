@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use canvas;
+
+// Bits per palette index, and the largest code of the LZW compression
+const MIN_CODE_SIZE: u8 = 8;
+const MAX_CODE: u16 = 4095;
+
+/**
+ * Writes canvases as the frames of a looping animated GIF. Colors are reduced
+ * to a fixed palette of a color cube and grays
+ */
+pub struct GifWriter {
+    writer: BufWriter<File>,
+    width: usize,
+    height: usize,
+    delay: u16,
+}
+
+impl GifWriter {
+    /**
+     * Creates the GIF file at the given path for frames of the given size,
+     * each shown for the given time in hundredths of a second
+     */
+    pub fn create(path: &Path, width: usize, height: usize, delay: u16) -> io::Result<GifWriter> {
+        let mut writer = BufWriter::new(try!(File::create(path)));
+
+        try!(writer.write_all(b"GIF89a"));
+        try!(writer.write_all(&u16_bytes(width as u16)));
+        try!(writer.write_all(&u16_bytes(height as u16)));
+        // Global palette of 256 colors, no background color or aspect ratio
+        try!(writer.write_all(&[0xF7, 0, 0]));
+        try!(writer.write_all(&palette()));
+
+        // Loop the animation forever
+        try!(writer.write_all(&[0x21, 0xFF, 0x0B]));
+        try!(writer.write_all(b"NETSCAPE2.0"));
+        try!(writer.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00]));
+
+        Ok(GifWriter {
+            writer: writer,
+            width: width,
+            height: height,
+            delay: delay,
+        })
+    }
+
+    /**
+     * Appends the given canvas as the next frame. The canvas must have the
+     * size of the animation
+     */
+    pub fn add_frame(&mut self, canvas: &canvas::Canvas) -> io::Result<()> {
+        assert!(canvas.width == self.width && canvas.height == self.height);
+
+        // Frame delay, then the image covering the whole animation
+        try!(self.writer.write_all(&[0x21, 0xF9, 0x04, 0x00]));
+        try!(self.writer.write_all(&u16_bytes(self.delay)));
+        try!(self.writer.write_all(&[0x00, 0x00, 0x2C, 0, 0, 0, 0]));
+        try!(self.writer.write_all(&u16_bytes(self.width as u16)));
+        try!(self.writer.write_all(&u16_bytes(self.height as u16)));
+        try!(self.writer.write_all(&[0x00, MIN_CODE_SIZE]));
+
+        let indices: Vec<u8> = canvas.pixels.chunks(4).map(palette_index).collect();
+        for block in lzw_encode(&indices).chunks(255) {
+            try!(self.writer.write_all(&[block.len() as u8]));
+            try!(self.writer.write_all(block));
+        }
+        self.writer.write_all(&[0x00])
+    }
+
+    /**
+     * Ends the animation, flushing it to the file
+     */
+    pub fn finish(mut self) -> io::Result<()> {
+        try!(self.writer.write_all(&[0x3B]));
+        self.writer.flush()
+    }
+}
+
+/**
+ * Returns the given number in little endian byte order
+ */
+fn u16_bytes(value: u16) -> [u8; 2] {
+    [value as u8, (value >> 8) as u8]
+}
+
+/**
+ * Returns the palette of a 6x6x6 color cube followed by 40 grays, as RGB
+ * triplets
+ */
+fn palette() -> Vec<u8> {
+    let mut colors = Vec::with_capacity(256 * 3);
+    for red in 0..6 {
+        for green in 0..6 {
+            for blue in 0..6 {
+                colors.extend_from_slice(&[red * 51, green * 51, blue * 51]);
+            }
+        }
+    }
+    for gray in 0..40 {
+        let level = (gray * 255 / 39) as u8;
+        colors.extend_from_slice(&[level, level, level]);
+    }
+    colors
+}
+
+/**
+ * Returns the index of the palette color closest to the given RGBA pixel,
+ * drawn over black
+ */
+fn palette_index(pixel: &[u8]) -> u8 {
+    let alpha = pixel[3] as u32;
+    let channels: Vec<u32> = pixel[..3].iter().map(|&value| value as u32 * alpha / 255).collect();
+
+    let cube: Vec<u32> = channels.iter().map(|&value| (value + 25) / 51).collect();
+    let cube_error = channels.iter()
+        .zip(cube.iter())
+        .fold(0, |sum, (&value, &step)| sum + square_difference(value, step * 51));
+
+    let average = channels.iter().fold(0, |sum, &value| sum + value) / 3;
+    let gray = (average * 39 + 127) / 255;
+    let gray_error = channels.iter()
+        .fold(0, |sum, &value| sum + square_difference(value, gray * 255 / 39));
+
+    if gray_error < cube_error {
+        (216 + gray) as u8
+    } else {
+        (cube[0] * 36 + cube[1] * 6 + cube[2]) as u8
+    }
+}
+
+fn square_difference(a: u32, b: u32) -> u32 {
+    let difference = if a > b { a - b } else { b - a };
+    difference * difference
+}
+
+/**
+ * Compresses the given palette indices with the variable code length LZW
+ * compression used by GIF, starting over whenever the code table is full
+ */
+fn lzw_encode(indices: &[u8]) -> Vec<u8> {
+    let clear = 1_u16 << MIN_CODE_SIZE;
+    let end = clear + 1;
+
+    let mut output = BitWriter::new();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end + 1;
+    let mut code_size = MIN_CODE_SIZE + 1;
+
+    output.write(clear, code_size);
+
+    let mut iter = indices.iter();
+    let mut prefix = match iter.next() {
+        Some(&index) => index as u16,
+        None => {
+            output.write(end, code_size);
+            return output.finish();
+        }
+    };
+
+    for &index in iter {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+
+        output.write(prefix, code_size);
+        if next_code <= MAX_CODE {
+            table.insert((prefix, index), next_code);
+            next_code += 1;
+            if next_code > (1 << code_size) && code_size < 12 {
+                code_size += 1;
+            }
+        } else {
+            output.write(clear, code_size);
+            table.clear();
+            next_code = end + 1;
+            code_size = MIN_CODE_SIZE + 1;
+        }
+        prefix = index as u16;
+    }
+
+    output.write(prefix, code_size);
+    output.write(end, code_size);
+    output.finish()
+}
+
+/**
+ * Packs codes of varying length into bytes, least significant bit first
+ */
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            bytes: Vec::new(),
+            buffer: 0,
+            bits: 0,
+        }
+    }
+
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/**
+ * Decompresses GIF LZW data, for checking the encoder
+ */
+#[cfg(test)]
+fn lzw_decode(data: &[u8]) -> Vec<u8> {
+    let clear = 1_usize << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let initial: Vec<Vec<u8>> = (0..end + 1).map(|code| vec![code as u8]).collect();
+
+    let mut table = initial.clone();
+    let mut code_size = MIN_CODE_SIZE as usize + 1;
+    let mut previous: Option<Vec<u8>> = None;
+    let mut output = Vec::new();
+    let (mut buffer, mut bits, mut position) = (0_usize, 0, 0);
+
+    loop {
+        while bits < code_size {
+            buffer |= (data[position] as usize) << bits;
+            position += 1;
+            bits += 8;
+        }
+        let code = buffer & ((1 << code_size) - 1);
+        buffer >>= code_size;
+        bits -= code_size;
+
+        if code == clear {
+            table = initial.clone();
+            code_size = MIN_CODE_SIZE as usize + 1;
+            previous = None;
+            continue;
+        }
+        if code == end {
+            return output;
+        }
+
+        let entry = match (table.get(code).cloned(), previous.clone()) {
+            (Some(entry), _) => entry,
+            (None, Some(mut entry)) => {
+                let first = entry[0];
+                entry.push(first);
+                entry
+            }
+            (None, None) => panic!("invalid code {}", code),
+        };
+        output.extend_from_slice(&entry);
+
+        if let Some(mut added) = previous {
+            added.push(entry[0]);
+            table.push(added);
+            if table.len() == 1 << code_size && code_size < 12 {
+                code_size += 1;
+            }
+        }
+        previous = Some(entry);
+    }
+}
+
+#[test]
+fn test_lzw_round_trip() {
+    // Long and varied enough to fill the code table several times
+    let mut state = 12345_u32;
+    let indices: Vec<u8> = (0..50000)
+        .map(|i| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            if i % 3 == 0 { 7 } else { (state >> 16) as u8 }
+        })
+        .collect();
+    assert_eq!(lzw_decode(&lzw_encode(&indices)), indices);
+
+    let repeated = vec![3; 10000];
+    assert_eq!(lzw_decode(&lzw_encode(&repeated)), repeated);
+    assert_eq!(lzw_decode(&lzw_encode(&[])), Vec::<u8>::new());
+}
+
+#[test]
+fn test_palette_index() {
+    assert_eq!(palette().len(), 256 * 3);
+    assert_eq!(palette_index(&[0, 0, 0, 255]), 0);
+    assert_eq!(palette_index(&[255, 0, 0, 255]), 5 * 36);
+    assert_eq!(palette_index(&[0, 0, 255, 255]), 5);
+    assert_eq!(palette_index(&[255, 255, 255, 128]), 216 + 20);
+    assert_eq!(palette_index(&[255, 0, 0, 0]), 0);
+}
@@ -1,17 +1,25 @@
-use na::Point2;
-use piston::input::RenderArgs;
-use opengl_graphics::GlGraphics;
-use graphics::Transformed;
-use graphics::ellipse;
+use na::{Point2, Vector2};
 use na::FloatPoint;
 use poolball;
+use catalogue;
+use renderer;
 
 /**
  * Implements a basic goalzone to be used in each corner of the playing field.
+ * Goalzones may be restricted to only accept certain ball types, move along a
+ * path, multiply the score of potted balls and close after a number of pots
  */
+#[derive(Clone, Debug, PartialEq)]
 pub struct Goalzone {
     position: Point2<f64>,
     radius: f64,
+    accepts: Vec<poolball::BallType>,
+    path: Vec<Point2<f64>>,
+    speed: f64,
+    travelled: f64,
+    multiplier: i32,
+    capacity: Option<u32>,
+    pots: u32,
 }
 const RADIUS: f64 = 0.05;
 
@@ -23,37 +31,224 @@ impl Goalzone {
         Goalzone {
             position: position,
             radius: RADIUS,
+            accepts: Vec::new(),
+            path: Vec::new(),
+            speed: 0.0,
+            travelled: 0.0,
+            multiplier: 1,
+            capacity: None,
+            pots: 0,
         }
     }
 
     /**
-     * Returns `true` if the given poolball is inside the goalzone
+     * Restricts the goalzone to only accept the given ball types. An empty list
+     * accepts any ball type
+     */
+    pub fn with_accepts(mut self, accepts: Vec<poolball::BallType>) -> Goalzone {
+        self.accepts = accepts;
+        self
+    }
+
+    /**
+     * Makes the goalzone move along the closed path through the given points
+     * with the given speed, starting at the first point
+     */
+    pub fn with_path(mut self, path: Vec<Point2<f64>>, speed: f64) -> Goalzone {
+        if let Some(start) = path.first() {
+            self.position = start.clone();
+        }
+        self.path = path;
+        self.speed = speed;
+        self
+    }
+
+    /**
+     * Multiplies the score of every ball potted in the goalzone
+     */
+    pub fn with_multiplier(mut self, multiplier: i32) -> Goalzone {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /**
+     * Closes the goalzone after the given number of balls have been potted
+     */
+    pub fn with_capacity(mut self, capacity: u32) -> Goalzone {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /**
+     * Returns the current position of the goalzone
+     */
+    pub fn position(&self) -> Point2<f64> {
+        self.position
+    }
+
+    /**
+     * Changes the radius within which poolballs are potted
+     */
+    pub fn set_radius(&mut self, radius: f64) {
+        self.radius = radius;
+    }
+
+    /**
+     * Moves the goalzone along with its path by the given offset
+     */
+    pub fn translate(&mut self, offset: Vector2<f64>) {
+        self.position = self.position + offset;
+        for point in &mut self.path {
+            *point = *point + offset;
+        }
+    }
+
+    /**
+     * Returns the line describing the goalzone in a level file, as it was
+     * before any balls were potted or it moved along its path
+     */
+    pub fn to_source(&self) -> String {
+        let start = self.path.first().cloned().unwrap_or(self.position);
+        let mut source = format!("goalzone {} {}", start.x, start.y);
+        if !self.accepts.is_empty() {
+            let names: Vec<&str> = self.accepts.iter().map(|ball_type| ball_type.name()).collect();
+            source.push_str(&format!(" accepts={}", names.join(",")));
+        }
+        if self.multiplier != 1 {
+            source.push_str(&format!(" multiplier={}", self.multiplier));
+        }
+        if let Some(capacity) = self.capacity {
+            source.push_str(&format!(" capacity={}", capacity));
+        }
+        if !self.path.is_empty() {
+            let points: Vec<String> =
+                self.path.iter().map(|point| format!("{},{}", point.x, point.y)).collect();
+            source.push_str(&format!(" path={} speed={}", points.join(";"), self.speed));
+        }
+        source
+    }
+
+    /**
+     * Returns `true` if the goalzone still accepts balls
+     */
+    pub fn is_open(&self) -> bool {
+        match self.capacity {
+            Some(capacity) => self.pots < capacity,
+            None => true,
+        }
+    }
+
+    /**
+     * Returns `true` if the goalzone is open and accepts the type of the given
+     * poolball
+     */
+    pub fn accepts(&self, ball: &poolball::Poolball) -> bool {
+        self.is_open() && (self.accepts.is_empty() || self.accepts.contains(&ball.ball_type))
+    }
+
+    /**
+     * Returns `true` if the given poolball is inside the goalzone and may be
+     * potted in it
      */
     pub fn reached_goal(&self, ball: &poolball::Poolball) -> bool {
-        self.position.distance(&ball.position) <= self.radius + ball.radius
+        self.accepts(ball) && self.position.distance(&ball.position) <= self.radius + ball.radius
     }
 
     /**
-     * Renders itself using the given graphics
+     * Registers the given poolball as potted, returning the score it is worth
      */
-    pub fn render(&self, args: &RenderArgs, gl: &mut GlGraphics) {
+    pub fn pot(&mut self, ball: &poolball::Poolball) -> i32 {
+        self.pots += 1;
+        ball.get_value() * self.multiplier
+    }
+
+    /**
+     * Moves the goalzone along its path, if any, for the given time
+     */
+    pub fn update(&mut self, delta_time: f64) {
+        if self.path.len() < 2 {
+            return;
+        }
+
+        // Length of the closed path, including the way back to the start
+        let length = (0..self.path.len())
+            .map(|i| self.path[i].distance(&self.path[(i + 1) % self.path.len()]))
+            .fold(0.0, |sum, segment| sum + segment);
+        if length == 0.0 {
+            return;
+        }
+
+        self.travelled = (self.travelled + self.speed * delta_time) % length;
+
+        // Find the segment we are on and interpolate the position along it
+        let mut left = self.travelled;
+        for i in 0..self.path.len() {
+            let ref from = self.path[i];
+            let ref to = self.path[(i + 1) % self.path.len()];
+            let segment = from.distance(to);
+            if left <= segment && segment > 0.0 {
+                self.position = *from + (*to - *from) * (left / segment);
+                return;
+            }
+            left -= segment;
+        }
+    }
+
+    /**
+     * Renders itself using the given renderer. Restricted goalzones are drawn
+     * in the color of the accepted ball types, multipliers as extra rings,
+     * remaining capacity as dots and closed goalzones in gray. Ball type colors
+     * are looked up in the given catalogue
+     */
+    pub fn render(&self, renderer: &mut renderer::Renderer, catalogue: &catalogue::Catalogue) {
 
         const GREEN: [f32; 4] = [0.0, 1.0, 0.0, 1.0];
+        const GRAY: [f32; 4] = [0.4, 0.4, 0.4, 1.0];
+        const DIM_GREEN: [f32; 4] = [0.0, 1.0, 0.0, 0.3];
+        const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
 
-        let ellipse = ellipse::Ellipse::new(GREEN);
+        let color = if !self.is_open() {
+            GRAY
+        } else if self.accepts.len() == 1 {
+            catalogue.properties(&self.accepts[0]).color
+        } else {
+            GREEN
+        };
 
-        gl.draw(args.viewport(), |c, gl| {
+        // Draw the path of moving goal zones
+        for i in 0..self.path.len() {
+            let from = self.path[i];
+            let to = self.path[(i + 1) % self.path.len()];
+            renderer.line(DIM_GREEN, from, to, 0.002);
+        }
 
-            let trans = c.transform
-                .scale(args.width as f64, args.height as f64)
-                .trans(self.position.x, self.position.y);
+        // Draw the goal zone
+        renderer.circle(color, self.position, self.radius);
 
-            // Draw the goal zone
-            ellipse.draw(ellipse::circle(0.0, 0.0, self.radius),
-                         &c.draw_state,
-                         trans,
-                         gl);
-        });
+        // Zones accepting several types get a ring per accepted type
+        if self.accepts.len() > 1 {
+            for (i, ball_type) in self.accepts.iter().enumerate() {
+                let color = catalogue.properties(ball_type).color;
+                let radius = self.radius * (1.0 - 0.15 * (i + 1) as f64);
+                renderer.ring(color, self.position, radius, 0.004);
+            }
+        }
+
+        // Draw a white ring outside the zone for each step of multiplier
+        for i in 1..self.multiplier {
+            let radius = self.radius + 0.004 * i as f64;
+            renderer.ring(WHITE, self.position, radius, 0.002);
+        }
+
+        // Draw one dot for each pot left before the zone closes
+        if let Some(capacity) = self.capacity {
+            let left = capacity.saturating_sub(self.pots);
+            for i in 0..left {
+                let x = (i as f64 - (left as f64 - 1.0) / 2.0) * 0.01;
+                let dot = Point2::new(self.position.x + x, self.position.y);
+                renderer.circle(WHITE, dot, 0.003);
+            }
+        }
     }
 }
 
@@ -68,3 +263,44 @@ fn test_reach() {
     let goalzone = Goalzone::new(Point2::new(10.0, 10.0));
     assert!(!goalzone.reached_goal(&ball));
 }
+
+#[test]
+fn test_accepts() {
+    let red = poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Red);
+    let blue = poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Blue);
+    let goalzone = Goalzone::new(Point2::new(0.0, 0.0))
+        .with_accepts(vec![poolball::BallType::Blue]);
+
+    assert!(!goalzone.reached_goal(&red));
+    assert!(goalzone.reached_goal(&blue));
+}
+
+#[test]
+fn test_multiplier_and_capacity() {
+    let ball = poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Red);
+    let mut goalzone = Goalzone::new(Point2::new(0.0, 0.0))
+        .with_multiplier(3)
+        .with_capacity(2);
+
+    assert_eq!(goalzone.pot(&ball), 30);
+    assert!(goalzone.reached_goal(&ball));
+    assert_eq!(goalzone.pot(&ball), 30);
+    assert!(!goalzone.is_open());
+    assert!(!goalzone.reached_goal(&ball));
+}
+
+#[test]
+fn test_moving() {
+    let path = vec![Point2::new(0.0, 0.0), Point2::new(1.0, 0.0)];
+    let mut goalzone = Goalzone::new(Point2::new(0.5, 0.5)).with_path(path, 0.5);
+    assert_eq!(goalzone.position, Point2::new(0.0, 0.0));
+
+    goalzone.update(1.0);
+    assert_eq!(goalzone.position, Point2::new(0.5, 0.0));
+
+    // Turns around at the end of the path and heads back to the start
+    goalzone.update(3.0);
+    assert_eq!(goalzone.position, Point2::new(0.0, 0.0));
+    goalzone.update(2.5);
+    assert_eq!(goalzone.position, Point2::new(0.75, 0.0));
+}
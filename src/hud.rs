@@ -0,0 +1,229 @@
+use na::Point2;
+use std::iter;
+use renderer::{Color, Renderer};
+use event;
+use poolball;
+
+/**
+ * The state of the game shown by the HUD
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Status {
+    pub score: i32,
+    pub shots: usize,
+    pub lives: Option<u32>,
+    pub remaining: Vec<(poolball::BallType, usize, Color)>,
+    pub power: Option<f64>,
+}
+
+/**
+ * The heads-up display drawn over the table: the score, shots taken, lives
+ * left, the poolballs remaining by type, the power of the shot being aimed and
+ * short messages about what just happened
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hud {
+    messages: Vec<Message>,
+}
+
+/**
+ * A message shown for a short while, fading out at the end
+ */
+#[derive(Clone, Debug, PartialEq)]
+struct Message {
+    text: String,
+    color: Color,
+    age: f64,
+}
+
+// Time in seconds a message is shown, the last part of it fading out
+const MESSAGE_TIME: f64 = 1.5;
+const FADE_TIME: f64 = 0.5;
+
+// Messages shown at once, older ones being dropped
+const MAX_MESSAGES: usize = 4;
+
+// Number of bars in the power meter
+const POWER_BARS: usize = 20;
+
+const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+const GRAY: Color = [0.4, 0.4, 0.4, 1.0];
+const GREEN: Color = [0.3, 1.0, 0.3, 1.0];
+const RED: Color = [1.0, 0.3, 0.3, 1.0];
+const ORANGE: Color = [1.0, 0.5, 0.0, 1.0];
+
+impl Hud {
+    /**
+     * Creates a HUD without any messages
+     */
+    pub fn new() -> Hud {
+        Hud { messages: Vec::new() }
+    }
+
+    /**
+     * Ages the messages by the given time and adds messages for the events of
+     * the latest update
+     */
+    pub fn update(&mut self, delta_time: f64, events: &[event::Event]) {
+        for message in &mut self.messages {
+            message.age += delta_time;
+        }
+        self.messages.retain(|message| message.age < MESSAGE_TIME);
+
+        for event in events {
+            let (text, color) = match *event {
+                event::Event::Potted { ball_type: poolball::BallType::White, .. } => {
+                    (String::from("Foul!"), RED)
+                }
+                event::Event::Potted { points, .. } if points < 0 => {
+                    (format!("{}", points), RED)
+                }
+                event::Event::Potted { points, .. } => (format!("+{}", points), GREEN),
+                event::Event::Swallowed { .. } => {
+                    (String::from("Swallowed by blackhole"), ORANGE)
+                }
+                _ => continue,
+            };
+            self.messages.push(Message {
+                text: text,
+                color: color,
+                age: 0.0,
+            });
+        }
+
+        if self.messages.len() > MAX_MESSAGES {
+            let excess = self.messages.len() - MAX_MESSAGES;
+            self.messages.drain(..excess);
+        }
+    }
+
+    /**
+     * Renders the HUD for the given state of the game
+     */
+    pub fn render(&self, status: &Status, renderer: &mut Renderer) {
+        let score = format!("Score: {}", status.score);
+        renderer.text(WHITE, 18, Point2::new(0.46, 0.02), &score);
+
+        // Shots, lives and the poolballs left in the top right corner
+        let lives = match status.lives {
+            Some(lives) => format!("{}", lives),
+            None => String::from("unlimited"),
+        };
+        let counts = format!("Shots: {}  Lives: {}", status.shots, lives);
+        renderer.text(WHITE, 12, Point2::new(0.78, 0.025), &counts);
+        for (i, &(ref ball_type, count, color)) in status.remaining.iter().enumerate() {
+            let line = format!("{}: {}", ball_type.name(), count);
+            let position = Point2::new(0.78, 0.045 + 0.0175 * i as f64);
+            renderer.text([color[0], color[1], color[2], 1.0], 12, position, &line);
+        }
+
+        // The meter is drawn as the same bars twice, so the filled part lines
+        // up with the empty one whatever the font
+        if let Some(power) = status.power {
+            let (filled, track) = power_bars(power);
+            let label = format!("Power: {:3.0}%", power * 100.0);
+            renderer.text(WHITE, 14, Point2::new(0.3, 0.96), &label);
+            renderer.text(GRAY, 14, Point2::new(0.43, 0.96), &track);
+            renderer.text(power_color(power), 14, Point2::new(0.43, 0.96), &filled);
+        }
+
+        // Newest message at the top
+        for (i, message) in self.messages.iter().rev().enumerate() {
+            let fade = ((MESSAGE_TIME - message.age) / FADE_TIME).min(1.0) as f32;
+            let color = [message.color[0], message.color[1], message.color[2], fade];
+            let position = Point2::new(0.44, 0.1 + 0.035 * i as f64);
+            renderer.text(color, 16, position, &message.text);
+        }
+    }
+}
+
+/**
+ * Counts the poolballs other than the white one by type, in alphabetical
+ * order of the types
+ */
+pub fn remaining(balls: &[poolball::Poolball]) -> Vec<(poolball::BallType, usize, Color)> {
+    let mut counts: Vec<(poolball::BallType, usize, Color)> = Vec::new();
+    for ball in balls {
+        if ball.ball_type == poolball::BallType::White {
+            continue;
+        }
+        match counts.iter().position(|count| count.0 == ball.ball_type) {
+            Some(index) => counts[index].1 += 1,
+            None => counts.push((ball.ball_type.clone(), 1, ball.color)),
+        }
+    }
+    counts.sort_by(|a, b| a.0.name().cmp(b.0.name()));
+    counts
+}
+
+/**
+ * Returns the filled bars of the power meter for the given power between 0
+ * and 1, and the bars of the whole meter
+ */
+fn power_bars(power: f64) -> (String, String) {
+    let filled = (power.max(0.0).min(1.0) * POWER_BARS as f64).round() as usize;
+    (iter::repeat('|').take(filled).collect(), iter::repeat('|').take(POWER_BARS).collect())
+}
+
+/**
+ * Returns the colour of the power meter, going from green to red
+ */
+fn power_color(power: f64) -> Color {
+    let power = power.max(0.0).min(1.0) as f32;
+    [power, 1.0 - power, 0.0, 1.0]
+}
+
+#[test]
+fn test_messages() {
+    let mut hud = Hud::new();
+    let events = vec![event::Event::Potted {
+                          ball_type: poolball::BallType::Blue,
+                          position: Point2::new(0.5, 0.5),
+                          points: 30,
+                      },
+                      event::Event::Potted {
+                          ball_type: poolball::BallType::White,
+                          position: Point2::new(0.5, 0.5),
+                          points: -100,
+                      },
+                      event::Event::Swallowed {
+                          ball_type: poolball::BallType::Red,
+                          position: Point2::new(0.5, 0.5),
+                      },
+                      event::Event::Teleported {
+                          ball_type: poolball::BallType::Red,
+                          from: Point2::new(0.5, 0.5),
+                          to: Point2::new(0.1, 0.1),
+                      }];
+    hud.update(0.0, &events);
+    let texts: Vec<&str> = hud.messages.iter().map(|message| message.text.as_str()).collect();
+    assert_eq!(texts, vec!["+30", "Foul!", "Swallowed by blackhole"]);
+
+    // Messages disappear after a while, and only the latest few are kept
+    hud.update(MESSAGE_TIME, &[]);
+    assert!(hud.messages.is_empty());
+    hud.update(0.0, &[events.clone(), events.clone()].concat());
+    assert_eq!(hud.messages.len(), MAX_MESSAGES);
+    assert_eq!(hud.messages[MAX_MESSAGES - 1].text, "Swallowed by blackhole");
+}
+
+#[test]
+fn test_remaining() {
+    let balls = vec![poolball::Poolball::new(Point2::new(0.1, 0.1), poolball::BallType::White),
+                     poolball::Poolball::new(Point2::new(0.2, 0.1), poolball::BallType::Red),
+                     poolball::Poolball::new(Point2::new(0.3, 0.1), poolball::BallType::Blue),
+                     poolball::Poolball::new(Point2::new(0.4, 0.1), poolball::BallType::Red)];
+    let counts: Vec<(poolball::BallType, usize)> = remaining(&balls)
+        .into_iter()
+        .map(|(ball_type, count, _)| (ball_type, count))
+        .collect();
+    assert_eq!(counts,
+               vec![(poolball::BallType::Blue, 1), (poolball::BallType::Red, 2)]);
+}
+
+#[test]
+fn test_power_bars() {
+    assert_eq!(power_bars(0.5), (String::from("||||||||||"), String::from("||||||||||||||||||||")));
+    assert_eq!(power_bars(2.0).0.len(), POWER_BARS);
+    assert_eq!(power_bars(0.0).0, "");
+}
@@ -0,0 +1,270 @@
+use na::{Point2, Vector2};
+use std::str::FromStr;
+
+/**
+ * The position and velocity of a body being integrated
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct State {
+    pub position: Point2<f64>,
+    pub velocity: Vector2<f64>,
+}
+
+/**
+ * A numerical method advancing a state through time given the acceleration
+ * at any state
+ */
+pub trait Integrator {
+    /**
+     * Returns the state after the given time, starting from the given state
+     */
+    fn step(&self, state: State, delta_time: f64, acceleration: &Fn(&State) -> Vector2<f64>)
+            -> State;
+}
+
+/**
+ * The available integrators, used for selecting one at runtime
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    ExplicitEuler,
+    SemiImplicitEuler,
+    VelocityVerlet,
+    RungeKutta4,
+}
+
+/**
+ * Explicit Euler: moves using the old velocity, then updates the velocity.
+ * Cheap but gains energy steadily in orbits
+ */
+pub struct ExplicitEuler;
+
+/**
+ * Semi-implicit (symplectic) Euler: updates the velocity first, then moves
+ * using the new velocity. As cheap as explicit Euler, but the energy only
+ * oscillates instead of drifting
+ */
+pub struct SemiImplicitEuler;
+
+/**
+ * Velocity Verlet: second order and symplectic, at the cost of one extra
+ * acceleration evaluation per step
+ */
+pub struct VelocityVerlet;
+
+/**
+ * Classic fourth order Runge-Kutta: very accurate for smooth forces, at the
+ * cost of four acceleration evaluations per step
+ */
+pub struct RungeKutta4;
+
+impl Integrator for ExplicitEuler {
+    fn step(&self, state: State, delta_time: f64, acceleration: &Fn(&State) -> Vector2<f64>)
+            -> State {
+        State {
+            position: state.position + state.velocity * delta_time,
+            velocity: state.velocity + acceleration(&state) * delta_time,
+        }
+    }
+}
+
+impl Integrator for SemiImplicitEuler {
+    fn step(&self, state: State, delta_time: f64, acceleration: &Fn(&State) -> Vector2<f64>)
+            -> State {
+        let velocity = state.velocity + acceleration(&state) * delta_time;
+        State {
+            position: state.position + velocity * delta_time,
+            velocity: velocity,
+        }
+    }
+}
+
+impl Integrator for VelocityVerlet {
+    fn step(&self, state: State, delta_time: f64, acceleration: &Fn(&State) -> Vector2<f64>)
+            -> State {
+        let start = acceleration(&state);
+        let position = state.position + state.velocity * delta_time +
+                       start * (0.5 * delta_time * delta_time);
+
+        // Velocity dependent forces see a first guess of the new velocity
+        let guess = State {
+            position: position,
+            velocity: state.velocity + start * delta_time,
+        };
+        let end = acceleration(&guess);
+
+        State {
+            position: position,
+            velocity: state.velocity + (start + end) * (0.5 * delta_time),
+        }
+    }
+}
+
+impl Integrator for RungeKutta4 {
+    fn step(&self, state: State, delta_time: f64, acceleration: &Fn(&State) -> Vector2<f64>)
+            -> State {
+        let half = delta_time / 2.0;
+
+        // Each stage is the derivative (velocity, acceleration) at a trial state
+        let k1_velocity = state.velocity;
+        let k1_acceleration = acceleration(&state);
+
+        let k2_state = State {
+            position: state.position + k1_velocity * half,
+            velocity: state.velocity + k1_acceleration * half,
+        };
+        let k2_velocity = k2_state.velocity;
+        let k2_acceleration = acceleration(&k2_state);
+
+        let k3_state = State {
+            position: state.position + k2_velocity * half,
+            velocity: state.velocity + k2_acceleration * half,
+        };
+        let k3_velocity = k3_state.velocity;
+        let k3_acceleration = acceleration(&k3_state);
+
+        let k4_state = State {
+            position: state.position + k3_velocity * delta_time,
+            velocity: state.velocity + k3_acceleration * delta_time,
+        };
+        let k4_velocity = k4_state.velocity;
+        let k4_acceleration = acceleration(&k4_state);
+
+        let sixth = delta_time / 6.0;
+        State {
+            position: state.position +
+                      (k1_velocity + k2_velocity * 2.0 + k3_velocity * 2.0 + k4_velocity) * sixth,
+            velocity: state.velocity +
+                      (k1_acceleration + k2_acceleration * 2.0 + k3_acceleration * 2.0 +
+                       k4_acceleration) * sixth,
+        }
+    }
+}
+
+impl Method {
+    /**
+     * Returns the integrator implementing the method
+     */
+    pub fn integrator(&self) -> Box<Integrator> {
+        match *self {
+            Method::ExplicitEuler => Box::new(ExplicitEuler),
+            Method::SemiImplicitEuler => Box::new(SemiImplicitEuler),
+            Method::VelocityVerlet => Box::new(VelocityVerlet),
+            Method::RungeKutta4 => Box::new(RungeKutta4),
+        }
+    }
+
+    /**
+     * Returns the method following this one, wrapping around after the last
+     */
+    pub fn next(&self) -> Method {
+        match *self {
+            Method::ExplicitEuler => Method::SemiImplicitEuler,
+            Method::SemiImplicitEuler => Method::VelocityVerlet,
+            Method::VelocityVerlet => Method::RungeKutta4,
+            Method::RungeKutta4 => Method::ExplicitEuler,
+        }
+    }
+
+    /**
+     * Returns the name of the method as used in files and on screen
+     */
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Method::ExplicitEuler => "euler",
+            Method::SemiImplicitEuler => "semi-implicit-euler",
+            Method::VelocityVerlet => "verlet",
+            Method::RungeKutta4 => "rk4",
+        }
+    }
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Method, String> {
+        match name {
+            "euler" => Ok(Method::ExplicitEuler),
+            "semi-implicit-euler" => Ok(Method::SemiImplicitEuler),
+            "verlet" => Ok(Method::VelocityVerlet),
+            "rk4" => Ok(Method::RungeKutta4),
+            _ => Err(format!("unknown integrator '{}'", name)),
+        }
+    }
+}
+
+/**
+ * Integrates an eccentric orbit around a blackhole of unit mass at the origin
+ * for about fifty revolutions, returning the largest relative deviation of the
+ * orbital energy from its starting value
+ */
+#[cfg(test)]
+fn orbit_energy_drift(method: Method) -> f64 {
+    use physics;
+
+    let gravity = |state: &State| {
+        let distance = state.position.x.hypot(state.position.y);
+        let towards = Vector2::new(-state.position.x, -state.position.y) / distance;
+        towards * physics::gravity_acceleration(1.0, distance)
+    };
+    let energy = |state: &State| {
+        let speed = state.velocity.x.hypot(state.velocity.y);
+        0.5 * speed * speed - 1.0 / state.position.x.hypot(state.position.y)
+    };
+
+    let integrator = method.integrator();
+    let mut state = State {
+        position: Point2::new(1.0, 0.0),
+        velocity: Vector2::new(0.0, 0.8),
+    };
+    let start = energy(&state);
+    let mut drift = 0.0_f64;
+
+    for _ in 0..20000 {
+        state = integrator.step(state, 0.01, &gravity);
+        drift = drift.max(((energy(&state) - start) / start).abs());
+    }
+    drift
+}
+
+#[test]
+fn test_orbit_energy_drift() {
+    let euler = orbit_energy_drift(Method::ExplicitEuler);
+    let semi_implicit = orbit_energy_drift(Method::SemiImplicitEuler);
+    let verlet = orbit_energy_drift(Method::VelocityVerlet);
+    let rk4 = orbit_energy_drift(Method::RungeKutta4);
+
+    // Explicit Euler spirals outwards, the others stay close to the orbit,
+    // the higher order methods closer than the lower order ones
+    assert!(euler > 0.1);
+    assert!(semi_implicit < 0.02);
+    assert!(verlet < 0.001);
+    assert!(rk4 < 0.000001);
+    assert!(rk4 < verlet && verlet < semi_implicit && semi_implicit < euler);
+}
+
+#[test]
+fn test_constant_acceleration() {
+    // Verlet and RK4 are exact for constant acceleration
+    let falling = |_: &State| Vector2::new(0.0, 2.0);
+    let start = State {
+        position: Point2::new(0.0, 0.0),
+        velocity: Vector2::new(1.0, 0.0),
+    };
+
+    for method in vec![Method::VelocityVerlet, Method::RungeKutta4] {
+        let end = method.integrator().step(start, 1.0, &falling);
+        assert_eq!(end.position, Point2::new(1.0, 1.0));
+        assert_eq!(end.velocity, Vector2::new(1.0, 2.0));
+    }
+}
+
+#[test]
+fn test_method_names() {
+    let mut method = Method::ExplicitEuler;
+    for _ in 0..4 {
+        assert_eq!(method.name().parse::<Method>(), Ok(method));
+        method = method.next();
+    }
+    assert_eq!(method, Method::ExplicitEuler);
+}
@@ -0,0 +1,460 @@
+use na::{FloatPoint, Point2, Vector2};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use poolball;
+use blackhole;
+use goalzone;
+use wormhole;
+use catalogue;
+use parse;
+use nbody;
+use settings;
+
+/**
+ * The starting layout of the table. Levels are stored as plain text with one
+ * entity per line, positional arguments first followed by optional
+ * `key=value` settings:
+ *
+ * ```text
+ * # Comments start with a hash
+ * ball <type> <x> <y> [velocity=<vx>,<vy>]
+ * blackhole <x> <y> <mass> <radius> <reach> [accretion=<growth>] [decay=<rate>]
+ * goalzone <x> <y> [accepts=<type>,...] [multiplier=<n>] [capacity=<n>]
+ *                  [path=<x>,<y>;<x>,<y>;...] [speed=<speed>]
+ * wormhole <x> <y> <orientation> <x> <y> <orientation> [multiplier=<m>]
+ * nbody [strength=<g>] [softening=<s>] [theta=<theta>]
+ * settings [<key>=<value>] ...
+ * ```
+ *
+ * The `nbody` line turns on gravity between the poolballs themselves, and the
+ * `settings` line overrides constants of the settings file for this level
+ */
+#[derive(Clone)]
+pub struct Level {
+    pub balls: Vec<poolball::Poolball>,
+    pub blackholes: Vec<blackhole::Blackhole>,
+    pub goalzones: Vec<goalzone::Goalzone>,
+    pub wormholes: Vec<wormhole::Wormhole>,
+    pub n_body: Option<nbody::NBody>,
+    pub settings: Vec<(String, f64)>,
+}
+
+// Default settings for gravity between poolballs, weak enough that balls at
+// rest only slowly drift together
+const DEFAULT_STRENGTH: f64 = 0.001;
+const DEFAULT_SOFTENING: f64 = 0.02;
+const DEFAULT_THETA: f64 = 0.5;
+
+impl Level {
+    /**
+     * Reads and parses the level file at the given path, creating poolballs
+     * from the given catalogue
+     */
+    pub fn load(path: &Path, catalogue: &catalogue::Catalogue) -> Result<Level, String> {
+        let mut source = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut source))
+            .map_err(|err| format!("could not read {}: {}", path.display(), err)));
+        Level::parse(&source, catalogue).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /**
+     * Parses a level from its textual representation, creating poolballs from
+     * the given catalogue
+     */
+    pub fn parse(source: &str, catalogue: &catalogue::Catalogue) -> Result<Level, String> {
+        let mut level = Level {
+            balls: Vec::new(),
+            blackholes: Vec::new(),
+            goalzones: Vec::new(),
+            wormholes: Vec::new(),
+            n_body: None,
+            settings: Vec::new(),
+        };
+
+        for line in parse::lines(source) {
+            try!(level.parse_entity(&line, catalogue)
+                .map_err(|err| format!("line {}: {}", line.number, err)));
+        }
+
+        Ok(level)
+    }
+
+    /**
+     * Returns the textual representation of the level
+     */
+    pub fn to_source(&self) -> String {
+        let mut lines = Vec::new();
+        for ball in &self.balls {
+            let mut line = format!("ball {} {} {}",
+                                   ball.ball_type.name(),
+                                   ball.position.x,
+                                   ball.position.y);
+            if ball.velocity != Vector2::new(0.0, 0.0) {
+                line.push_str(&format!(" velocity={},{}", ball.velocity.x, ball.velocity.y));
+            }
+            lines.push(line);
+        }
+        for hole in &self.blackholes {
+            let mut line = format!("blackhole {} {} {} {} {}",
+                                   hole.position.x,
+                                   hole.position.y,
+                                   hole.mass,
+                                   hole.radius,
+                                   hole.reach);
+            if let Some(ref accretion) = hole.accretion {
+                line.push_str(&format!(" accretion={} decay={}",
+                                       accretion.growth,
+                                       accretion.decay));
+            }
+            lines.push(line);
+        }
+        for goalzone in &self.goalzones {
+            lines.push(goalzone.to_source());
+        }
+        for wormhole in &self.wormholes {
+            let (first, second) = (&wormhole.portals[0], &wormhole.portals[1]);
+            lines.push(format!("wormhole {} {} {} {} {} {} multiplier={}",
+                               first.position.x,
+                               first.position.y,
+                               first.orientation,
+                               second.position.x,
+                               second.position.y,
+                               second.orientation,
+                               wormhole.speed_multiplier));
+        }
+        if let Some(ref n_body) = self.n_body {
+            lines.push(format!("nbody strength={} softening={} theta={}",
+                               n_body.strength,
+                               n_body.softening,
+                               n_body.theta));
+        }
+        if !self.settings.is_empty() {
+            let overrides: Vec<String> = self.settings
+                .iter()
+                .map(|&(ref key, value)| format!("{}={}", key, value))
+                .collect();
+            lines.push(format!("settings {}", overrides.join(" ")));
+        }
+
+        let mut source = lines.join("\n");
+        source.push('\n');
+        source
+    }
+
+    /**
+     * Writes the level to the file at the given path
+     */
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        write_source(path, &self.to_source())
+    }
+
+    /**
+     * Returns what keeps the level from being played as intended: a missing
+     * or second white ball, entities off the table, poolballs overlapping each
+     * other or inside a blackhole, and poolballs worth points that no goalzone
+     * accepts
+     */
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let on_table = |position: &Point2<f64>| {
+            position.x >= 0.0 && position.x <= 1.0 && position.y >= 0.0 && position.y <= 1.0
+        };
+
+        let whites = self.balls
+            .iter()
+            .filter(|ball| ball.ball_type == poolball::BallType::White)
+            .count();
+        if whites != 1 {
+            problems.push(format!("{} white balls instead of one", whites));
+        }
+
+        for (i, ball) in self.balls.iter().enumerate() {
+            let name = format!("{} ball at {}, {}",
+                               ball.ball_type.name(),
+                               ball.position.x,
+                               ball.position.y);
+            if !on_table(&ball.position) {
+                problems.push(format!("{} is off the table", name));
+            }
+            for other in &self.balls[i + 1..] {
+                if ball.position.distance(&other.position) < ball.radius + other.radius {
+                    problems.push(format!("{} overlaps the {} ball at {}, {}",
+                                          name,
+                                          other.ball_type.name(),
+                                          other.position.x,
+                                          other.position.y));
+                }
+            }
+            if self.blackholes.iter().any(|hole| hole.is_spagettified(ball)) {
+                problems.push(format!("{} is inside a blackhole", name));
+            }
+            let scoring = ball.ball_type != poolball::BallType::White && ball.get_value() > 0;
+            if scoring && !self.goalzones.iter().any(|zone| zone.accepts(ball)) {
+                problems.push(format!("{} is not accepted by any goalzone", name));
+            }
+        }
+
+        for hole in &self.blackholes {
+            if !on_table(&hole.position) {
+                problems.push(format!("blackhole at {}, {} is off the table",
+                                      hole.position.x,
+                                      hole.position.y));
+            }
+        }
+        for zone in &self.goalzones {
+            if !on_table(&zone.position()) {
+                problems.push(format!("goalzone at {}, {} is off the table",
+                                      zone.position().x,
+                                      zone.position().y));
+            }
+        }
+        problems
+    }
+
+    /**
+     * Parses a single entity line and adds the entity to the level
+     */
+    fn parse_entity(&mut self,
+                    line: &parse::Line,
+                    catalogue: &catalogue::Catalogue)
+                    -> Result<(), String> {
+        match line.kind {
+            "ball" => {
+                try!(line.expect_arguments(3));
+                let ball_type = try!(line.arguments[0].parse::<poolball::BallType>());
+                let position = Point2::new(try!(parse::parse_number(line.arguments[1])),
+                                           try!(parse::parse_number(line.arguments[2])));
+                let mut ball = catalogue.create(position, ball_type);
+
+                for &(key, value) in &line.options {
+                    match key {
+                        "velocity" => {
+                            let velocity = try!(parse::parse_point(value));
+                            ball.set_velocity(Vector2::new(velocity.x, velocity.y));
+                        }
+                        _ => return Err(line.unknown_option(key)),
+                    }
+                }
+                self.balls.push(ball);
+            }
+            "blackhole" => {
+                try!(line.expect_arguments(5));
+                let numbers = try!(line.numbers());
+                let mut blackhole = blackhole::Blackhole::new(Point2::new(numbers[0],
+                                                                          numbers[1]),
+                                                              numbers[2],
+                                                              numbers[3],
+                                                              numbers[4]);
+                let mut growth = None;
+                let mut decay = 0.0;
+
+                for &(key, value) in &line.options {
+                    match key {
+                        "accretion" => growth = Some(try!(parse::parse_number(value))),
+                        "decay" => decay = try!(parse::parse_number(value)),
+                        _ => return Err(line.unknown_option(key)),
+                    }
+                }
+
+                if let Some(growth) = growth {
+                    blackhole = blackhole.with_accretion(growth, decay);
+                }
+                self.blackholes.push(blackhole);
+            }
+            "goalzone" => {
+                try!(line.expect_arguments(2));
+                let numbers = try!(line.numbers());
+                let mut goalzone = goalzone::Goalzone::new(Point2::new(numbers[0], numbers[1]));
+                let mut path = Vec::new();
+                let mut speed = 0.0;
+
+                for &(key, value) in &line.options {
+                    match key {
+                        "accepts" => {
+                            let mut accepts = Vec::new();
+                            for name in value.split(',') {
+                                accepts.push(try!(name.parse::<poolball::BallType>()));
+                            }
+                            goalzone = goalzone.with_accepts(accepts);
+                        }
+                        "multiplier" => {
+                            goalzone = goalzone.with_multiplier(try!(parse::parse_integer(value)));
+                        }
+                        "capacity" => {
+                            let capacity = try!(parse::parse_integer(value));
+                            if capacity < 0 {
+                                return Err(format!("'{}' is not a positive integer", value));
+                            }
+                            goalzone = goalzone.with_capacity(capacity as u32);
+                        }
+                        "path" => {
+                            for point in value.split(';') {
+                                path.push(try!(parse::parse_point(point)));
+                            }
+                        }
+                        "speed" => speed = try!(parse::parse_number(value)),
+                        _ => return Err(line.unknown_option(key)),
+                    }
+                }
+
+                if !path.is_empty() {
+                    goalzone = goalzone.with_path(path, speed);
+                }
+                self.goalzones.push(goalzone);
+            }
+            "wormhole" => {
+                try!(line.expect_arguments(6));
+                let numbers = try!(line.numbers());
+                let mut speed_multiplier = 1.0;
+
+                for &(key, value) in &line.options {
+                    match key {
+                        "multiplier" => speed_multiplier = try!(parse::parse_number(value)),
+                        _ => return Err(line.unknown_option(key)),
+                    }
+                }
+
+                let first = wormhole::Portal::new(Point2::new(numbers[0], numbers[1]), numbers[2]);
+                let second = wormhole::Portal::new(Point2::new(numbers[3], numbers[4]),
+                                                   numbers[5]);
+                self.wormholes.push(wormhole::Wormhole::new(first, second, speed_multiplier));
+            }
+            "nbody" => {
+                try!(line.expect_arguments(0));
+                let mut n_body =
+                    nbody::NBody::new(DEFAULT_STRENGTH, DEFAULT_SOFTENING, DEFAULT_THETA);
+
+                for &(key, value) in &line.options {
+                    match key {
+                        "strength" => n_body.strength = try!(parse::parse_number(value)),
+                        "softening" => n_body.softening = try!(parse::parse_number(value)),
+                        "theta" => n_body.theta = try!(parse::parse_number(value)),
+                        _ => return Err(line.unknown_option(key)),
+                    }
+                }
+                self.n_body = Some(n_body);
+            }
+            "settings" => {
+                try!(line.expect_arguments(0));
+                for &(key, value) in &line.options {
+                    let value = try!(parse::parse_number(value));
+                    try!(settings::Settings::new().set(key, value));
+                    self.settings.push((String::from(key), value));
+                }
+            }
+            _ => return Err(format!("unknown entity '{}'", line.kind)),
+        }
+
+        Ok(())
+    }
+}
+
+/**
+ * Writes the given level file source to the file at the given path
+ */
+pub fn write_source(path: &Path, source: &str) -> Result<(), String> {
+    File::create(path)
+        .and_then(|mut file| file.write_all(source.as_bytes()))
+        .map_err(|err| format!("could not write {}: {}", path.display(), err))
+}
+
+#[test]
+fn test_parse() {
+    let source = "
+        # A small test level
+        ball white 0.5 0.3
+        ball red 0.1 0.1 velocity=0.2,-0.3
+        blackhole 0.6 0.7 0.01 0.0000001 0.1
+        blackhole 0.2 0.7 0.01 0.0000001 0.1 accretion=0.5 decay=0.1
+        goalzone 0.0 0.0
+        goalzone 1.0 1.0 accepts=red,blue multiplier=2 capacity=3
+        goalzone 0.5 0.5 path=0.2,0.5;0.8,0.5 speed=0.1
+        wormhole 0.3 0.5 0.0 0.85 0.4 1.5 multiplier=1.2
+    ";
+    let level = Level::parse(source, &catalogue::Catalogue::builtin()).unwrap();
+
+    assert_eq!(level.balls.len(), 2);
+    assert_eq!(level.balls[0].ball_type, poolball::BallType::White);
+    assert_eq!(level.balls[1].velocity, Vector2::new(0.2, -0.3));
+    assert_eq!(level.blackholes.len(), 2);
+    assert_eq!(level.blackholes[0].reach, 0.1);
+    assert_eq!(level.blackholes[0].accretion, None);
+    assert_eq!(level.blackholes[1].accretion.as_ref().unwrap().decay, 0.1);
+    assert_eq!(level.goalzones.len(), 3);
+    assert!(!level.goalzones[1].accepts(&level.balls[0]));
+    assert!(level.goalzones[1].accepts(&level.balls[1]));
+    assert_eq!(level.wormholes.len(), 1);
+    assert_eq!(level.wormholes[0].speed_multiplier, 1.2);
+    assert_eq!(level.n_body, None);
+
+    let level = Level::parse("nbody strength=0.5 theta=0", &catalogue::Catalogue::builtin())
+        .unwrap();
+    let n_body = level.n_body.unwrap();
+    assert_eq!(n_body.strength, 0.5);
+    assert_eq!(n_body.softening, DEFAULT_SOFTENING);
+    assert_eq!(n_body.theta, 0.0);
+
+    let level = Level::parse("settings friction=0.2 shot_speed=4",
+                             &catalogue::Catalogue::builtin())
+        .unwrap();
+    assert_eq!(level.settings,
+               vec![(String::from("friction"), 0.2), (String::from("shot_speed"), 4.0)]);
+}
+
+#[test]
+fn test_round_trip() {
+    let source = "ball white 0.5 0.3
+ball red 0.1 0.1 velocity=0.2,-0.3
+blackhole 0.2 0.7 0.01 0.0000001 0.1 accretion=0.5 decay=0.1
+goalzone 1 1 accepts=red,blue multiplier=2 capacity=3
+goalzone 0.2 0.5 path=0.2,0.5;0.8,0.5 speed=0.1
+wormhole 0.3 0.5 0 0.85 0.4 1.5 multiplier=1.2
+nbody strength=0.5 softening=0.02 theta=0
+settings friction=0.2 shot_speed=4
+";
+    let catalogue = catalogue::Catalogue::builtin();
+    let level = Level::parse(source, &catalogue).unwrap();
+    assert_eq!(level.to_source(), source);
+}
+
+#[test]
+fn test_parse_errors() {
+    let catalogue = catalogue::Catalogue::builtin();
+    assert!(Level::parse("ball green 0.1 0.1", &catalogue).is_err());
+    assert!(Level::parse("ball red 0.1", &catalogue).is_err());
+    assert!(Level::parse("blackhole 0.1 0.1 a 0.1 0.1", &catalogue).is_err());
+    assert!(Level::parse("goalzone 0.1 0.1 colour=red", &catalogue).is_err());
+    assert!(Level::parse("goalzone 0.1 0.1 capacity=-1", &catalogue).is_err());
+    assert!(Level::parse("table 1.0 1.0", &catalogue).is_err());
+    assert!(Level::parse("settings drag=0.1", &catalogue).is_err());
+    assert!(Level::parse("settings friction=-1", &catalogue).is_err());
+
+    let err = Level::parse("ball red 0.1 0.1\nball red 0.1", &catalogue).err().unwrap();
+    assert!(err.starts_with("line 2"));
+}
+
+#[test]
+fn test_problems() {
+    let catalogue = catalogue::Catalogue::builtin();
+    for name in &["classic", "cluster", "menagerie", "zones"] {
+        let level = Level::load(Path::new(&format!("levels/{}.lvl", name)), &catalogue).unwrap();
+        assert_eq!(level.problems(), Vec::<String>::new());
+    }
+
+    let level = Level::parse("ball red 0.5 0.5
+                              ball blue 0.51 0.5
+                              ball red 1.5 0.5
+                              blackhole 0.2 0.2 0.01 0.1 0.2
+                              ball red 0.2 0.2
+                              goalzone 0.0 0.0 accepts=red",
+                             &catalogue)
+        .unwrap();
+    assert_eq!(level.problems(),
+               vec!["0 white balls instead of one",
+                    "red ball at 0.5, 0.5 overlaps the blue ball at 0.51, 0.5",
+                    "blue ball at 0.51, 0.5 is not accepted by any goalzone",
+                    "red ball at 1.5, 0.5 is off the table",
+                    "red ball at 0.2, 0.2 is inside a blackhole"]);
+}
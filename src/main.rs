@@ -10,11 +10,13 @@ use piston::window::WindowSettings;
 use piston::event_loop::*;
 use glutin_window::GlutinWindow;
 use piston::input::*;
-use opengl_graphics::{GlGraphics, OpenGL};
+use opengl_graphics::GlGraphics;
 use std::path::Path;
+use std::env;
+use std::io::{self, Write};
+use std::process;
 use opengl_graphics::glyph_cache::GlyphCache;
-
-use na::{Vector2, Point2};
+use na::{Point2, Vector2};
 
 mod poolball;
 mod goalzone;
@@ -23,81 +25,643 @@ mod blackhole;
 mod math;
 mod game;
 mod arrow;
+mod wormhole;
+mod event;
+mod level;
+mod catalogue;
+mod parse;
+mod nbody;
+mod integrator;
+mod diagnostics;
+mod renderer;
+mod canvas;
+mod replay;
+mod png;
+mod gif;
+mod export;
+mod camera;
+mod effects;
+mod field;
+mod debug;
+mod hud;
+mod menu;
+mod scores;
+mod trace;
+mod svg;
+mod terminal;
+mod ai;
+mod solver;
+mod generator;
+mod editor;
+mod cli;
+mod settings;
+mod tuning;
+
+// Zoom per step of the mouse wheel, and pixels panned per key press
+const ZOOM_STEP: f64 = 1.1;
+const PAN_STEP: f64 = 40.0;
+
+// Factor blackholes are resized by per key press in the editor
+const RESIZE_STEP: f64 = 1.1;
+
+// File the best score of every level is kept in
+const SCORES_PATH: &'static str = "scores.txt";
+
+// Fixed steps taken at most per update, so that the game slows down rather than
+// falling further behind when updates take too long
+const MAX_STEPS: f64 = 8.0;
+
+// File the diagnostics of every update are logged to
+const DIAGNOSTICS_PATH: &'static str = "diagnostics.csv";
+
+// File the physics and aiming constants are read from, and reloaded from
+// whenever it changes while playing
+const SETTINGS_PATH: &'static str = "assets/settings.cfg";
 
 fn main() {
-    // Change this to OpenGL::V2_1 if not working.
-    let opengl = OpenGL::V3_2;
+    // Load the ball catalogue
+    let catalogue = match catalogue::Catalogue::load_or_default(Path::new("assets/balls.cat")) {
+        Ok(catalogue) => catalogue,
+        Err(err) => fail(&format!("Failed to load ball catalogue: {}", err)),
+    };
 
-    // Create an Glutin window.
-    let mut window: GlutinWindow = WindowSettings::new("spinning-square", [800, 800])
+    // The window flags may be given anywhere, the rest names the command
+    let arguments: Vec<String> = env::args().skip(1).collect();
+    let (window_settings, arguments) = match cli::parse_window(&arguments) {
+        Ok(parsed) => parsed,
+        Err(err) => fail(&format!("{}\n{}", err, cli::USAGE)),
+    };
+
+    // Constants of the physics and the aiming, used by every command and
+    // tuned while playing
+    let mut settings = match settings::Settings::load_or_default(Path::new(SETTINGS_PATH)) {
+        Ok(settings) => settings,
+        Err(err) => fail(&format!("Failed to load settings: {}", err)),
+    };
+
+    // Run a command without a window instead of playing, reporting what it
+    // did or why it failed
+    {
+        let rest = if arguments.is_empty() { &arguments[..] } else { &arguments[1..] };
+        match arguments.first().map(|argument| argument.as_str()) {
+            Some("help") => finish(Ok(String::from(cli::USAGE))),
+            Some("export") => {
+                finish(export::run(rest, catalogue, &settings)
+                    .map(|frames| format!("Exported {} frames", frames))
+                    .map_err(|err| format!("Failed to export: {}", err)))
+            }
+            Some("svg") => {
+                finish(export::run_svg(rest, catalogue, &settings)
+                    .map(|()| format!("Exported {}", rest[1]))
+                    .map_err(|err| format!("Failed to export: {}", err)))
+            }
+            Some("terminal") => {
+                finish(terminal::run(rest, catalogue, &settings)
+                    .map(|score| format!("Final score: {}", score))
+                    .map_err(|err| format!("Failed to play in the terminal: {}", err)))
+            }
+            Some("solve") => {
+                finish(solver::run(rest, catalogue, &settings)
+                    .map(|(shots, output)| {
+                        format!("Solved in {} shots, saved the solution to {}", shots, output)
+                    })
+                    .map_err(|err| format!("Failed to solve: {}", err)))
+            }
+            Some("generate") => {
+                finish(generator::run(rest, catalogue, &settings)
+                    .map(|shots| format!("Generated {}, cleared in {} shots", rest[0], shots))
+                    .map_err(|err| format!("Failed to generate a level: {}", err)))
+            }
+            Some("simulate") => {
+                finish(cli::run_simulate(rest, catalogue, &settings)
+                    .map_err(|err| format!("Failed to simulate: {}", err)))
+            }
+            Some("render") => {
+                finish(cli::run_render(rest, catalogue, &settings)
+                    .map(|frames| format!("Rendered {} frames", frames))
+                    .map_err(|err| format!("Failed to render: {}", err)))
+            }
+            Some("validate") => {
+                finish(cli::run_validate(rest, catalogue)
+                    .map(|()| format!("{} is valid", rest[0]))
+                    .map_err(|err| format!("Failed to validate: {}", err)))
+            }
+            Some("bench") => {
+                finish(cli::run_bench(rest, catalogue, &settings)
+                    .map(|levels| format!("Timed {} levels", levels))
+                    .map_err(|err| format!("Failed to bench: {}", err)))
+            }
+            _ => {}
+        }
+    }
+
+    // Levels to choose from and the best scores reached on them
+    let levels = match menu::find_levels(Path::new("levels")) {
+        Ok(levels) => levels,
+        Err(err) => fail(&format!("Failed to list levels: {}", err)),
+    };
+    let scores = scores::Scores::load(Path::new(SCORES_PATH)).unwrap_or(scores::Scores::new());
+    let mut menu = menu::Menu::new(levels, scores);
+
+    let mut watcher = settings::Watcher::new(Path::new(SETTINGS_PATH));
+    let mut panel = tuning::Panel::new();
+
+    // Edit the level given after `edit`, play the level given after `play` or
+    // on its own straight away, or start at the title screen
+    let arguments = if arguments.first().map(|argument| argument.as_str()) == Some("play") {
+        if arguments.len() != 2 {
+            fail("usage: play <level>");
+        }
+        arguments[1..].to_vec()
+    } else {
+        arguments
+    };
+    let mut level_path = arguments.first().cloned().unwrap_or(String::from("levels/classic.lvl"));
+    let mut game = None;
+    let mut opponent = None;
+    let mut editor = None;
+    if arguments.first().map(|argument| argument.as_str()) == Some("edit") {
+        level_path = match arguments.get(1) {
+            Some(path) => path.clone(),
+            None => fail("usage: edit <level>"),
+        };
+        match editor::Editor::open(&level_path, &catalogue) {
+            Ok(opened) => editor = Some(opened),
+            Err(err) => fail(&format!("Failed to open level: {}", err)),
+        }
+        menu.close();
+    } else if !arguments.is_empty() {
+        match start_game(&level_path, &catalogue, &settings, &menu.options) {
+            Ok(started) => {
+                opponent = start_opponent(&started, &menu.options);
+                game = Some(started);
+            }
+            Err(err) => fail(&format!("Failed to load level: {}", err)),
+        }
+        menu.close();
+    }
+
+    // Create an Glutin window, trying an older OpenGL version with
+    // `--opengl 2.1` if this fails
+    let opengl = window_settings.opengl;
+    let size = [window_settings.width, window_settings.height];
+    let mut window: GlutinWindow = WindowSettings::new("rymdbiljard", size)
         .opengl(opengl)
-        .exit_on_esc(true)
+        .exit_on_esc(false)
+        .fullscreen(window_settings.fullscreen)
+        .vsync(window_settings.vsync)
         .build()
         .unwrap();
     let mut gl = GlGraphics::new(opengl);
 
-    // Create some starting poolballs
-    let mut balls = vec![
-        poolball::Poolball::new(Point2::new(0.5, 0.3), poolball::BallType::White),
-        poolball::Poolball::new(Point2::new(0.1, 0.1), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.2, 0.1), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.3, 0.1), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.4, 0.1), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.5, 0.1), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.6, 0.1), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.7, 0.1), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.8, 0.1), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.9, 0.1), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.1, 0.2), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.1, 0.3), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.1, 0.4), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.1, 0.5), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.1, 0.6), poolball::BallType::Red),
-        poolball::Poolball::new(Point2::new(0.1, 0.7), poolball::BallType::Blue),
-        poolball::Poolball::new(Point2::new(0.1, 0.8), poolball::BallType::Blue),
-        poolball::Poolball::new(Point2::new(0.1, 0.9), poolball::BallType::Blue),
-    ];
-
-    // Create blackholes
-    let blackholes = vec![
-        blackhole::Blackhole::new(Point2::new(0.6, 0.7), 0.01, 0.0000001, 0.1),
-    ];
-
-    // Create goalzones
-    let goalzones = vec![
-        goalzone::Goalzone::new(Point2::new(0.0, 0.0)),
-        goalzone::Goalzone::new(Point2::new(1.0, 0.0)),
-        goalzone::Goalzone::new(Point2::new(0.0, 1.0)),
-        goalzone::Goalzone::new(Point2::new(1.0, 1.0)),
-    ];
-
-    // Set velocity for all poolballs
-    for ball in &mut balls {
-        ball.set_velocity(Vector2::new(0.2, -0.3));
-    }
-
-    // Create and start the game
-    let mut game = game::Game::new(balls, blackholes, goalzones);
-
     let font_path = Path::new("assets/FiraSans-Regular.ttf");
     let ref mut cache = GlyphCache::new(font_path).unwrap();
 
+    // The camera follows the size of the window, and is zoomed with the
+    // mouse wheel and panned by dragging with the right mouse button
+    let mut camera = camera::Camera::new(size[0] as f64, size[1] as f64);
+    let mut cursor = Point2::new(0.0, 0.0);
+    let mut dragging = false;
+
+    // Diagnostics are only logged to file when asked for
+    let mut diagnostics_log: Option<diagnostics::CsvLog> = None;
+
+    // The game is updated in fixed steps, the same as when exporting and
+    // replaying, taking as many steps as the game speed and time scale ask for
+    let step = UpdateArgs { dt: 1.0 / export::UPDATES_PER_SECOND as f64 };
+    let mut steps_due = 0.0;
+
     // Main game loop
     let mut events = window.events();
     while let Some(e) = events.next(&mut window) {
         if let Some(r) = e.render_args() {
-            game.render(&mut gl, &r, cache);
+            camera.resize(r.width as f64, r.height as f64);
+            gl.draw(r.viewport(), |c, gl| {
+                let mut renderer = renderer::GlRenderer::new(c, gl, cache, &camera);
+                if let Some(ref game) = game {
+                    if menu.in_game {
+                        game.render(&mut renderer);
+                        if let Some(ref opponent) = opponent {
+                            opponent.render(&mut renderer);
+                        }
+                        if panel.open {
+                            panel.render(game.settings(), game.overrides(), &mut renderer);
+                        }
+                    }
+                } else if let Some(ref editor) = editor {
+                    editor.render(&mut renderer);
+                }
+                menu.render(&mut renderer);
+            });
+        }
+
+        // What the menu and the editor ask for after the input of this event
+        let mut action = None;
+        let mut edit_action = None;
+        let mut stop_testing = false;
+
+        if let Some(position) = e.mouse_cursor_args() {
+            let moved = Point2::new(position[0], position[1]);
+            if dragging {
+                camera.pan(moved - cursor);
+            }
+            cursor = moved;
+            action = menu.input(menu::Input::Point(camera.to_view(&cursor)));
+            if panel.open && !menu.is_open() {
+                panel.input(tuning::Input::Point(camera.to_view(&cursor)), &mut settings);
+            }
+            if game.is_none() {
+                if let Some(ref mut editor) = editor {
+                    editor.input(editor::Input::Drag(camera.to_world(&cursor)));
+                }
+            }
+        }
+
+        if let Some(scroll) = e.mouse_scroll_args() {
+            if !menu.is_open() {
+                camera.zoom_at(&cursor, ZOOM_STEP.powf(scroll[1]));
+            }
+        }
+
+        if let Some(Button::Mouse(MouseButton::Right)) = e.release_args() {
+            dragging = false;
         }
 
         if let Some(u) = e.update_args() {
-            game.update(&u);
+            match watcher.poll(u.dt) {
+                Some(Ok(reloaded)) => {
+                    settings = reloaded;
+                    if let Some(ref mut game) = game {
+                        game.set_settings(&settings);
+                    }
+                    println!("Reloaded settings");
+                }
+                Some(Err(err)) => println!("Failed to reload settings: {}", err),
+                None => {}
+            }
+
+            if !menu.is_open() {
+                let mut finished = false;
+                if let Some(ref mut game) = game {
+                    let dt = u.dt * menu.options.speed * game.settings().time_scale;
+                    steps_due = (steps_due + dt * export::UPDATES_PER_SECOND as f64)
+                        .min(MAX_STEPS);
+                    while steps_due >= 1.0 && !finished {
+                        steps_due -= 1.0;
+                        game.update(&step);
+                        finished = game.is_over() || game.is_cleared();
+                    }
+
+                    // The computer plans its shots once per update, whatever
+                    // the number of steps
+                    if let Some(ref mut opponent) = opponent {
+                        if !finished {
+                            opponent.update(game, dt);
+                        }
+                    }
+
+                    // Logging stops rather than the game if the file can not be
+                    // written
+                    if let Some(mut log) = diagnostics_log.take() {
+                        match log.write(game.diagnostics()) {
+                            Ok(()) => diagnostics_log = Some(log),
+                            Err(err) => println!("Stopped logging diagnostics: {}", err),
+                        }
+                    }
+                }
+
+                // Record the score and go back to the level selection once
+                // the game is over or the table is cleared, or back to editing
+                // after test playing
+                if finished {
+                    opponent = None;
+                    steps_due = 0.0;
+                    let score = game.take().unwrap().score();
+                    if let Some(ref mut editor) = editor {
+                        editor.message = Some(format!("Test play ended with a score of {}",
+                                                      score));
+                    } else if menu.end_game(&level_path, score) {
+                        if let Err(err) = menu.scores.save(Path::new(SCORES_PATH)) {
+                            println!("Failed to save scores: {}", err);
+                        }
+                    }
+                }
+            }
         }
 
-        // Listen for user input
-        if let Some(Button::Keyboard(key)) = e.press_args() {
-            if key == Key::Space {
-                game.try_switch_mode();
+        // Navigate the menu while it is open
+        if menu.is_open() {
+            if let Some(Button::Mouse(MouseButton::Left)) = e.press_args() {
+                action = menu.input(menu::Input::Click(camera.to_view(&cursor)));
             }
+
+            if let Some(Button::Keyboard(key)) = e.press_args() {
+                let input = match key {
+                    Key::Up => Some(menu::Input::Up),
+                    Key::Down => Some(menu::Input::Down),
+                    Key::Left => Some(menu::Input::Left),
+                    Key::Right => Some(menu::Input::Right),
+                    Key::Return | Key::Space => Some(menu::Input::Select),
+                    Key::Escape | Key::Backspace => Some(menu::Input::Back),
+                    _ => None,
+                };
+                if let Some(input) = input {
+                    action = menu.input(input);
+                }
+            }
+        } else if let Some(ref mut game) = game {
+            // Listen for user input
+            if let Some(Button::Mouse(MouseButton::Right)) = e.press_args() {
+                dragging = true;
+            }
+
+            if let Some(Button::Keyboard(key)) = e.press_args() {
+                // The player waits while the computer opponent takes its shot
+                let computer_turn = opponent.as_ref()
+                    .map_or(false, |opponent| opponent.is_computer_turn());
+                if key == Key::Space && !computer_turn {
+                    game.try_switch_mode();
+                }
+
+                // Freeze the game and show the pause overlay, or stop test
+                // playing the level being edited
+                if key == Key::Escape {
+                    if editor.is_some() {
+                        stop_testing = true;
+                    } else {
+                        menu.options.follow(game);
+                        menu.pause();
+                    }
+                    dragging = false;
+                }
+
+                // Show or hide the tuning panel, which takes over the arrow
+                // keys while shown
+                if key == Key::Tab {
+                    panel.toggle();
+                }
+                let tune = match key {
+                    Key::Up if panel.open => Some(tuning::Input::Up),
+                    Key::Down if panel.open => Some(tuning::Input::Down),
+                    Key::Left if panel.open => Some(tuning::Input::Left),
+                    Key::Right if panel.open => Some(tuning::Input::Right),
+                    Key::Return if panel.open => Some(tuning::Input::Export),
+                    _ => None,
+                };
+                match tune.and_then(|input| panel.input(input, &mut settings)) {
+                    Some(tuning::Action::Changed) => game.set_settings(&settings),
+                    Some(tuning::Action::Export) => {
+                        panel.message = Some(match settings.save(Path::new(SETTINGS_PATH)) {
+                            Ok(()) => format!("Exported settings to {}", SETTINGS_PATH),
+                            Err(err) => format!("Failed to export settings: {}", err),
+                        });
+                    }
+                    None => {}
+                }
+
+                // Zoom and pan with the keyboard, or show the whole table again
+                let middle = Point2::new(camera.width / 2.0, camera.height / 2.0);
+                match key {
+                    Key::Equals => camera.zoom_at(&middle, ZOOM_STEP),
+                    Key::Minus => camera.zoom_at(&middle, 1.0 / ZOOM_STEP),
+                    _ if tune.is_some() => {}
+                    Key::Left => camera.pan(Vector2::new(PAN_STEP, 0.0)),
+                    Key::Right => camera.pan(Vector2::new(-PAN_STEP, 0.0)),
+                    Key::Up => camera.pan(Vector2::new(0.0, PAN_STEP)),
+                    Key::Down => camera.pan(Vector2::new(0.0, -PAN_STEP)),
+                    Key::D0 => camera.reset(),
+                    _ => {}
+                }
+
+                // Cycle through the integrators
+                if key == Key::I {
+                    let next = game.integrator().next();
+                    game.set_integrator(next);
+                }
+
+                // Show or hide the diagnostics overlay
+                if key == Key::D {
+                    game.toggle_diagnostics();
+                }
+
+                // Show or hide the physics debug view
+                if key == Key::F3 {
+                    game.toggle_debug();
+                }
+
+                // Cycle through the ways of showing the gravity field
+                if key == Key::F {
+                    let next = game.field_view().next();
+                    game.set_field_view(next);
+                }
+
+                // Show or hide the trails and the impact particles
+                if key == Key::T {
+                    game.toggle_trails();
+                }
+                if key == Key::B {
+                    game.toggle_particles();
+                }
+
+                // Save the shots taken so far as a replay
+                if key == Key::R {
+                    let replay = replay::Replay::new(&level_path, game.shots().clone())
+                        .with_integrator(game.integrator());
+                    match replay.save(Path::new("replay.rpl")) {
+                        Ok(()) => println!("Saved replay to replay.rpl"),
+                        Err(err) => println!("Failed to save replay: {}", err),
+                    }
+                }
+
+                // Draw the table and the paths of the aimed shot as an SVG image
+                if key == Key::P {
+                    let shot = game.aimed_shot();
+                    match svg::save(game, Some(&shot), Path::new("shot.svg"), 800, 800) {
+                        Ok(()) => println!("Saved shot to shot.svg"),
+                        Err(err) => println!("Failed to save shot: {}", err),
+                    }
+                }
+
+                // Start or stop logging diagnostics to file
+                if key == Key::L {
+                    diagnostics_log = match diagnostics_log.take() {
+                        Some(log) => {
+                            stop_logging(log);
+                            None
+                        }
+                        None => {
+                            match diagnostics::CsvLog::create(Path::new(DIAGNOSTICS_PATH)) {
+                                Ok(log) => Some(log),
+                                Err(err) => {
+                                    println!("Failed to log diagnostics: {}", err);
+                                    None
+                                }
+                            }
+                        }
+                    };
+                }
+            }
+        } else if let Some(ref mut editor) = editor {
+            // Place, select and drag entities with the left mouse button
+            if let Some(Button::Mouse(button)) = e.press_args() {
+                match button {
+                    MouseButton::Left => {
+                        edit_action = editor.input(editor::Input::Press(camera.to_world(&cursor)))
+                    }
+                    MouseButton::Right => dragging = true,
+                    _ => {}
+                }
+            }
+            if let Some(Button::Mouse(MouseButton::Left)) = e.release_args() {
+                editor.input(editor::Input::Release);
+            }
+
+            if let Some(Button::Keyboard(key)) = e.press_args() {
+                let input = match key {
+                    Key::Tab => Some(editor::Input::NextTool),
+                    Key::G => Some(editor::Input::ToggleSnap),
+                    Key::Delete | Key::Backspace => Some(editor::Input::Delete),
+                    Key::LeftBracket => Some(editor::Input::Reach(1.0 / RESIZE_STEP)),
+                    Key::RightBracket => Some(editor::Input::Reach(RESIZE_STEP)),
+                    Key::Minus => Some(editor::Input::Mass(1.0 / RESIZE_STEP)),
+                    Key::Equals => Some(editor::Input::Mass(RESIZE_STEP)),
+                    Key::Comma => Some(editor::Input::Horizon(1.0 / RESIZE_STEP)),
+                    Key::Period => Some(editor::Input::Horizon(RESIZE_STEP)),
+                    Key::Return => Some(editor::Input::TestPlay),
+                    Key::S => Some(editor::Input::Save),
+                    Key::Escape => Some(editor::Input::Quit),
+                    _ => None,
+                };
+                if let Some(input) = input {
+                    edit_action = editor.input(input);
+                }
+            }
+        }
+
+        // Test play the level being edited from its current state, or go back
+        // to editing it
+        if stop_testing {
+            game = None;
         }
+        match edit_action {
+            Some(editor::Action::TestPlay) => {
+                if let Some(ref editor) = editor {
+                    let mut started = game::Game::new(editor.level.clone(), catalogue.clone());
+                    started.set_settings(&settings);
+                    menu.options.apply(&mut started);
+                    game = Some(started);
+                    steps_due = 0.0;
+                }
+            }
+            Some(editor::Action::Quit) => break,
+            None => {}
+        }
+
+        // Start, restart, leave or change the game as asked by the menu
+        match action {
+            Some(menu::Action::Play(path)) => {
+                level_path = path;
+                game = None;
+                steps_due = 0.0;
+                match start_game(&level_path, &catalogue, &settings, &menu.options) {
+                    Ok(started) => {
+                        opponent = start_opponent(&started, &menu.options);
+                        game = Some(started);
+                    }
+                    Err(err) => menu.show_error(err),
+                }
+                camera.reset();
+            }
+            Some(menu::Action::Restart) => {
+                steps_due = 0.0;
+                match start_game(&level_path, &catalogue, &settings, &menu.options) {
+                    Ok(started) => {
+                        opponent = start_opponent(&started, &menu.options);
+                        game = Some(started);
+                    }
+                    Err(err) => menu.show_error(err),
+                }
+            }
+            Some(menu::Action::Leave) => {
+                game = None;
+                opponent = None;
+            }
+            Some(menu::Action::Apply) => {
+                if let Some(ref mut game) = game {
+                    menu.options.apply(game);
+
+                    // A new difficulty keeps the scores of the game so far
+                    opponent = match (opponent.take(), menu.options.opponent) {
+                        (Some(mut current), Some(difficulty)) => {
+                            current.ai.difficulty = difficulty;
+                            Some(current)
+                        }
+                        (None, Some(_)) => start_opponent(game, &menu.options),
+                        (_, None) => None,
+                    };
+                }
+            }
+            Some(menu::Action::Resume) | None => {}
+            Some(menu::Action::Quit) => break,
+        }
+    }
+
+    if let Some(log) = diagnostics_log {
+        stop_logging(log);
+    }
+}
+
+/**
+ * Writes what is left of the given diagnostics log to its file
+ */
+fn stop_logging(mut log: diagnostics::CsvLog) {
+    if let Err(err) = log.flush() {
+        println!("Failed to write diagnostics: {}", err);
+    }
+}
+
+/**
+ * Loads the level at the given path and starts a game of it with the given
+ * settings and options
+ */
+fn start_game(path: &str,
+              catalogue: &catalogue::Catalogue,
+              settings: &settings::Settings,
+              options: &menu::Options)
+              -> Result<game::Game, String> {
+    let level = try!(level::Level::load(Path::new(path), catalogue));
+    let mut game = game::Game::new(level, catalogue.clone());
+    game.set_settings(settings);
+    options.apply(&mut game);
+    Ok(game)
+}
+
+/**
+ * Creates the computer opponent chosen in the given options for the given
+ * game, if any
+ */
+fn start_opponent(game: &game::Game, options: &menu::Options) -> Option<ai::Opponent> {
+    options.opponent.map(|difficulty| ai::Opponent::new(ai::Ai::new(difficulty), game))
+}
+
+/**
+ * Prints what a command run instead of playing did and exits, or prints why it
+ * failed and exits with a failing status
+ */
+fn finish(result: Result<String, String>) -> ! {
+    match result {
+        Ok(report) => {
+            println!("{}", report);
+            process::exit(0);
+        }
+        Err(err) => fail(&err),
     }
 }
+
+/**
+ * Prints the given message to standard error and exits with a failing status
+ */
+fn fail(message: &str) -> ! {
+    let _ = writeln!(io::stderr(), "{}", message);
+    process::exit(1);
+}
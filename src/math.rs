@@ -7,6 +7,16 @@ pub fn calc_magnitude(vector: Vector2<f64>) -> f64 {
     return ((vector.x).powf(2.0) + (vector.y).powf(2.0)).sqrt();
 }
 
+/**
+ * Returns the next number between 0 and 1 of a simple pseudo random sequence
+ * continuing from the given seed, so that the same seed always gives the same
+ * numbers
+ */
+pub fn random(seed: &mut u32) -> f64 {
+    *seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+    ((*seed >> 16) & 0x7FFF) as f64 / 32768.0
+}
+
 #[test]
 fn test_magnitude() {
     let vector = Vector2::new(1.0, 1.0);
@@ -21,3 +31,12 @@ fn test_magnitude() {
     let magnitude3 = calc_magnitude(vector3);
     assert_eq!(magnitude3, 2.0);
 }
+
+#[test]
+fn test_random() {
+    let (mut seed, mut again) = (7, 7);
+    let numbers: Vec<f64> = (0..100).map(|_| random(&mut seed)).collect();
+    assert!(numbers.iter().all(|&number| number >= 0.0 && number < 1.0));
+    assert!(numbers.iter().zip(&numbers[1..]).any(|(a, b)| a != b));
+    assert_eq!(random(&mut again), numbers[0]);
+}
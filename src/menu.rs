@@ -0,0 +1,640 @@
+use na::Point2;
+use std::fs;
+use std::path::Path;
+use renderer::{Color, Renderer};
+use ai;
+use field;
+use game;
+use integrator;
+use scores;
+
+/**
+ * The pages of the menu
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Page {
+    Title,
+    Levels,
+    Options,
+    Controls,
+    Paused,
+}
+
+/**
+ * Input for navigating the menu, from the keyboard or the mouse. Positions are
+ * given in view coordinates
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+    Point(Point2<f64>),
+    Click(Point2<f64>),
+}
+
+/**
+ * What the menu asks of the game after some input
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Play(String),
+    Resume,
+    Restart,
+    Leave,
+    Apply,
+    Quit,
+}
+
+/**
+ * The settings chosen in the options page, applied to every game started from
+ * the menu. The speed scales the time passing in the game, and with an
+ * opponent the computer takes every other shot
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub integrator: integrator::Method,
+    pub speed: f64,
+    pub field_view: field::FieldView,
+    pub trails: bool,
+    pub particles: bool,
+    pub diagnostics: bool,
+    pub debug: bool,
+    pub opponent: Option<ai::Difficulty>,
+}
+
+/**
+ * A level found in the levels directory
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelEntry {
+    pub name: String,
+    pub path: String,
+}
+
+/**
+ * The title screen, level selection, options and pause overlay, navigated
+ * with the arrow keys, enter and escape or by pointing and clicking. The menu
+ * is closed while playing
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Menu {
+    pub page: Option<Page>,
+    pub levels: Vec<LevelEntry>,
+    pub options: Options,
+    pub scores: scores::Scores,
+    pub message: Option<String>,
+    pub in_game: bool,
+    selected: usize,
+}
+
+// Speeds of the game to choose from
+const SPEEDS: [f64; 3] = [0.5, 1.0, 2.0];
+
+// Position of the first item, the largest distance between items and the
+// space the items may take up, in view coordinates
+const LEFT: f64 = 0.35;
+const TOP: f64 = 0.35;
+const SPACING: f64 = 0.05;
+const LIST_HEIGHT: f64 = 0.55;
+
+// Keys of the game listed in the controls page
+const CONTROLS: [(&'static str, &'static str); 15] = [("Space", "Aim, then shoot"),
+                                                       ("Escape", "Pause"),
+                                                       ("Mouse wheel, + and -", "Zoom"),
+                                                       ("Right drag, arrows", "Pan"),
+                                                       ("0", "Show the whole table"),
+                                                       ("I", "Next integrator"),
+                                                       ("F", "Gravity field view"),
+                                                       ("T", "Trails"),
+                                                       ("B", "Particles"),
+                                                       ("D", "Diagnostics"),
+                                                       ("F3", "Physics debug view"),
+                                                       ("L", "Log diagnostics"),
+                                                       ("Tab", "Physics tuning panel"),
+                                                       ("R", "Save replay"),
+                                                       ("P", "Save shot as SVG")];
+
+const BACKGROUND: Color = [0.0, 0.0, 0.0, 1.0];
+const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+const GRAY: Color = [0.6, 0.6, 0.6, 1.0];
+const YELLOW: Color = [1.0, 0.85, 0.2, 1.0];
+
+impl Options {
+    /**
+     * Creates the options matching a newly created game
+     */
+    pub fn new() -> Options {
+        Options {
+            integrator: integrator::Method::ExplicitEuler,
+            speed: 1.0,
+            field_view: field::FieldView::Hidden,
+            trails: true,
+            particles: true,
+            diagnostics: false,
+            debug: false,
+            opponent: None,
+        }
+    }
+
+    /**
+     * Applies the options to the given game
+     */
+    pub fn apply(&self, game: &mut game::Game) {
+        game.set_integrator(self.integrator);
+        game.set_field_view(self.field_view);
+        if game.trails_shown() != self.trails {
+            game.toggle_trails();
+        }
+        if game.particles_shown() != self.particles {
+            game.toggle_particles();
+        }
+        if game.diagnostics_shown() != self.diagnostics {
+            game.toggle_diagnostics();
+        }
+        if game.debug_shown() != self.debug {
+            game.toggle_debug();
+        }
+    }
+
+    /**
+     * Takes over the settings changed with keys while playing the given game
+     */
+    pub fn follow(&mut self, game: &game::Game) {
+        self.integrator = game.integrator();
+        self.field_view = game.field_view();
+        self.trails = game.trails_shown();
+        self.particles = game.particles_shown();
+        self.diagnostics = game.diagnostics_shown();
+        self.debug = game.debug_shown();
+    }
+
+    /**
+     * Returns the labels of the options, in the order of the options page
+     */
+    fn labels(&self) -> Vec<String> {
+        vec![format!("Integrator: {}", self.integrator.name()),
+             format!("Speed: {}x", self.speed),
+             format!("Gravity field: {}", self.field_view.name()),
+             format!("Trails: {}", on_off(self.trails)),
+             format!("Particles: {}", on_off(self.particles)),
+             format!("Diagnostics: {}", on_off(self.diagnostics)),
+             format!("Debug view: {}", on_off(self.debug)),
+             format!("Opponent: {}",
+                     self.opponent.map_or("off", |difficulty| difficulty.name()))]
+    }
+
+    /**
+     * Moves the option at the given index of the options page on to its next
+     * value
+     */
+    fn change(&mut self, index: usize) {
+        match index {
+            0 => self.integrator = self.integrator.next(),
+            1 => {
+                let current = SPEEDS.iter().position(|&speed| speed == self.speed).unwrap_or(0);
+                self.speed = SPEEDS[(current + 1) % SPEEDS.len()];
+            }
+            2 => self.field_view = self.field_view.next(),
+            3 => self.trails = !self.trails,
+            4 => self.particles = !self.particles,
+            5 => self.diagnostics = !self.diagnostics,
+            6 => self.debug = !self.debug,
+            _ => {
+                self.opponent = match self.opponent {
+                    None => Some(ai::Difficulty::Easy),
+                    Some(ai::Difficulty::Hard) => None,
+                    Some(difficulty) => Some(difficulty.next()),
+                }
+            }
+        }
+    }
+}
+
+impl Menu {
+    /**
+     * Creates a menu showing the title screen, listing the given levels with
+     * the given best scores
+     */
+    pub fn new(levels: Vec<LevelEntry>, scores: scores::Scores) -> Menu {
+        Menu {
+            page: Some(Page::Title),
+            levels: levels,
+            options: Options::new(),
+            scores: scores,
+            message: None,
+            in_game: false,
+            selected: 0,
+        }
+    }
+
+    /**
+     * Returns `true` if a page of the menu is shown, freezing the game
+     */
+    pub fn is_open(&self) -> bool {
+        self.page.is_some()
+    }
+
+    /**
+     * Closes the menu to play, or keep playing, a game
+     */
+    pub fn close(&mut self) {
+        self.page = None;
+        self.in_game = true;
+    }
+
+    /**
+     * Pauses the game being played, showing the pause overlay
+     */
+    pub fn pause(&mut self) {
+        self.in_game = true;
+        self.open(Page::Paused);
+    }
+
+    /**
+     * Records the final score of a game of the level at the given path and
+     * returns to the level selection. Returns `true` if the score is the best
+     * on the level so far
+     */
+    pub fn end_game(&mut self, level: &str, score: i32) -> bool {
+        let best = self.scores.record(level, score);
+        self.message = Some(if best {
+            format!("Final score: {}, a new best!", score)
+        } else {
+            format!("Final score: {}", score)
+        });
+        self.in_game = false;
+        self.open(Page::Levels);
+        best
+    }
+
+    /**
+     * Returns to the level selection showing the given error, such as a level
+     * failing to load
+     */
+    pub fn show_error(&mut self, err: String) {
+        self.message = Some(err);
+        self.in_game = false;
+        self.open(Page::Levels);
+    }
+
+    /**
+     * Handles the given input, returning what the game should do about it
+     */
+    pub fn input(&mut self, input: Input) -> Option<Action> {
+        let page = match self.page {
+            Some(page) => page,
+            None => return None,
+        };
+        let count = self.items().len();
+
+        match input {
+            Input::Up => self.selected = (self.selected + count - 1) % count,
+            Input::Down => self.selected = (self.selected + 1) % count,
+            Input::Left | Input::Right if page == Page::Options &&
+                                          self.selected < self.options.labels().len() => {
+                self.options.change(self.selected);
+                return Some(Action::Apply);
+            }
+            Input::Left | Input::Right => {}
+            Input::Select => return self.activate(),
+            Input::Back => return self.back(),
+            Input::Point(position) => {
+                if let Some(index) = self.item_at(&position) {
+                    self.selected = index;
+                }
+            }
+            Input::Click(position) => {
+                if let Some(index) = self.item_at(&position) {
+                    self.selected = index;
+                    return self.activate();
+                }
+            }
+        }
+        None
+    }
+
+    /**
+     * Renders the current page. Outside a game the page covers the whole
+     * window, while paused it is drawn over the table
+     */
+    pub fn render(&self, renderer: &mut Renderer) {
+        let page = match self.page {
+            Some(page) => page,
+            None => return,
+        };
+        if !self.in_game {
+            renderer.clear(BACKGROUND);
+        }
+
+        let heading = match page {
+            Page::Title => "Rymdbiljard",
+            Page::Levels => "Select level",
+            Page::Options => "Options",
+            Page::Controls => "Controls",
+            Page::Paused => "Paused",
+        };
+        renderer.text(WHITE, 40, Point2::new(LEFT, 0.22), heading);
+
+        if page == Page::Title {
+            renderer.text(GRAY, 14, Point2::new(LEFT, 0.27), "Billiards around blackholes");
+        }
+        if page == Page::Levels {
+            if let Some(ref message) = self.message {
+                renderer.text(YELLOW, 16, Point2::new(LEFT, 0.28), message);
+            }
+        }
+        if page == Page::Controls {
+            for (i, &(key, action)) in CONTROLS.iter().enumerate() {
+                let y = 0.3 + 0.03 * i as f64;
+                renderer.text(YELLOW, 14, Point2::new(0.2, y), key);
+                renderer.text(WHITE, 14, Point2::new(0.5, y), action);
+            }
+        }
+
+        let spacing = self.spacing();
+        for (i, item) in self.items().iter().enumerate() {
+            let position = self.item_position(i, spacing);
+            if i == self.selected {
+                renderer.text(YELLOW, 20, position, &format!("> {}", item));
+            } else {
+                renderer.text(WHITE, 20, position, item);
+            }
+        }
+
+        renderer.text(GRAY,
+                      12,
+                      Point2::new(LEFT, 0.97),
+                      "Arrow keys and enter or the mouse to choose, escape to go back");
+    }
+
+    /**
+     * Shows the given page with its first item selected
+     */
+    fn open(&mut self, page: Page) {
+        self.page = Some(page);
+        self.selected = 0;
+    }
+
+    /**
+     * Returns the labels of the items of the current page
+     */
+    fn items(&self) -> Vec<String> {
+        let labels = match self.page {
+            Some(Page::Levels) => {
+                let mut items: Vec<String> = self.levels
+                    .iter()
+                    .map(|level| match self.scores.best(&level.path) {
+                        Some(best) => format!("{}  (best {})", level.name, best),
+                        None => level.name.clone(),
+                    })
+                    .collect();
+                items.push(String::from("Back"));
+                return items;
+            }
+            Some(Page::Options) => {
+                let mut items = self.options.labels();
+                items.push(String::from("Controls"));
+                items.push(String::from("Back"));
+                return items;
+            }
+            Some(Page::Controls) => vec!["Back"],
+            Some(Page::Paused) => vec!["Resume", "Restart", "Options", "Quit to title"],
+            Some(Page::Title) | None => vec!["Play", "Options", "Quit"],
+        };
+        labels.iter().map(|&label| String::from(label)).collect()
+    }
+
+    /**
+     * Acts on the selected item of the current page
+     */
+    fn activate(&mut self) -> Option<Action> {
+        let index = self.selected;
+        match self.page {
+            Some(Page::Title) => {
+                match index {
+                    0 => self.open(Page::Levels),
+                    1 => self.open(Page::Options),
+                    _ => return Some(Action::Quit),
+                }
+            }
+            Some(Page::Levels) => {
+                if index == self.levels.len() {
+                    return self.back();
+                }
+                let path = self.levels[index].path.clone();
+                self.message = None;
+                self.close();
+                return Some(Action::Play(path));
+            }
+            Some(Page::Options) => {
+                let count = self.options.labels().len();
+                if index < count {
+                    self.options.change(index);
+                    return Some(Action::Apply);
+                } else if index == count {
+                    self.open(Page::Controls);
+                } else {
+                    return self.back();
+                }
+            }
+            Some(Page::Controls) => return self.back(),
+            Some(Page::Paused) => {
+                match index {
+                    0 => {
+                        self.close();
+                        return Some(Action::Resume);
+                    }
+                    1 => {
+                        self.close();
+                        return Some(Action::Restart);
+                    }
+                    2 => self.open(Page::Options),
+                    _ => {
+                        self.in_game = false;
+                        self.open(Page::Title);
+                        return Some(Action::Leave);
+                    }
+                }
+            }
+            None => {}
+        }
+        None
+    }
+
+    /**
+     * Goes back to the page the current one was reached from
+     */
+    fn back(&mut self) -> Option<Action> {
+        match self.page {
+            Some(Page::Title) => return Some(Action::Quit),
+            Some(Page::Levels) => self.open(Page::Title),
+            Some(Page::Options) => {
+                let page = if self.in_game { Page::Paused } else { Page::Title };
+                self.open(page);
+            }
+            Some(Page::Controls) => {
+                self.open(Page::Options);
+                self.selected = self.options.labels().len();
+            }
+            Some(Page::Paused) => {
+                self.close();
+                return Some(Action::Resume);
+            }
+            None => {}
+        }
+        None
+    }
+
+    /**
+     * Returns the distance between items, closer together on long lists
+     */
+    fn spacing(&self) -> f64 {
+        SPACING.min(LIST_HEIGHT / self.items().len() as f64)
+    }
+
+    /**
+     * Returns the start of the baseline of the item at the given index
+     */
+    fn item_position(&self, index: usize, spacing: f64) -> Point2<f64> {
+        let top = if self.page == Some(Page::Controls) { 0.8 } else { TOP };
+        Point2::new(LEFT, top + spacing * index as f64)
+    }
+
+    /**
+     * Returns the index of the item at the given position, if any
+     */
+    fn item_at(&self, position: &Point2<f64>) -> Option<usize> {
+        if position.x < LEFT - 0.05 || position.x > 1.0 {
+            return None;
+        }
+        let spacing = self.spacing();
+        (0..self.items().len()).position(|index| {
+            let baseline = self.item_position(index, spacing).y;
+            position.y > baseline - 0.7 * spacing && position.y <= baseline + 0.3 * spacing
+        })
+    }
+}
+
+/**
+ * Lists the level files in the given directory, sorted by name
+ */
+pub fn find_levels(directory: &Path) -> Result<Vec<LevelEntry>, String> {
+    let entries = try!(fs::read_dir(directory)
+        .map_err(|err| format!("could not read {}: {}", directory.display(), err)));
+
+    let mut levels = Vec::new();
+    for entry in entries {
+        let path = try!(entry.map_err(|err| format!("could not read {}: {}",
+                                                    directory.display(),
+                                                    err)))
+            .path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some("lvl") {
+            continue;
+        }
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        levels.push(LevelEntry {
+            name: name,
+            path: path.to_string_lossy().into_owned(),
+        });
+    }
+    levels.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(levels)
+}
+
+/**
+ * Returns the word shown for a setting being on or off
+ */
+fn on_off(on: bool) -> &'static str {
+    if on { "on" } else { "off" }
+}
+
+#[cfg(test)]
+fn test_menu() -> Menu {
+    let levels = vec![LevelEntry {
+                          name: String::from("classic"),
+                          path: String::from("levels/classic.lvl"),
+                      },
+                      LevelEntry {
+                          name: String::from("zones"),
+                          path: String::from("levels/zones.lvl"),
+                      }];
+    Menu::new(levels, scores::Scores::new())
+}
+
+#[test]
+fn test_navigation() {
+    let mut menu = test_menu();
+    assert_eq!(menu.input(Input::Up), None);
+    assert_eq!(menu.selected, 2);
+    assert_eq!(menu.input(Input::Down), None);
+    assert_eq!(menu.input(Input::Select), None);
+    assert_eq!(menu.page, Some(Page::Levels));
+
+    menu.input(Input::Down);
+    assert_eq!(menu.input(Input::Select),
+               Some(Action::Play(String::from("levels/zones.lvl"))));
+    assert!(!menu.is_open());
+
+    // Pausing and leaving the game returns to the title screen
+    menu.pause();
+    menu.input(Input::Up);
+    assert_eq!(menu.input(Input::Select), Some(Action::Leave));
+    assert_eq!(menu.page, Some(Page::Title));
+    assert!(!menu.in_game);
+    assert_eq!(menu.input(Input::Back), Some(Action::Quit));
+}
+
+#[test]
+fn test_options() {
+    let mut menu = test_menu();
+    menu.pause();
+    menu.input(Input::Down);
+    menu.input(Input::Down);
+    menu.input(Input::Select);
+    assert_eq!(menu.page, Some(Page::Options));
+
+    menu.input(Input::Down);
+    assert_eq!(menu.input(Input::Right), Some(Action::Apply));
+    assert_eq!(menu.options.speed, 2.0);
+    assert_eq!(menu.input(Input::Select), Some(Action::Apply));
+    assert_eq!(menu.options.speed, 0.5);
+
+    // Back from the options while playing goes to the pause overlay
+    assert_eq!(menu.input(Input::Back), None);
+    assert_eq!(menu.page, Some(Page::Paused));
+    assert_eq!(menu.input(Input::Back), Some(Action::Resume));
+}
+
+#[test]
+fn test_mouse() {
+    let mut menu = test_menu();
+    let options = Point2::new(0.4, TOP + SPACING - 0.01);
+    menu.input(Input::Point(options));
+    assert_eq!(menu.selected, 1);
+    menu.input(Input::Point(Point2::new(0.1, TOP)));
+    assert_eq!(menu.selected, 1);
+    assert_eq!(menu.input(Input::Click(Point2::new(0.5, TOP + 2.0 * SPACING))),
+               Some(Action::Quit));
+    assert_eq!(menu.input(Input::Click(Point2::new(0.5, TOP))), None);
+    assert_eq!(menu.page, Some(Page::Levels));
+}
+
+#[test]
+fn test_end_game() {
+    let mut menu = test_menu();
+    assert!(menu.end_game("levels/classic.lvl", 40));
+    assert!(!menu.end_game("levels/classic.lvl", 10));
+    assert_eq!(menu.page, Some(Page::Levels));
+    assert_eq!(menu.items()[0], "classic  (best 40)");
+}
+
+#[test]
+fn test_find_levels() {
+    let levels = find_levels(Path::new("levels")).unwrap();
+    assert!(levels.iter().any(|level| level.name == "classic"));
+    assert!(levels.windows(2).all(|pair| pair[0].name <= pair[1].name));
+}
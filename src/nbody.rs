@@ -0,0 +1,349 @@
+use na::{Point2, Vector2};
+use poolball;
+use physics;
+
+/**
+ * Settings for gravity between the poolballs themselves. The pull between two
+ * poolballs is softened so that it stays finite as they get close, and far
+ * away groups of poolballs are approximated by their center of mass when they
+ * appear smaller than `theta` (the Barnes–Hut opening angle). A `theta` of
+ * zero always calculates the exact pull from every poolball
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct NBody {
+    pub strength: f64,
+    pub softening: f64,
+    pub theta: f64,
+}
+
+// Ball counts below this are always calculated exactly, the tree not paying
+// off for so few poolballs
+const EXACT_LIMIT: usize = 16;
+
+// Depth at which cells stop being divided, so that poolballs at the same
+// position do not divide the tree forever
+const MAX_DEPTH: usize = 24;
+
+/**
+ * A square cell of the Barnes–Hut quadtree, holding the total mass and center
+ * of mass of all poolballs inside it
+ */
+struct Cell {
+    middle: Point2<f64>,
+    half_size: f64,
+    mass: f64,
+    center_of_mass: Point2<f64>,
+    children: Vec<Cell>,
+    indices: Vec<usize>,
+}
+
+/**
+ * The pull of poolballs held at their positions, from which the acceleration
+ * on one of them can be calculated wherever it moves during a step
+ */
+pub struct Attraction<'a> {
+    n_body: &'a NBody,
+    balls: &'a Vec<poolball::Poolball>,
+    tree: Option<Cell>,
+}
+
+impl NBody {
+    /**
+     * Creates new settings with the given gravity strength, softening and
+     * opening angle
+     */
+    pub fn new(strength: f64, softening: f64, theta: f64) -> NBody {
+        NBody {
+            strength: strength,
+            softening: softening,
+            theta: theta,
+        }
+    }
+
+    /**
+     * Returns the pull of the given poolballs, building the tree for them once
+     * unless the pull is calculated exactly
+     */
+    pub fn attraction<'a>(&'a self, balls: &'a Vec<poolball::Poolball>) -> Attraction<'a> {
+        let tree = if self.theta <= 0.0 || balls.len() < EXACT_LIMIT {
+            None
+        } else {
+            Some(build_tree(balls))
+        };
+        Attraction {
+            n_body: self,
+            balls: balls,
+            tree: tree,
+        }
+    }
+
+    /**
+     * Calculates the acceleration every poolball feels from all other
+     * poolballs, in the same order as the given poolballs
+     */
+    #[cfg(test)]
+    pub fn calculate_accelerations(&self, balls: &Vec<poolball::Poolball>) -> Vec<Vector2<f64>> {
+        let attraction = self.attraction(balls);
+        balls.iter()
+            .enumerate()
+            .map(|(index, ball)| attraction.acceleration(index, &ball.position))
+            .collect()
+    }
+
+    /**
+     * Calculates the acceleration a poolball at the given position would feel
+     * from all the given poolballs
+     */
+    pub fn acceleration_at(&self,
+                           balls: &Vec<poolball::Poolball>,
+                           position: &Point2<f64>)
+                           -> Vector2<f64> {
+        let mut result = Vector2::new(0.0, 0.0);
+        for ball in balls {
+            result += self.pull(position, &ball.position, ball.mass);
+        }
+        result
+    }
+
+    /**
+     * Calculates the softened gravitational potential of all the given
+     * poolballs at the given position
+     */
+    pub fn potential_at(&self, balls: &Vec<poolball::Poolball>, position: &Point2<f64>) -> f64 {
+        balls.iter().map(|ball| self.potential(position, &ball.position, ball.mass)).sum()
+    }
+
+    /**
+     * Calculates the softened gravitational potential at the given position of
+     * a poolball of the given mass at the other position
+     */
+    pub fn potential(&self, at: &Point2<f64>, from: &Point2<f64>, mass: f64) -> f64 {
+        let offset = *from - *at;
+        let distance_squared = offset.x * offset.x + offset.y * offset.y;
+        let softened = (distance_squared + self.softening * self.softening).sqrt();
+        if softened == 0.0 {
+            return 0.0;
+        }
+        -self.strength * mass / softened
+    }
+
+    /**
+     * Calculates the exact acceleration on the poolball with the given index
+     * at the given position
+     */
+    fn exact_acceleration(&self,
+                          balls: &Vec<poolball::Poolball>,
+                          index: usize,
+                          position: &Point2<f64>)
+                          -> Vector2<f64> {
+        let mut result = Vector2::new(0.0, 0.0);
+        for (other_index, other) in balls.iter().enumerate() {
+            if other_index != index {
+                result += self.pull(position, &other.position, other.mass);
+            }
+        }
+        result
+    }
+
+    /**
+     * Adds the acceleration from the poolballs in the given cell to the
+     * acceleration of the poolball with the given index at the given position,
+     * opening up cells that are too close to be approximated
+     */
+    fn accumulate(&self,
+                  cell: &Cell,
+                  balls: &Vec<poolball::Poolball>,
+                  index: usize,
+                  position: &Point2<f64>,
+                  acceleration: &mut Vector2<f64>) {
+        // Leaves are always summed up exactly
+        if cell.children.is_empty() {
+            for &other in &cell.indices {
+                if other != index {
+                    let ref other = balls[other];
+                    *acceleration += self.pull(position, &other.position, other.mass);
+                }
+            }
+            return;
+        }
+
+        // Far away cells not containing the poolball are approximated. The
+        // tree holds the poolball where it started, which the cell must not
+        // contain for the poolball not to pull itself
+        let offset = cell.center_of_mass - *position;
+        let distance = offset.x.hypot(offset.y);
+        if !cell.contains(position) && !cell.contains(&balls[index].position) &&
+           2.0 * cell.half_size < self.theta * distance {
+            *acceleration += self.pull(position, &cell.center_of_mass, cell.mass);
+            return;
+        }
+
+        for child in &cell.children {
+            self.accumulate(child, balls, index, position, acceleration);
+        }
+    }
+
+    /**
+     * Returns the softened acceleration towards the given mass
+     */
+    fn pull(&self, from: &Point2<f64>, to: &Point2<f64>, mass: f64) -> Vector2<f64> {
+        let offset = *to - *from;
+        let distance_squared = offset.x * offset.x + offset.y * offset.y;
+        let softened = (distance_squared + self.softening * self.softening).sqrt();
+        if softened == 0.0 {
+            return Vector2::new(0.0, 0.0);
+        }
+
+        // Plummer softening: strength * mass * offset / (distance^2 + softening^2)^(3/2)
+        offset * (physics::gravity_acceleration(self.strength * mass, softened) / softened)
+    }
+}
+
+impl<'a> Attraction<'a> {
+    /**
+     * Calculates the acceleration the poolball with the given index feels from
+     * all other poolballs when at the given position
+     */
+    pub fn acceleration(&self, index: usize, position: &Point2<f64>) -> Vector2<f64> {
+        match self.tree {
+            Some(ref tree) => {
+                let mut acceleration = Vector2::new(0.0, 0.0);
+                self.n_body.accumulate(tree, self.balls, index, position, &mut acceleration);
+                acceleration
+            }
+            None => self.n_body.exact_acceleration(self.balls, index, position),
+        }
+    }
+}
+
+impl Cell {
+    /**
+     * Returns `true` if the given position lies inside the cell
+     */
+    fn contains(&self, position: &Point2<f64>) -> bool {
+        (position.x - self.middle.x).abs() <= self.half_size &&
+        (position.y - self.middle.y).abs() <= self.half_size
+    }
+}
+
+/**
+ * Builds a quadtree around all the given poolballs
+ */
+fn build_tree(balls: &Vec<poolball::Poolball>) -> Cell {
+    let mut min = Point2::new(0.0, 0.0);
+    let mut max = Point2::new(1.0, 1.0);
+    for ball in balls {
+        min.x = min.x.min(ball.position.x);
+        min.y = min.y.min(ball.position.y);
+        max.x = max.x.max(ball.position.x);
+        max.y = max.y.max(ball.position.y);
+    }
+
+    let middle = Point2::new((min.x + max.x) / 2.0, (min.y + max.y) / 2.0);
+    let half_size = (max.x - min.x).max(max.y - min.y) / 2.0;
+    build_cell(balls, (0..balls.len()).collect(), middle, half_size, 0)
+}
+
+/**
+ * Builds the cell with the given bounds holding the poolballs with the given
+ * indices, dividing it into four quadrants while it holds more than one
+ */
+fn build_cell(balls: &Vec<poolball::Poolball>,
+              indices: Vec<usize>,
+              middle: Point2<f64>,
+              half_size: f64,
+              depth: usize)
+              -> Cell {
+    let mass = indices.iter().fold(0.0, |sum, &index| sum + balls[index].mass);
+    let mut center_of_mass = Point2::new(0.0, 0.0);
+    if mass > 0.0 {
+        for &index in &indices {
+            let ref ball = balls[index];
+            center_of_mass.x += ball.position.x * ball.mass / mass;
+            center_of_mass.y += ball.position.y * ball.mass / mass;
+        }
+    }
+
+    let mut cell = Cell {
+        middle: middle,
+        half_size: half_size,
+        mass: mass,
+        center_of_mass: center_of_mass,
+        children: Vec::new(),
+        indices: Vec::new(),
+    };
+
+    if indices.len() <= 1 || depth >= MAX_DEPTH {
+        cell.indices = indices;
+        return cell;
+    }
+
+    // Sort the poolballs into the four quadrants
+    let mut quadrants = vec![Vec::new(), Vec::new(), Vec::new(), Vec::new()];
+    for index in indices {
+        let ref position = balls[index].position;
+        let quadrant = (position.x >= middle.x) as usize + 2 * (position.y >= middle.y) as usize;
+        quadrants[quadrant].push(index);
+    }
+
+    let quarter = half_size / 2.0;
+    for (quadrant, indices) in quadrants.into_iter().enumerate() {
+        if indices.is_empty() {
+            continue;
+        }
+        let x = if quadrant % 2 == 1 { middle.x + quarter } else { middle.x - quarter };
+        let y = if quadrant / 2 == 1 { middle.y + quarter } else { middle.y - quarter };
+        cell.children.push(build_cell(balls, indices, Point2::new(x, y), quarter, depth + 1));
+    }
+
+    cell
+}
+
+#[test]
+fn test_two_balls_attract() {
+    let nbody = NBody::new(1.0, 0.0, 0.0);
+    let mut light = poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Red);
+    light.mass = 1.0;
+    let mut heavy = poolball::Poolball::new(Point2::new(2.0, 0.0), poolball::BallType::Red);
+    heavy.mass = 2.0;
+
+    let accelerations = nbody.calculate_accelerations(&vec![light, heavy]);
+    assert!((accelerations[0].x - 0.5).abs() < 0.0001);
+    assert!((accelerations[1].x + 0.25).abs() < 0.0001);
+
+    // Equal and opposite forces conserve momentum
+    assert!((accelerations[0].x * 1.0 + accelerations[1].x * 2.0).abs() < 0.0001);
+}
+
+#[test]
+fn test_softening() {
+    let nbody = NBody::new(1.0, 0.1, 0.0);
+    let balls = vec![poolball::Poolball::new(Point2::new(0.5, 0.5), poolball::BallType::Red),
+                     poolball::Poolball::new(Point2::new(0.5, 0.5), poolball::BallType::Red)];
+
+    // Balls on top of each other feel no pull instead of an infinite one
+    let accelerations = nbody.calculate_accelerations(&balls);
+    assert_eq!(accelerations[0], Vector2::new(0.0, 0.0));
+}
+
+#[test]
+fn test_barnes_hut_matches_exact() {
+    let mut balls = Vec::new();
+    for i in 0..10 {
+        for j in 0..10 {
+            // Slightly skewed grid so that the pulls do not cancel out exactly
+            let x = 0.05 + 0.09 * i as f64;
+            let y = 0.05 + 0.09 * j as f64 + 0.003 * i as f64;
+            balls.push(poolball::Poolball::new(Point2::new(x, y), poolball::BallType::Red));
+        }
+    }
+
+    let exact = NBody::new(1.0, 0.01, 0.0).calculate_accelerations(&balls);
+    let approximate = NBody::new(1.0, 0.01, 0.5).calculate_accelerations(&balls);
+    let largest = exact.iter().fold(0.0_f64, |largest, a| largest.max(a.x.hypot(a.y)));
+
+    for (exact, approximate) in exact.iter().zip(approximate.iter()) {
+        let error = *exact - *approximate;
+        assert!(error.x.hypot(error.y) <= 0.05 * largest);
+    }
+}
@@ -0,0 +1,148 @@
+use na::Point2;
+
+/**
+ * A single line of a level or catalogue file, split into the kind of entity it
+ * describes, its positional arguments and its `key=value` options
+ */
+pub struct Line<'a> {
+    pub number: usize,
+    pub kind: &'a str,
+    pub arguments: Vec<&'a str>,
+    pub options: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Line<'a> {
+    /**
+     * Returns an error unless exactly `count` arguments were given
+     */
+    pub fn expect_arguments(&self, count: usize) -> Result<(), String> {
+        if self.arguments.len() != count {
+            return Err(format!("{} takes {} arguments but {} were given",
+                               self.kind,
+                               count,
+                               self.arguments.len()));
+        }
+        Ok(())
+    }
+
+    /**
+     * Returns an error if any options were given
+     */
+    pub fn expect_no_options(&self) -> Result<(), String> {
+        match self.options.first() {
+            Some(&(key, _)) => Err(self.unknown_option(key)),
+            None => Ok(()),
+        }
+    }
+
+    /**
+     * Returns the error message for an option the entity does not support
+     */
+    pub fn unknown_option(&self, key: &str) -> String {
+        format!("unknown option '{}' for {}", key, self.kind)
+    }
+
+    /**
+     * Parses all positional arguments as numbers
+     */
+    pub fn numbers(&self) -> Result<Vec<f64>, String> {
+        let mut numbers = Vec::new();
+        for word in &self.arguments {
+            numbers.push(try!(parse_number(word)));
+        }
+        Ok(numbers)
+    }
+}
+
+/**
+ * Splits the given source into lines, skipping comments starting with `#` and
+ * empty lines
+ */
+pub fn lines(source: &str) -> Vec<Line> {
+    let mut result = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let kind = words.next().unwrap();
+        let (arguments, options): (Vec<&str>, Vec<&str>) =
+            words.partition(|word| !word.contains('='));
+
+        result.push(Line {
+            number: number + 1,
+            kind: kind,
+            arguments: arguments,
+            options: options.iter()
+                .map(|option| {
+                    let mut parts = option.splitn(2, '=');
+                    (parts.next().unwrap(), parts.next().unwrap())
+                })
+                .collect(),
+        });
+    }
+
+    result
+}
+
+pub fn parse_number(word: &str) -> Result<f64, String> {
+    word.parse::<f64>().map_err(|_| format!("'{}' is not a number", word))
+}
+
+pub fn parse_integer(word: &str) -> Result<i32, String> {
+    word.parse::<i32>().map_err(|_| format!("'{}' is not an integer", word))
+}
+
+pub fn parse_bool(word: &str) -> Result<bool, String> {
+    word.parse::<bool>().map_err(|_| format!("'{}' is neither true nor false", word))
+}
+
+/**
+ * Parses a point written as `<x>,<y>`
+ */
+pub fn parse_point(word: &str) -> Result<Point2<f64>, String> {
+    let parts: Vec<&str> = word.split(',').collect();
+    if parts.len() != 2 {
+        return Err(format!("'{}' is not a point", word));
+    }
+    Ok(Point2::new(try!(parse_number(parts[0])), try!(parse_number(parts[1]))))
+}
+
+/**
+ * Parses a color written as `<red>,<green>,<blue>,<alpha>`
+ */
+pub fn parse_color(word: &str) -> Result<[f32; 4], String> {
+    let parts: Vec<&str> = word.split(',').collect();
+    if parts.len() != 4 {
+        return Err(format!("'{}' is not a color", word));
+    }
+
+    let mut color = [0.0; 4];
+    for (channel, part) in color.iter_mut().zip(parts) {
+        *channel = try!(parse_number(part)) as f32;
+    }
+    Ok(color)
+}
+
+#[test]
+fn test_lines() {
+    let lines = lines("# comment\n\nball red 0.1 0.2 velocity=1,2 # trailing\n");
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].number, 3);
+    assert_eq!(lines[0].kind, "ball");
+    assert_eq!(lines[0].arguments, vec!["red", "0.1", "0.2"]);
+    assert_eq!(lines[0].options, vec![("velocity", "1,2")]);
+}
+
+#[test]
+fn test_parse_values() {
+    assert_eq!(parse_point("0.5,-1"), Ok(Point2::new(0.5, -1.0)));
+    assert!(parse_point("0.5").is_err());
+    assert_eq!(parse_color("1,0,0.5,1"), Ok([1.0, 0.0, 0.5, 1.0]));
+    assert!(parse_color("1,0,0").is_err());
+    assert_eq!(parse_bool("true"), Ok(true));
+    assert!(parse_integer("1.5").is_err());
+}
@@ -1,66 +1,115 @@
 use na::{Vector2, Point2, Norm, FloatPoint, Dot};
 use poolball;
 use blackhole;
+use wormhole;
 use math;
 use std::f64;
 
-const FRICTION: f64 = 0.1;
+// Impulse given to a poolball right next to an exploding poolball
+const BLAST_IMPULSE: f64 = 0.05;
+
+// Gap within which two poolballs are considered to be touching
+const CONTACT_TOLERANCE: f64 = 0.000001;
+
+// Poolballs closing in on each other slower than this come to rest against
+// each other instead of bouncing
+const RESTING_SPEED: f64 = 0.001;
+
+// Passes over the impacts when solving them together
+const CONTACT_ITERATIONS: usize = 16;
+
+// Passes over the overlapping poolballs when pushing them apart
+const SEPARATION_ITERATIONS: usize = 32;
 
 /**
  * Calculates the gravity acceleration from an object with given mass at given
- * distance
+ * distance, with a gravity constant of one
  */
-fn gravity_acceleration(mass: f64, distance: f64) -> f64 {
-    // let gravity_constant = 6.673 * (10.0_f32).powf(-11.0);
-    // We use gravity_constant = 1 for now, as the actual constant would require very high mass
-    let gravity_constant = 1.0;
-    (gravity_constant * mass) / distance.powf(2.0)
+pub fn gravity_acceleration(mass: f64, distance: f64) -> f64 {
+    mass / distance.powf(2.0)
 }
 
 /**
  * Calculates the acceleration acting upon the ball from the given black holes
+ * with the given gravity constant. Balls immune to blackholes feel no
+ * acceleration
  */
 pub fn calculate_gravity(blackholes: &Vec<blackhole::Blackhole>,
-                         ball: &poolball::Poolball)
+                         ball: &poolball::Poolball,
+                         gravity: f64)
                          -> Vector2<f64> {
+    if ball.immune {
+        return Vector2::new(0.0, 0.0);
+    }
+    gravity_at(blackholes, &ball.position, gravity)
+}
+
+/**
+ * Calculates the acceleration from the given black holes at the given position
+ * with the given gravity constant
+ */
+pub fn gravity_at(blackholes: &Vec<blackhole::Blackhole>,
+                  position: &Point2<f64>,
+                  gravity: f64)
+                  -> Vector2<f64> {
     let mut result = Vector2::new(0.0, 0.0);
     // Calculate each acceleration vector individually and add them to the reuslt
     for blackhole in blackholes {
-        let distance = ball.position.distance(&blackhole.position);
+        let distance = position.distance(&blackhole.position);
 
-        if distance < blackhole.reach {
-            let direction_vector = blackhole.position.to_vector() - ball.position.to_vector();
+        if distance < blackhole.reach && distance > 0.0 {
+            let direction_vector = blackhole.position.to_vector() - position.to_vector();
             let normalized_vector = direction_vector.normalize();
-            result += normalized_vector * gravity_acceleration(blackhole.mass, distance);
+            result += normalized_vector * gravity_acceleration(gravity * blackhole.mass, distance);
         }
     }
     return result;
 }
 
+/**
+ * Calculates the gravitational potential of the given black holes at the given
+ * position with the given gravity constant. The pull of a blackhole ends at its
+ * reach, where its potential is zero, and within its core the potential at the
+ * edge of the core is used
+ */
+pub fn gravity_potential(blackholes: &Vec<blackhole::Blackhole>,
+                         position: &Point2<f64>,
+                         gravity: f64)
+                         -> f64 {
+    let mut result = 0.0;
+    for blackhole in blackholes {
+        let distance = position.distance(&blackhole.position).max(blackhole.radius);
+        if distance < blackhole.reach && distance > 0.0 {
+            let mass = gravity * blackhole.mass;
+            result += mass / blackhole.reach - mass / distance;
+        }
+    }
+    result
+}
+
 /**
  * Calculates the direction and size of the friction acceleration on the given
- * ball
+ * ball, slowing it down by the given speed per second
  */
-pub fn calculate_friction(poolball: &poolball::Poolball) -> Vector2<f64> {
+pub fn calculate_friction(poolball: &poolball::Poolball, friction: f64) -> Vector2<f64> {
     if poolball.velocity == Vector2::new(0.0, 0.0) {
         return Vector2::new(0.0, 0.0);
     }
 
     // Make sure friction is not greater than speed
     let speed = math::calc_magnitude(poolball.velocity);
-    if speed <= FRICTION {
+    if speed <= friction {
         return -1.0 * poolball.velocity;
     }
 
     let ball_direction = poolball.velocity.normalize();
-    let friction = -1.0 * ball_direction * FRICTION;
-
-    return friction;
+    return -1.0 * ball_direction * friction;
 }
 
 /**
-* Calculates the new velocities for 2 colliding poolballs after time delta_time,
-* (delta_time is in seconds)
+* Calculates the new velocities for 2 colliding poolballs. The collision is
+* elastic for balls with a restitution of 1, otherwise the combined restitution
+* is the geometric mean of the two
 */
 pub fn ball_ball_collision(ball1: &mut poolball::Poolball, ball2: &mut poolball::Poolball) {
 
@@ -74,11 +123,13 @@ pub fn ball_ball_collision(ball1: &mut poolball::Poolball, ball2: &mut poolball:
 
     // Calculate a common component in the formula
     // optimizedP
-    let optimized_p: f64 = (2.0 * (a1 - a2)) / (ball1.mass + ball2.mass);
+    let restitution = (ball1.restitution * ball2.restitution).sqrt();
+    let optimized_p: f64 = ((1.0 + restitution) * (a1 - a2)) / (ball1.mass + ball2.mass);
 
-    // Calculate the new movementvector for the balls
-    let new_v1 = ball1.velocity - optimized_p * ball1.mass * n;
-    let new_v2 = ball2.velocity + optimized_p * ball2.mass * n;
+    // Calculate the new movementvector for the balls, the lighter ball
+    // receiving the larger change in velocity
+    let new_v1 = ball1.velocity - optimized_p * ball2.mass * n;
+    let new_v2 = ball2.velocity + optimized_p * ball1.mass * n;
 
     // Set the new velocities for the balls
     ball1.velocity = new_v1;
@@ -97,12 +148,26 @@ pub fn time_to_ball_ball_collision(a: &poolball::Poolball, b: &poolball::Poolbal
     // let mut dist = a.position.distance(&b.position);
     let sum_radii = a.radius + b.radius;
 
-    // Normalize the movement vector
-    let normalized_vector = move_vec.normalize();
-
     // Calculate the direction between the two balls
     let direction_between = (b.position - a.position) as Vector2<f64>;
 
+    // Touching or overlapping balls collide right away if they are closing
+    // in on each other, and not at all if they are moving apart
+    if math::calc_magnitude(direction_between) <= sum_radii {
+        if Vector2::dot(&direction_between, &move_vec) > 0.0 {
+            return 0.0;
+        }
+        return f64::INFINITY;
+    }
+
+    // Balls moving in parallel never meet
+    if move_vec == Vector2::new(0.0, 0.0) {
+        return f64::INFINITY;
+    }
+
+    // Normalize the movement vector
+    let normalized_vector = move_vec.normalize();
+
     // Calculate the dot product of the normalied vector and the direction
     let dot_product = Vector2::dot(&direction_between, &normalized_vector);
 
@@ -130,7 +195,7 @@ pub fn time_to_ball_ball_collision(a: &poolball::Poolball, b: &poolball::Poolbal
     let movement_distance = dot_product - t.sqrt();
     let move_vec_magnitude = math::calc_magnitude(move_vec);
 
-    return movement_distance / move_vec_magnitude;
+    return (movement_distance / move_vec_magnitude).max(0.0);
 }
 
 /**
@@ -144,34 +209,270 @@ pub fn time_to_wall_collision(ball: &poolball::Poolball) -> f64 {
     let vertical_distance_to_wall = (ball.position.y - (ball.velocity.y.signum() / 2.0 + 0.5))
         .abs() - ball.radius;
 
-    let x_time_ratio = horizontal_distance_to_wall / ball.velocity.x.abs();
-    let y_time_ratio = vertical_distance_to_wall / ball.velocity.y.abs();
+    // Balls not moving towards a wall never hit it, and balls already
+    // touching or overlapping a wall hit it right away
+    let x_time_ratio = if ball.velocity.x == 0.0 {
+        f64::INFINITY
+    } else {
+        (horizontal_distance_to_wall / ball.velocity.x.abs()).max(0.0)
+    };
+    let y_time_ratio = if ball.velocity.y == 0.0 {
+        f64::INFINITY
+    } else {
+        (vertical_distance_to_wall / ball.velocity.y.abs()).max(0.0)
+    };
 
     let min_time = x_time_ratio.min(y_time_ratio);
     return min_time;
 }
 
+/**
+ * Returns the time until the center of the given poolball enters the given
+ * portal. Infinity is returned if the poolball never enters the portal or if
+ * it is already inside it
+ */
+pub fn time_to_portal_entry(ball: &poolball::Poolball, portal: &wormhole::Portal) -> f64 {
+    let offset = portal.position - ball.position;
+    let distance_squared = offset.dot(&offset);
+    let radius_squared = portal.radius * portal.radius;
+
+    // Balls already inside have to leave before they can enter again
+    if distance_squared <= radius_squared {
+        return f64::INFINITY;
+    }
+
+    // Moving away from or not moving towards the portal
+    let speed_squared = ball.velocity.dot(&ball.velocity);
+    let along = offset.dot(&ball.velocity);
+    if speed_squared == 0.0 || along <= 0.0 {
+        return f64::INFINITY;
+    }
+
+    // Solve |offset - velocity * t| = radius for the smallest t
+    let discriminant = along * along - speed_squared * (distance_squared - radius_squared);
+    if discriminant < 0.0 {
+        return f64::INFINITY;
+    }
+
+    (along - discriminant.sqrt()) / speed_squared
+}
+
 /**
  * Recalculates the new velocities for the ball given collision with a wall
  */
 pub fn ball_wall_collision(ball: &mut poolball::Poolball) {
-    // will be the distance to the wall in the x direction the ball is moving
-    let horizontal_distance_to_wall =
-        ((ball.position.x - (ball.velocity.x.signum() / 2.0 + 0.5)).abs() - ball.radius).abs();
+    // will be the distance to the wall in the x direction the ball is moving,
+    // walls the ball is not moving towards being infinitely far away
+    let horizontal_distance_to_wall = if ball.velocity.x == 0.0 {
+        f64::INFINITY
+    } else {
+        ((ball.position.x - (ball.velocity.x.signum() / 2.0 + 0.5)).abs() - ball.radius).abs()
+    };
     // will be the distance to the wall in the y direction the ball is moving
-    let vertical_distance_to_wall =
-        ((ball.position.y - (ball.velocity.y.signum() / 2.0 + 0.5)).abs() - ball.radius).abs();
+    let vertical_distance_to_wall = if ball.velocity.y == 0.0 {
+        f64::INFINITY
+    } else {
+        ((ball.position.y - (ball.velocity.y.signum() / 2.0 + 0.5)).abs() - ball.radius).abs()
+    };
 
     // change the velocity given which wall was hit (the closest)
     let mut tmp = ball.velocity;
     if horizontal_distance_to_wall < vertical_distance_to_wall {
-        tmp.x = tmp.x * (-1.0);
+        tmp.x = tmp.x * (-ball.restitution);
     } else {
-        tmp.y = tmp.y * (-1.0);
+        tmp.y = tmp.y * (-ball.restitution);
     }
     ball.set_velocity(tmp);
 }
 
+/**
+ * Solves all impacts between touching poolballs closing in on each other at
+ * once. Every such pair is given an impulse so that it separates with its
+ * combined restitution, taking the other impacts at the same moment into
+ * account, so that a poolball hitting two others at once gives the same result
+ * whatever the order of the poolballs. Pairs closing in slower than the
+ * resting speed come to rest against each other instead of bouncing. Sticky
+ * poolballs are left to be merged
+ */
+pub fn solve_impacts(balls: &mut Vec<poolball::Poolball>) {
+    struct Impact {
+        first: usize,
+        second: usize,
+        normal: Vector2<f64>,
+        target: f64,
+        impulse: f64,
+    }
+
+    let mut impacts = Vec::new();
+    for i in 0..balls.len() {
+        for j in (i + 1)..balls.len() {
+            // Pairs always start with the lowest id, whatever the order
+            let (i, j) = if balls[i].id < balls[j].id { (i, j) } else { (j, i) };
+            let (a, b) = (&balls[i], &balls[j]);
+            if a.sticky || b.sticky {
+                continue;
+            }
+
+            let offset = b.position - a.position;
+            let distance = math::calc_magnitude(offset);
+            if distance == 0.0 || distance > a.radius + b.radius + CONTACT_TOLERANCE {
+                continue;
+            }
+
+            let normal = offset / distance;
+            let approach = (a.velocity - b.velocity).dot(&normal);
+            if approach <= 0.0 {
+                continue;
+            }
+
+            let restitution = if approach < RESTING_SPEED {
+                0.0
+            } else {
+                (a.restitution * b.restitution).sqrt()
+            };
+            impacts.push(Impact {
+                first: i,
+                second: j,
+                normal: normal,
+                target: restitution * approach,
+                impulse: 0.0,
+            });
+        }
+    }
+
+    // Solve the impacts one at a time in the order of the poolball ids,
+    // repeatedly, never letting the total impulse of a pair pull it together
+    impacts.sort_by_key(|impact| (balls[impact.first].id, balls[impact.second].id));
+    for _ in 0..CONTACT_ITERATIONS {
+        for impact in &mut impacts {
+            let mass1 = balls[impact.first].mass;
+            let mass2 = balls[impact.second].mass;
+            let separation = (balls[impact.second].velocity - balls[impact.first].velocity)
+                .dot(&impact.normal);
+
+            let change = (impact.target - separation) * mass1 * mass2 / (mass1 + mass2);
+            let impulse = (impact.impulse + change).max(0.0);
+            let applied = impulse - impact.impulse;
+            impact.impulse = impulse;
+
+            balls[impact.first].velocity -= impact.normal * (applied / mass1);
+            balls[impact.second].velocity += impact.normal * (applied / mass2);
+        }
+    }
+}
+
+/**
+ * Pushes overlapping poolballs apart until they just touch, the lighter
+ * poolball moving the most, and pushes poolballs outside the walls back onto
+ * the table. Of two poolballs on top of each other the second one is pushed
+ * towards the middle of the table
+ */
+pub fn separate_overlaps(balls: &mut Vec<poolball::Poolball>) {
+    for _ in 0..SEPARATION_ITERATIONS {
+        let mut overlapping = false;
+
+        for i in 0..balls.len() {
+            for j in (i + 1)..balls.len() {
+                let offset = balls[j].position - balls[i].position;
+                let distance = math::calc_magnitude(offset);
+                let penetration = balls[i].radius + balls[j].radius - distance;
+                if penetration <= CONTACT_TOLERANCE {
+                    continue;
+                }
+                overlapping = true;
+
+                // Share of the way moved by the first poolball
+                let (normal, share) = if distance > 0.0 {
+                    let inverse1 = 1.0 / balls[i].mass;
+                    let inverse2 = 1.0 / balls[j].mass;
+                    (offset / distance, inverse1 / (inverse1 + inverse2))
+                } else {
+                    let towards_middle = Point2::new(0.5, 0.5) - balls[j].position;
+                    let length = math::calc_magnitude(towards_middle);
+                    if length > 0.0 {
+                        (towards_middle / length, 0.0)
+                    } else {
+                        (Vector2::new(1.0, 0.0), 0.0)
+                    }
+                };
+
+                let position1 = balls[i].position - normal * (penetration * share);
+                let position2 = balls[j].position + normal * (penetration * (1.0 - share));
+                balls[i].position = position1;
+                balls[j].position = position2;
+            }
+        }
+
+        for ball in balls.iter_mut() {
+            let x = ball.position.x.max(ball.radius).min(1.0 - ball.radius);
+            let y = ball.position.y.max(ball.radius).min(1.0 - ball.radius);
+            if x != ball.position.x || y != ball.position.y {
+                ball.position = Point2::new(x, y);
+            }
+        }
+
+        if !overlapping {
+            return;
+        }
+    }
+}
+
+/**
+ * Merges two touching poolballs into a single poolball, conserving their
+ * combined mass and momentum. The merged poolball keeps the type of the white
+ * poolball if one of them is white, otherwise that of the sticky one, and is
+ * worth the sum of both poolballs
+ */
+pub fn merge(ball1: &poolball::Poolball, ball2: &poolball::Poolball) -> poolball::Poolball {
+    let second_is_base = match (&ball1.ball_type, &ball2.ball_type) {
+        (&poolball::BallType::White, _) => false,
+        (_, &poolball::BallType::White) => true,
+        _ => !ball1.sticky && ball2.sticky,
+    };
+    let (base, other) = if second_is_base {
+        (ball2, ball1)
+    } else {
+        (ball1, ball2)
+    };
+
+    let mass = ball1.mass + ball2.mass;
+    let mut merged = base.clone();
+    merged.mass = mass;
+    merged.radius = (ball1.radius.powi(2) + ball2.radius.powi(2)).sqrt();
+    merged.position = Point2::new((ball1.position.x * ball1.mass + ball2.position.x * ball2.mass) /
+                                  mass,
+                                  (ball1.position.y * ball1.mass + ball2.position.y * ball2.mass) /
+                                  mass);
+    merged.velocity = (ball1.velocity * ball1.mass + ball2.velocity * ball2.mass) / mass;
+
+    if base.ball_type != poolball::BallType::White {
+        merged.value = base.value + other.value;
+    }
+
+    // Keep the grown ball on the table
+    merged.position.x = merged.position.x.max(merged.radius).min(1.0 - merged.radius);
+    merged.position.y = merged.position.y.max(merged.radius).min(1.0 - merged.radius);
+
+    merged
+}
+
+/**
+ * Pushes all given poolballs within the blast radius of the exploding poolball
+ * away from it, harder the closer they are and the lighter they are
+ */
+pub fn explode(bomb: &poolball::Poolball, balls: &mut Vec<poolball::Poolball>) {
+    for ball in balls {
+        let offset = ball.position - bomb.position;
+        let distance = math::calc_magnitude(offset);
+        if distance == 0.0 || distance >= bomb.blast_radius {
+            continue;
+        }
+
+        let impulse = BLAST_IMPULSE * (1.0 - distance / bomb.blast_radius);
+        ball.velocity += offset * (impulse / (ball.mass * distance));
+    }
+}
+
 // Basic tests for gravity_acceleration
 #[test]
 fn test_gravity_acceleration() {
@@ -191,7 +492,7 @@ fn test_calculate_gravity() {
     let blackholes = vec![blackhole::Blackhole::new(Point2::new(0.0, 0.0), 1.0, 1.0, 1.0),
                           blackhole::Blackhole::new(Point2::new(0.0, 1.0), 1.0, 1.0, 1.0)];
     let ball = poolball::Poolball::new(Point2::new(1.0, 1.0), poolball::BallType::Red);
-    let acc_vector = calculate_gravity(&blackholes, &ball);
+    let acc_vector = calculate_gravity(&blackholes, &ball, 1.0);
     assert!((acc_vector.len() as f64) -
             ((1.0 / (8.0_f64).sqrt() *
               Vector2::new(-1.0 - (8.0_f64).sqrt(),
@@ -205,19 +506,29 @@ fn test_calculate_gravity_reach() {
     let blackholes = vec![blackhole::Blackhole::new(Point2::new(0.0, 0.0), 1.0, 1.0, 0.0),
                           blackhole::Blackhole::new(Point2::new(0.0, 1.0), 1.0, 1.0, 0.0)];
     let ball = poolball::Poolball::new(Point2::new(1.0, 1.0), poolball::BallType::Red);
-    let acc_vector = calculate_gravity(&blackholes, &ball);
+    let acc_vector = calculate_gravity(&blackholes, &ball, 1.0);
     assert_eq!(acc_vector, Vector2::new(0.0, 0.0));
 }
 
+#[test]
+fn test_gravity_constant() {
+    let blackholes = vec![blackhole::Blackhole::new(Point2::new(0.0, 0.0), 1.0, 0.1, 10.0)];
+    let ball = poolball::Poolball::new(Point2::new(1.0, 0.0), poolball::BallType::Red);
+    assert_eq!(calculate_gravity(&blackholes, &ball, 2.0),
+               calculate_gravity(&blackholes, &ball, 1.0) * 2.0);
+    assert_eq!(gravity_potential(&blackholes, &ball.position, 2.0),
+               gravity_potential(&blackholes, &ball.position, 1.0) * 2.0);
+}
+
 #[test]
 fn test_calculate_friction() {
     let mut ball = poolball::Poolball::new(Point2::new(1.0, 1.0), poolball::BallType::Red);
-    assert_eq!(calculate_friction(&mut ball), Vector2::new(0.0, 0.0));
+    assert_eq!(calculate_friction(&mut ball, 0.1), Vector2::new(0.0, 0.0));
 
     let mut ball2 = poolball::Poolball::new(Point2::new(1.0, 1.0), poolball::BallType::Red);
     ball2.velocity = Vector2::new(1.0, 0.0);
-    assert_eq!(Vector2::new(-1.0, 0.0) * FRICTION,
-               calculate_friction(&mut ball2));
+    assert_eq!(Vector2::new(-1.0, 0.0) * 0.1,
+               calculate_friction(&mut ball2, 0.1));
 }
 
 
@@ -336,3 +647,205 @@ fn test_ball_wall_collision() {
     ball_wall_collision(&mut ball);
     assert_eq!(ball.velocity, Vector2::new(1.0, 0.0));
 }
+
+#[test]
+fn test_time_to_portal_entry() {
+    let portal = wormhole::Portal::new(Point2::new(0.5, 0.5), 0.0);
+    let mut ball = poolball::Poolball::new(Point2::new(0.5 - portal.radius - 0.5, 0.5),
+                                           poolball::BallType::Red);
+    ball.velocity = Vector2::new(1.0, 0.0);
+    assert!((time_to_portal_entry(&ball, &portal) - 0.5).abs() < 0.0001);
+
+    // Moving away from the portal
+    ball.velocity = Vector2::new(-1.0, 0.0);
+    assert_eq!(time_to_portal_entry(&ball, &portal), f64::INFINITY);
+
+    // Passing by the portal
+    ball.velocity = Vector2::new(0.0, 1.0);
+    assert_eq!(time_to_portal_entry(&ball, &portal), f64::INFINITY);
+
+    // Already inside the portal
+    ball.position = Point2::new(0.5, 0.5);
+    ball.velocity = Vector2::new(1.0, 0.0);
+    assert_eq!(time_to_portal_entry(&ball, &portal), f64::INFINITY);
+}
+
+#[test]
+fn test_unequal_mass_ball_ball_collision() {
+    let mut light = poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Red);
+    light.mass = 1.0;
+    light.velocity = Vector2::new(1.0, 0.0);
+
+    let mut heavy = poolball::Poolball::new(Point2::new(4.0, 0.0), poolball::BallType::Red);
+    heavy.mass = 3.0;
+
+    ball_ball_collision(&mut light, &mut heavy);
+    assert_eq!(light.velocity, Vector2::new(-0.5, 0.0));
+    assert_eq!(heavy.velocity, Vector2::new(0.5, 0.0));
+}
+
+#[test]
+fn test_inelastic_collisions() {
+    let mut ball1 = poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Red);
+    ball1.restitution = 0.0;
+    ball1.velocity = Vector2::new(1.0, 0.0);
+    let mut ball2 = poolball::Poolball::new(Point2::new(4.0, 0.0), poolball::BallType::Red);
+
+    // Perfectly inelastic balls move on together
+    ball_ball_collision(&mut ball1, &mut ball2);
+    assert!((ball1.velocity.x - 0.5).abs() < 0.0001);
+    assert!((ball2.velocity.x - 0.5).abs() < 0.0001);
+
+    let mut ball = poolball::Poolball::new(Point2::new(0.9, 0.5), poolball::BallType::Red);
+    ball.radius = 0.1;
+    ball.restitution = 0.5;
+    ball.velocity = Vector2::new(1.0, 0.0);
+    ball_wall_collision(&mut ball);
+    assert_eq!(ball.velocity, Vector2::new(-0.5, 0.0));
+}
+
+#[test]
+fn test_calculate_gravity_immune() {
+    let blackholes = vec![blackhole::Blackhole::new(Point2::new(0.0, 0.0), 1.0, 1.0, 10.0)];
+    let ball = poolball::Poolball::new(Point2::new(1.0, 1.0), poolball::BallType::Ghost);
+    assert_eq!(calculate_gravity(&blackholes, &ball, 1.0), Vector2::new(0.0, 0.0));
+}
+
+#[test]
+fn test_merge() {
+    let mut sticky = poolball::Poolball::new(Point2::new(0.4, 0.5), poolball::BallType::Sticky);
+    sticky.velocity = Vector2::new(1.0, 0.0);
+    let red = poolball::Poolball::new(Point2::new(0.6, 0.5), poolball::BallType::Red);
+
+    let merged = merge(&red, &sticky);
+    assert_eq!(merged.ball_type, poolball::BallType::Sticky);
+    assert_eq!(merged.mass, sticky.mass + red.mass);
+    assert_eq!(merged.velocity, Vector2::new(0.5, 0.0));
+    assert!((merged.position.x - 0.5).abs() < 0.0001);
+    assert_eq!(merged.get_value(), sticky.get_value() + red.get_value());
+
+    // The white ball always survives a merge
+    let white = poolball::Poolball::new(Point2::new(0.6, 0.5), poolball::BallType::White);
+    let merged = merge(&sticky, &white);
+    assert_eq!(merged.ball_type, poolball::BallType::White);
+    assert_eq!(merged.get_value(), white.get_value());
+}
+
+#[test]
+fn test_explode() {
+    let mut bomb = poolball::Poolball::new(Point2::new(0.5, 0.5), poolball::BallType::Bomb);
+    bomb.blast_radius = 0.2;
+    let mut balls = vec![poolball::Poolball::new(Point2::new(0.6, 0.5), poolball::BallType::Red),
+                         poolball::Poolball::new(Point2::new(0.9, 0.5), poolball::BallType::Red)];
+    explode(&bomb, &mut balls);
+
+    // Only the ball within the blast radius is pushed, straight away
+    assert!(balls[0].velocity.x > 0.0);
+    assert_eq!(balls[0].velocity.y, 0.0);
+    assert_eq!(balls[1].velocity, Vector2::new(0.0, 0.0));
+}
+
+#[test]
+fn test_overlapping_ball_ball_collision() {
+    let mut ball1 = poolball::Poolball::new(Point2::new(0.5, 0.5), poolball::BallType::Red);
+    let mut ball2 = poolball::Poolball::new(Point2::new(0.51, 0.5), poolball::BallType::Red);
+
+    // Overlapping balls closing in collide right away
+    ball1.velocity = Vector2::new(1.0, 0.0);
+    assert_eq!(time_to_ball_ball_collision(&ball1, &ball2), 0.0);
+
+    // Overlapping balls moving apart never collide
+    ball1.velocity = Vector2::new(-1.0, 0.0);
+    assert_eq!(time_to_ball_ball_collision(&ball1, &ball2), f64::INFINITY);
+
+    // Balls not moving relative to each other never collide
+    ball2.position = Point2::new(0.6, 0.5);
+    ball2.velocity = Vector2::new(-1.0, 0.0);
+    assert_eq!(time_to_ball_ball_collision(&ball1, &ball2), f64::INFINITY);
+}
+
+#[test]
+fn test_overlapping_wall_collision() {
+    // A ball past the wall hits it right away, and not at all once moving back
+    let mut ball = poolball::Poolball::new(Point2::new(0.995, 0.5), poolball::BallType::Red);
+    ball.velocity = Vector2::new(1.0, 0.0);
+    assert_eq!(time_to_wall_collision(&ball), 0.0);
+    ball_wall_collision(&mut ball);
+    assert_eq!(ball.velocity, Vector2::new(-1.0, 0.0));
+    assert!(time_to_wall_collision(&ball) > 0.0);
+
+    // Stationary balls touching a wall never hit it
+    ball.position = Point2::new(0.99, 0.5);
+    ball.velocity = Vector2::new(0.0, 0.0);
+    assert_eq!(time_to_wall_collision(&ball), f64::INFINITY);
+}
+
+#[test]
+fn test_simultaneous_impacts() {
+    // A ball hitting two touching balls at once, symmetrically
+    let angle = f64::consts::PI / 6.0;
+    let mut hitting = poolball::Poolball::new(Point2::new(0.5, 0.5), poolball::BallType::Red);
+    hitting.velocity = Vector2::new(1.0, 0.0);
+    let mut balls = vec![hitting];
+    for side in vec![1.0, -1.0] {
+        let x = 0.5 + 0.02 * angle.cos();
+        let y = 0.5 + side * 0.02 * angle.sin();
+        balls.push(poolball::Poolball::new(Point2::new(x, y), poolball::BallType::Red));
+    }
+
+    let before = balls.clone();
+    solve_impacts(&mut balls);
+
+    // Both balls are hit equally hard and the energy is conserved
+    assert!((balls[1].velocity.x - balls[2].velocity.x).abs() < 0.000001);
+    assert!((balls[1].velocity.y + balls[2].velocity.y).abs() < 0.000001);
+    assert!((balls[0].velocity.x + 0.2).abs() < 0.000001);
+    let energy = |balls: &Vec<poolball::Poolball>| {
+        balls.iter().fold(0.0, |sum, ball| sum + ball.velocity.dot(&ball.velocity))
+    };
+    assert!((energy(&before) - energy(&balls)).abs() < 0.000001);
+
+    // The result does not depend on the order of the balls
+    let mut reversed = before.clone();
+    reversed.reverse();
+    solve_impacts(&mut reversed);
+    for ball in &balls {
+        let other = reversed.iter().find(|other| other.id == ball.id).unwrap();
+        assert_eq!(ball.velocity, other.velocity);
+    }
+}
+
+#[test]
+fn test_resting_contact() {
+    // Touching balls closing in slowly come to rest against each other
+    let mut ball1 = poolball::Poolball::new(Point2::new(0.5, 0.5), poolball::BallType::Red);
+    ball1.velocity = Vector2::new(0.0005, 0.0);
+    let ball2 = poolball::Poolball::new(Point2::new(0.52, 0.5), poolball::BallType::Red);
+
+    let mut balls = vec![ball1, ball2];
+    solve_impacts(&mut balls);
+    assert!((balls[0].velocity.x - balls[1].velocity.x).abs() < 0.000001);
+    assert!((balls[0].velocity.x - 0.00025).abs() < 0.000001);
+}
+
+#[test]
+fn test_separate_overlaps() {
+    let mut heavy = poolball::Poolball::new(Point2::new(0.5, 0.5), poolball::BallType::Red);
+    heavy.mass = 0.3;
+    let light = poolball::Poolball::new(Point2::new(0.51, 0.5), poolball::BallType::Red);
+    let mut balls = vec![heavy, light];
+    separate_overlaps(&mut balls);
+
+    // Pushed apart until touching, the light ball moving three times as far
+    assert!((balls[0].position.distance(&balls[1].position) - 0.02).abs() < 0.000001);
+    assert!((balls[0].position.x - 0.4975).abs() < 0.000001);
+
+    // Balls on top of each other in a corner end up apart and on the table
+    let mut balls = vec![poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Red),
+                         poolball::Poolball::new(Point2::new(0.0, 0.0), poolball::BallType::Red)];
+    separate_overlaps(&mut balls);
+    assert!(balls[0].position.distance(&balls[1].position) >= 0.02 - 0.000001);
+    for ball in &balls {
+        assert!(ball.position.x >= ball.radius && ball.position.y >= ball.radius);
+    }
+}
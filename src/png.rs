@@ -0,0 +1,146 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use canvas;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+// Largest amount of data in a single uncompressed deflate block
+const MAX_BLOCK: usize = 65535;
+
+/**
+ * Writes the given canvas to a PNG file at the given path
+ */
+pub fn save(canvas: &canvas::Canvas, path: &Path) -> io::Result<()> {
+    let mut file = try!(File::create(path));
+    file.write_all(&encode(canvas))
+}
+
+/**
+ * Encodes the given canvas as an RGBA PNG image. The image data is stored
+ * without compression, keeping the encoder small
+ */
+pub fn encode(canvas: &canvas::Canvas) -> Vec<u8> {
+    let mut header = Vec::new();
+    push_u32(&mut header, canvas.width as u32);
+    push_u32(&mut header, canvas.height as u32);
+    // Bit depth 8, RGBA color, default compression, filtering and no interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // Every row starts with the filter type, none
+    let row_length = canvas.width * 4;
+    let mut raw = Vec::with_capacity((row_length + 1) * canvas.height);
+    for row in canvas.pixels.chunks(row_length) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    push_chunk(&mut png, b"IHDR", &header);
+    push_chunk(&mut png, b"IDAT", &zlib_store(&raw));
+    push_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/**
+ * Wraps the given data in a zlib stream of uncompressed deflate blocks
+ */
+fn zlib_store(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = if data.is_empty() {
+        vec![data]
+    } else {
+        data.chunks(MAX_BLOCK).collect()
+    };
+
+    for (index, block) in blocks.iter().enumerate() {
+        let last = index + 1 == blocks.len();
+        let length = block.len() as u16;
+        stream.push(if last { 1 } else { 0 });
+        stream.extend_from_slice(&[length as u8, (length >> 8) as u8]);
+        stream.extend_from_slice(&[!length as u8, (!length >> 8) as u8]);
+        stream.extend_from_slice(block);
+    }
+
+    push_u32(&mut stream, adler32(data));
+    stream
+}
+
+/**
+ * Appends a chunk with the given type and data, followed by its checksum
+ */
+fn push_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    push_u32(png, data.len() as u32);
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let checksum = crc32(&png[start..]);
+    push_u32(png, checksum);
+}
+
+/**
+ * Appends the given number in big endian byte order
+ */
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8,
+                              value as u8]);
+}
+
+/**
+ * Calculates the CRC-32 checksum used by PNG chunks
+ */
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFF_u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/**
+ * Calculates the Adler-32 checksum ending a zlib stream
+ */
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(crc32(b"IEND"), 0xAE426082);
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+}
+
+#[test]
+fn test_encode() {
+    let canvas = canvas::Canvas::new(2, 1);
+    let png = encode(&canvas);
+    assert_eq!(&png[..8], &SIGNATURE);
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(&png[16..24], &[0, 0, 0, 2, 0, 0, 0, 1]);
+    assert_eq!(&png[png.len() - 12..],
+               &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+}
+
+#[test]
+fn test_zlib_store_blocks() {
+    // Data larger than a block is split, only the last block being final
+    let data = vec![7; MAX_BLOCK + 10];
+    let stream = zlib_store(&data);
+    assert_eq!(stream.len(), 2 + 2 * 5 + data.len() + 4);
+    assert_eq!(stream[2], 0);
+    assert_eq!(stream[2 + 5 + MAX_BLOCK], 1);
+    assert_eq!(&stream[3 + 5 + MAX_BLOCK..5 + 5 + MAX_BLOCK], &[10, 0]);
+}
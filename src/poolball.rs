@@ -1,8 +1,8 @@
 use na::{Vector2, Point2, ApproxEq};
-use piston::input::RenderArgs;
-use opengl_graphics::GlGraphics;
-use graphics::Transformed;
-use graphics::ellipse;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use catalogue;
+use renderer;
 
 /**
  * Poolball containing information about a single poolball in the game, and
@@ -10,38 +10,112 @@ use graphics::ellipse;
  */
 #[derive(Clone, Debug, PartialEq)]
 pub struct Poolball {
+    pub id: usize,
     pub position: Point2<f64>,
     pub radius: f64,
     pub mass: f64,
+    pub restitution: f64,
     pub velocity: Vector2<f64>,
     pub ball_type: BallType,
+    pub value: i32,
+    pub color: [f32; 4],
+    pub blast_radius: f64,
+    pub sticky: bool,
+    pub immune: bool,
 }
 
 /**
- * Used to differentiate between the different colored poolballs.
+ * Used to differentiate between the different kinds of poolballs, whose
+ * properties are looked up in the ball catalogue.
  * Only one poolball maybe White at any given moment
  */
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum BallType {
     White,
     Red,
     Blue,
+    Iron,
+    Light,
+    Large,
+    Bomb,
+    Sticky,
+    Ghost,
+}
+
+impl BallType {
+    /**
+     * Returns the name of the ball type as used in level and catalogue files
+     */
+    pub fn name(&self) -> &'static str {
+        match *self {
+            BallType::White => "white",
+            BallType::Red => "red",
+            BallType::Blue => "blue",
+            BallType::Iron => "iron",
+            BallType::Light => "light",
+            BallType::Large => "large",
+            BallType::Bomb => "bomb",
+            BallType::Sticky => "sticky",
+            BallType::Ghost => "ghost",
+        }
+    }
+}
+
+impl FromStr for BallType {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<BallType, String> {
+        match name {
+            "white" => Ok(BallType::White),
+            "red" => Ok(BallType::Red),
+            "blue" => Ok(BallType::Blue),
+            "iron" => Ok(BallType::Iron),
+            "light" => Ok(BallType::Light),
+            "large" => Ok(BallType::Large),
+            "bomb" => Ok(BallType::Bomb),
+            "sticky" => Ok(BallType::Sticky),
+            "ghost" => Ok(BallType::Ghost),
+            _ => Err(format!("unknown ball type '{}'", name)),
+        }
+    }
 }
 
-const MASS: f64 = 0.1;
-const RADIUS: f64 = 0.01;
+// Source of unique poolball ids, which stay the same as the poolball moves
+// around and is cloned
+static NEXT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
 
 impl Poolball {
     /**
-     * Creates a new Poolball with the given initial position and velocity
+     * Creates a new stationary Poolball with the given initial position and
+     * the built-in properties of its type, for tests
      */
+    #[cfg(test)]
     pub fn new(position: Point2<f64>, ball_type: BallType) -> Poolball {
+        let properties = catalogue::Catalogue::builtin().properties(&ball_type);
+        Poolball::with_properties(position, ball_type, &properties)
+    }
+
+    /**
+     * Creates a new stationary Poolball with the given initial position and
+     * properties
+     */
+    pub fn with_properties(position: Point2<f64>,
+                           ball_type: BallType,
+                           properties: &catalogue::BallProperties)
+                           -> Poolball {
         Poolball {
+            id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
             position: position,
-            radius: RADIUS,
-            mass: MASS,
+            radius: properties.radius,
+            mass: properties.mass,
+            restitution: properties.restitution,
             velocity: Vector2::new(0.0, 0.0),
             ball_type: ball_type,
+            value: properties.value,
+            color: properties.color,
+            blast_radius: properties.blast_radius,
+            sticky: properties.sticky,
+            immune: properties.immune,
         }
     }
 
@@ -67,54 +141,29 @@ impl Poolball {
     }
 
     /**
-     * Returns the score value of the poolball based on the ball type
+     * Returns the score value of the poolball, by default based on the ball type
      * - White: -100
      * - Red: 10
      * - Blue: 30
      */
     pub fn get_value(&self) -> i32 {
-        match self.ball_type {
-            BallType::White => -100,
-            BallType::Red => 10,
-            BallType::Blue => 30,
-        }
+        self.value
     }
 
     /**
-     * Returns `true` if the poolball is stationary
+     * Returns `true` if the poolball moves slower than the given speed along
+     * both axes, counting as stationary in a game with that speed
      */
-    pub fn is_stationary(&self) -> bool {
-        let eps = 0.005;
-        self.velocity.approx_eq_eps(&Vector2::new(0.0, 0.0), &eps)
+    pub fn is_slower_than(&self, speed: f64) -> bool {
+        self.velocity.approx_eq_eps(&Vector2::new(0.0, 0.0), &speed)
     }
 
     /**
      * Renders itself using the given graphics
      */
-    pub fn render(&self, args: &RenderArgs, gl: &mut GlGraphics) {
-
-        const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
-        const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 0.4];
-        const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
-
-        let ellipse = match self.ball_type {
-            BallType::White => ellipse::Ellipse::new(WHITE),
-            BallType::Red => ellipse::Ellipse::new(RED),
-            BallType::Blue => ellipse::Ellipse::new(BLUE),
-        };
-
-        gl.draw(args.viewport(), |c, gl| {
-
-            let trans = c.transform
-                .scale(args.width as f64, args.height as f64)
-                .trans(self.position.x, self.position.y);
-
-            // Draw the cue ball
-            ellipse.draw(ellipse::circle(0.0, 0.0, self.radius),
-                         &c.draw_state,
-                         trans,
-                         gl);
-        });
+    pub fn render(&self, renderer: &mut renderer::Renderer) {
+        // Draw the cue ball
+        renderer.circle(self.color, self.position, self.radius);
     }
 }
 
@@ -142,11 +191,12 @@ fn test_set_velocity() {
 }
 
 #[test]
-fn test_is_stationary() {
+fn test_is_slower_than() {
     let mut ball = Poolball::new(Point2::new(0.0, 0.0), BallType::Red);
-    assert!(ball.is_stationary());
+    assert!(ball.is_slower_than(0.005));
     ball.set_velocity(Vector2::new(1.0, 1.0));
-    assert!(!ball.is_stationary());
+    assert!(!ball.is_slower_than(0.005));
+    assert!(ball.is_slower_than(2.0));
 }
 
 #[test]
@@ -159,3 +209,27 @@ fn test_get_value() {
     assert_eq!(red.get_value(), 10);
     assert_eq!(blue.get_value(), 30);
 }
+
+#[test]
+fn test_ball_type_names() {
+    for ball_type in vec![BallType::White,
+                          BallType::Red,
+                          BallType::Blue,
+                          BallType::Iron,
+                          BallType::Light,
+                          BallType::Large,
+                          BallType::Bomb,
+                          BallType::Sticky,
+                          BallType::Ghost] {
+        assert_eq!(ball_type.name().parse::<BallType>(), Ok(ball_type.clone()));
+    }
+    assert!("green".parse::<BallType>().is_err());
+}
+
+#[test]
+fn test_unique_ids() {
+    let ball1 = Poolball::new(Point2::new(0.0, 0.0), BallType::Red);
+    let ball2 = Poolball::new(Point2::new(0.0, 0.0), BallType::Red);
+    assert!(ball1.id != ball2.id);
+    assert_eq!(ball1.clone().id, ball1.id);
+}
@@ -0,0 +1,202 @@
+use graphics::{Context, Transformed};
+use graphics::circle_arc;
+use graphics::ellipse;
+use graphics::line;
+use graphics::text;
+use graphics::math::Matrix2d;
+use opengl_graphics::GlGraphics;
+use opengl_graphics::glyph_cache::GlyphCache;
+use na::{Norm, Point2, Vector1, Vector2, Rotation2, Rotate};
+use std::f64;
+use camera;
+
+/**
+ * A color given as red, green, blue and alpha between 0 and 1
+ */
+pub type Color = [f32; 4];
+
+/**
+ * A surface the game can be drawn on. All positions and sizes are given in
+ * table coordinates, where the table spans 0 to 1 in both directions, except
+ * for text sizes which are given in pixels. Text is placed in view
+ * coordinates, unaffected by zooming and panning the camera
+ */
+pub trait Renderer {
+    /**
+     * Fills the whole surface with the given color
+     */
+    fn clear(&mut self, color: Color);
+
+    /**
+     * Draws a filled circle
+     */
+    fn circle(&mut self, color: Color, center: Point2<f64>, radius: f64);
+
+    /**
+     * Draws the part of a circle outline between the given angles (in
+     * radians, clockwise from the positive x axis)
+     */
+    fn arc(&mut self,
+           color: Color,
+           center: Point2<f64>,
+           radius: f64,
+           thickness: f64,
+           start: f64,
+           end: f64);
+
+    /**
+     * Draws a straight line
+     */
+    fn line(&mut self, color: Color, from: Point2<f64>, to: Point2<f64>, thickness: f64);
+
+    /**
+     * Draws text with its baseline starting at the given position
+     */
+    fn text(&mut self, color: Color, size: u32, position: Point2<f64>, text: &str);
+
+    /**
+     * Converts a position on the table to view coordinates, for placing text
+     * next to something drawn on the table
+     */
+    fn to_view(&self, position: Point2<f64>) -> Point2<f64>;
+
+    /**
+     * Draws connected lines through the given points
+     */
+    fn path(&mut self, color: Color, points: &[Point2<f64>], thickness: f64) {
+        for pair in points.windows(2) {
+            self.line(color, pair[0], pair[1], thickness);
+        }
+    }
+
+    /**
+     * Draws a full circle outline
+     */
+    fn ring(&mut self, color: Color, center: Point2<f64>, radius: f64, thickness: f64) {
+        self.arc(color, center, radius, thickness, 0.0, 2.0 * f64::consts::PI);
+    }
+
+    /**
+     * Draws a line with an arrow head of the given size at its end
+     */
+    fn arrow(&mut self,
+             color: Color,
+             from: Point2<f64>,
+             to: Point2<f64>,
+             thickness: f64,
+             head_size: f64) {
+        self.line(color, from, to, thickness);
+
+        let direction = to - from;
+        if direction == Vector2::new(0.0, 0.0) {
+            return;
+        }
+
+        // Two short lines pointing back from the tip
+        let back = -direction.normalize() * head_size;
+        for &angle in &[f64::consts::PI / 4.0, -f64::consts::PI / 4.0] {
+            let rotation = Rotation2::new(Vector1::new(angle));
+            self.line(color, to, to + rotation.rotate(&back), thickness);
+        }
+    }
+}
+
+/**
+ * Draws using OpenGL within a single `GlGraphics::draw` call, showing the
+ * table as seen by the given camera
+ */
+pub struct GlRenderer<'a> {
+    context: Context,
+    gl: &'a mut GlGraphics,
+    cache: &'a mut GlyphCache,
+    camera: &'a camera::Camera,
+}
+
+impl<'a> GlRenderer<'a> {
+    /**
+     * Creates a renderer drawing with the given context and graphics, using
+     * the given glyph cache for text
+     */
+    pub fn new(context: Context,
+               gl: &'a mut GlGraphics,
+               cache: &'a mut GlyphCache,
+               camera: &'a camera::Camera)
+               -> GlRenderer<'a> {
+        GlRenderer {
+            context: context,
+            gl: gl,
+            cache: cache,
+            camera: camera,
+        }
+    }
+
+    /**
+     * Returns the transform from table coordinates to the window
+     */
+    fn table_transform(&self) -> Matrix2d {
+        let origin = self.camera.to_screen(&Point2::new(0.0, 0.0));
+        let scale = self.camera.scale();
+        self.context.transform.trans(origin.x, origin.y).scale(scale, scale)
+    }
+}
+
+impl<'a> Renderer for GlRenderer<'a> {
+    fn clear(&mut self, color: Color) {
+        use graphics::clear;
+        clear(color, self.gl);
+    }
+
+    fn circle(&mut self, color: Color, center: Point2<f64>, radius: f64) {
+        let scaled = self.table_transform();
+        ellipse::Ellipse::new(color).draw(ellipse::circle(center.x, center.y, radius),
+                                          &self.context.draw_state,
+                                          scaled,
+                                          self.gl);
+    }
+
+    fn arc(&mut self,
+           color: Color,
+           center: Point2<f64>,
+           radius: f64,
+           thickness: f64,
+           start: f64,
+           end: f64) {
+        let scaled = self.table_transform();
+        circle_arc::CircleArc::new(color, thickness / 2.0, start, end)
+            .draw(ellipse::circle(center.x, center.y, radius),
+                  &self.context.draw_state,
+                  scaled,
+                  self.gl);
+    }
+
+    fn line(&mut self, color: Color, from: Point2<f64>, to: Point2<f64>, thickness: f64) {
+        let scaled = self.table_transform();
+        line::Line::new(color, thickness / 2.0)
+            .draw([from.x, from.y, to.x, to.y], &self.context.draw_state, scaled, self.gl);
+    }
+
+    fn text(&mut self, color: Color, size: u32, position: Point2<f64>, text: &str) {
+        let position = self.camera.view_to_screen(&position);
+        let trans = self.context.transform.trans(position.x, position.y);
+        text::Text::new_color(color, size)
+            .draw(text, self.cache, &self.context.draw_state, trans, self.gl);
+    }
+
+    fn to_view(&self, position: Point2<f64>) -> Point2<f64> {
+        self.camera.to_view(&self.camera.to_screen(&position))
+    }
+
+    fn arrow(&mut self,
+             color: Color,
+             from: Point2<f64>,
+             to: Point2<f64>,
+             thickness: f64,
+             head_size: f64) {
+        let scaled = self.table_transform();
+        line::Line::new(color, thickness / 2.0).draw_arrow([from.x, from.y, to.x, to.y],
+                                                           head_size,
+                                                           &self.context.draw_state,
+                                                           scaled,
+                                                           self.gl);
+    }
+}
@@ -0,0 +1,202 @@
+use na::Vector2;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use parse;
+use integrator;
+
+/**
+ * A single shot of the white ball, in the direction given by its angle (in
+ * radians) with the given speed. Shots with a time are taken at that time of
+ * the game, others as soon as the white ball is still
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shot {
+    pub angle: f64,
+    pub speed: f64,
+    pub time: Option<f64>,
+}
+
+/**
+ * The shots taken in a game of a level, and the integrator used, for playing
+ * the game again. Replays are stored as plain text in the same style as level
+ * files:
+ *
+ * ```text
+ * level <path>
+ * integrator <name>
+ * shot <angle> <speed> [time=<seconds>]
+ * ```
+ *
+ * The integrator may be left out, using the default one
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub level: String,
+    pub integrator: integrator::Method,
+    pub shots: Vec<Shot>,
+}
+
+impl Shot {
+    /**
+     * Creates a new shot in the given direction with the given speed, taken
+     * as soon as possible
+     */
+    pub fn new(direction: Vector2<f64>, speed: f64) -> Shot {
+        Shot {
+            angle: direction.y.atan2(direction.x),
+            speed: speed,
+            time: None,
+        }
+    }
+
+    /**
+     * Takes the shot at the given time of the game
+     */
+    pub fn at(mut self, time: f64) -> Shot {
+        self.time = Some(time);
+        self
+    }
+
+    /**
+     * Returns the velocity given to the white ball by the shot
+     */
+    pub fn velocity(&self) -> Vector2<f64> {
+        Vector2::new(self.angle.cos(), self.angle.sin()) * self.speed
+    }
+}
+
+impl Replay {
+    /**
+     * Creates a replay of the level at the given path with the given shots
+     */
+    pub fn new(level: &str, shots: Vec<Shot>) -> Replay {
+        Replay {
+            level: String::from(level),
+            integrator: integrator::Method::ExplicitEuler,
+            shots: shots,
+        }
+    }
+
+    /**
+     * Plays the replay using the given integrator
+     */
+    pub fn with_integrator(mut self, integrator: integrator::Method) -> Replay {
+        self.integrator = integrator;
+        self
+    }
+
+    /**
+     * Reads and parses the replay file at the given path
+     */
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let mut source = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut source))
+            .map_err(|err| format!("could not read {}: {}", path.display(), err)));
+        Replay::parse(&source).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /**
+     * Parses a replay from its textual representation
+     */
+    pub fn parse(source: &str) -> Result<Replay, String> {
+        let mut replay = Replay::new("", Vec::new());
+        let mut has_level = false;
+
+        for line in parse::lines(source) {
+            try!(parse_line(&line, &mut replay, &mut has_level)
+                .map_err(|err| format!("line {}: {}", line.number, err)));
+        }
+
+        if !has_level {
+            return Err(String::from("missing level"));
+        }
+        Ok(replay)
+    }
+
+    /**
+     * Returns the textual representation of the replay
+     */
+    pub fn to_source(&self) -> String {
+        let mut source = format!("level {}\nintegrator {}\n", self.level, self.integrator.name());
+        for shot in &self.shots {
+            source.push_str(&format!("shot {} {}", shot.angle, shot.speed));
+            if let Some(time) = shot.time {
+                source.push_str(&format!(" time={}", time));
+            }
+            source.push('\n');
+        }
+        source
+    }
+
+    /**
+     * Writes the replay to the file at the given path
+     */
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        File::create(path)
+            .and_then(|mut file| file.write_all(self.to_source().as_bytes()))
+            .map_err(|err| format!("could not write {}: {}", path.display(), err))
+    }
+}
+
+/**
+ * Parses a single line of a replay into the given replay
+ */
+fn parse_line(line: &parse::Line,
+              replay: &mut Replay,
+              has_level: &mut bool)
+              -> Result<(), String> {
+    match line.kind {
+        "level" => {
+            try!(line.expect_arguments(1));
+            try!(line.expect_no_options());
+            replay.level = String::from(line.arguments[0]);
+            *has_level = true;
+        }
+        "integrator" => {
+            try!(line.expect_arguments(1));
+            try!(line.expect_no_options());
+            replay.integrator = try!(line.arguments[0].parse::<integrator::Method>());
+        }
+        "shot" => {
+            try!(line.expect_arguments(2));
+            let numbers = try!(line.numbers());
+            let mut shot = Shot {
+                angle: numbers[0],
+                speed: numbers[1],
+                time: None,
+            };
+            for &(key, value) in &line.options {
+                match key {
+                    "time" => shot.time = Some(try!(parse::parse_number(value))),
+                    _ => return Err(line.unknown_option(key)),
+                }
+            }
+            replay.shots.push(shot);
+        }
+        _ => return Err(format!("unknown entry '{}'", line.kind)),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_round_trip() {
+    let shots = vec![Shot::new(Vector2::new(0.0, 1.0), 0.5),
+                     Shot::new(Vector2::new(1.0, 1.0), 0.123456789).at(2.5)];
+    let replay = Replay::new("levels/classic.lvl", shots)
+        .with_integrator(integrator::Method::RungeKutta4);
+
+    let parsed = Replay::parse(&replay.to_source()).unwrap();
+    assert_eq!(parsed, replay);
+    assert!((parsed.shots[0].velocity() - Vector2::new(0.0, 0.5)).x.abs() < 0.000001);
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(Replay::parse("shot 0 1").is_err());
+    assert!(Replay::parse("level a.lvl\nshot 0").is_err());
+    assert!(Replay::parse("level a.lvl\nshot 0 1 delay=2").is_err());
+    assert!(Replay::parse("level a.lvl\nball red 0 0").is_err());
+    assert!(Replay::parse("level a.lvl\nintegrator leapfrog").is_err());
+}
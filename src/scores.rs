@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use parse;
+
+/**
+ * The best score reached on each level, by the path of the level. Scores are
+ * stored as plain text in the same style as level files:
+ *
+ * ```text
+ * best <level path> <score>
+ * ```
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scores {
+    pub best: BTreeMap<String, i32>,
+}
+
+impl Scores {
+    /**
+     * Creates a table without any scores
+     */
+    pub fn new() -> Scores {
+        Scores { best: BTreeMap::new() }
+    }
+
+    /**
+     * Reads and parses the scores file at the given path
+     */
+    pub fn load(path: &Path) -> Result<Scores, String> {
+        let mut source = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut source))
+            .map_err(|err| format!("could not read {}: {}", path.display(), err)));
+        Scores::parse(&source).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /**
+     * Parses scores from their textual representation
+     */
+    pub fn parse(source: &str) -> Result<Scores, String> {
+        let mut scores = Scores::new();
+        for line in parse::lines(source) {
+            try!(parse_line(&line, &mut scores)
+                .map_err(|err| format!("line {}: {}", line.number, err)));
+        }
+        Ok(scores)
+    }
+
+    /**
+     * Returns the textual representation of the scores
+     */
+    pub fn to_source(&self) -> String {
+        let mut source = String::new();
+        for (level, score) in &self.best {
+            source.push_str(&format!("best {} {}\n", level, score));
+        }
+        source
+    }
+
+    /**
+     * Writes the scores to the file at the given path
+     */
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        File::create(path)
+            .and_then(|mut file| file.write_all(self.to_source().as_bytes()))
+            .map_err(|err| format!("could not write {}: {}", path.display(), err))
+    }
+
+    /**
+     * Returns the best score reached on the level at the given path
+     */
+    pub fn best(&self, level: &str) -> Option<i32> {
+        self.best.get(level).cloned()
+    }
+
+    /**
+     * Records a score reached on the level at the given path, returning `true`
+     * if it beats the best one so far
+     */
+    pub fn record(&mut self, level: &str, score: i32) -> bool {
+        if self.best(level).map_or(false, |best| best >= score) {
+            return false;
+        }
+        self.best.insert(String::from(level), score);
+        true
+    }
+}
+
+/**
+ * Parses a single line of a scores file into the given scores
+ */
+fn parse_line(line: &parse::Line, scores: &mut Scores) -> Result<(), String> {
+    match line.kind {
+        "best" => {
+            try!(line.expect_arguments(2));
+            try!(line.expect_no_options());
+            let score = try!(parse::parse_integer(line.arguments[1]));
+            scores.best.insert(String::from(line.arguments[0]), score);
+        }
+        _ => return Err(format!("unknown entry '{}'", line.kind)),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_record() {
+    let mut scores = Scores::new();
+    assert!(scores.record("levels/classic.lvl", 30));
+    assert!(!scores.record("levels/classic.lvl", 20));
+    assert!(scores.record("levels/classic.lvl", 50));
+    assert!(scores.record("levels/zones.lvl", -10));
+    assert_eq!(scores.best("levels/classic.lvl"), Some(50));
+    assert_eq!(scores.best("levels/cluster.lvl"), None);
+
+    assert_eq!(Scores::parse(&scores.to_source()).unwrap(), scores);
+    assert!(Scores::parse("best levels/classic.lvl").is_err());
+    assert!(Scores::parse("best levels/classic.lvl many").is_err());
+}
@@ -0,0 +1,374 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use parse;
+
+/**
+ * Tuning constants of the physics and the aiming. Settings are stored as plain
+ * text with one constant per line, in the same style as level files:
+ *
+ * ```text
+ * friction <acceleration>
+ * gravity <constant>
+ * stationary_speed <speed>
+ * goalzone_radius <radius>
+ * shot_speed <speed per arrow length>
+ * aim_speed <radians per second>
+ * arrow_length <length>
+ * restitution_scale <factor>
+ * blackhole_mass_scale <factor>
+ * blackhole_reach_scale <factor>
+ * time_scale <factor>
+ * ```
+ *
+ * Constants left out keep their defaults. The scales multiply the restitution
+ * of the poolballs, the mass and reach of the blackholes and the time passing
+ * in the game, and must be greater than zero. Levels may override any of the
+ * constants, and the mass and radius of the poolballs are set in the ball
+ * catalogue
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub friction: f64,
+    pub gravity: f64,
+    pub stationary_speed: f64,
+    pub goalzone_radius: f64,
+    pub shot_speed: f64,
+    pub aim_speed: f64,
+    pub arrow_length: f64,
+    pub restitution_scale: f64,
+    pub blackhole_mass_scale: f64,
+    pub blackhole_reach_scale: f64,
+    pub time_scale: f64,
+}
+
+/**
+ * Reloads a settings file whenever it changes
+ */
+#[derive(Clone, Debug)]
+pub struct Watcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    since_check: f64,
+}
+
+// Seconds between checks of the settings file for changes
+const CHECK_INTERVAL: f64 = 0.5;
+
+// Names of the constants, in the order they are written in
+const KEYS: [&'static str; 11] = ["friction",
+                                  "gravity",
+                                  "stationary_speed",
+                                  "goalzone_radius",
+                                  "shot_speed",
+                                  "aim_speed",
+                                  "arrow_length",
+                                  "restitution_scale",
+                                  "blackhole_mass_scale",
+                                  "blackhole_reach_scale",
+                                  "time_scale"];
+
+impl Settings {
+    /**
+     * Creates the default settings
+     */
+    pub fn new() -> Settings {
+        Settings {
+            friction: 0.1,
+            gravity: 1.0,
+            stationary_speed: 0.005,
+            goalzone_radius: 0.05,
+            shot_speed: 5.0,
+            aim_speed: 1.0,
+            arrow_length: 0.2,
+            restitution_scale: 1.0,
+            blackhole_mass_scale: 1.0,
+            blackhole_reach_scale: 1.0,
+            time_scale: 1.0,
+        }
+    }
+
+    /**
+     * Reads and parses the settings file at the given path
+     */
+    pub fn load(path: &Path) -> Result<Settings, String> {
+        let mut source = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut source))
+            .map_err(|err| format!("could not read {}: {}", path.display(), err)));
+        Settings::parse(&source).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /**
+     * Reads and parses the settings file at the given path, using the default
+     * settings if there is no such file
+     */
+    pub fn load_or_default(path: &Path) -> Result<Settings, String> {
+        if !path.exists() {
+            return Ok(Settings::new());
+        }
+        Settings::load(path)
+    }
+
+    /**
+     * Parses settings from their textual representation
+     */
+    pub fn parse(source: &str) -> Result<Settings, String> {
+        let mut settings = Settings::new();
+
+        for line in parse::lines(source) {
+            try!(parse_line(&line, &mut settings)
+                .map_err(|err| format!("line {}: {}", line.number, err)));
+        }
+
+        Ok(settings)
+    }
+
+    /**
+     * Writes the settings to the file at the given path, keeping the comments
+     * and the order of the settings already in it
+     */
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut previous = String::new();
+        if path.exists() {
+            try!(File::open(path)
+                .and_then(|mut file| file.read_to_string(&mut previous))
+                .map_err(|err| format!("could not read {}: {}", path.display(), err)));
+        }
+        File::create(path)
+            .and_then(|mut file| file.write_all(self.to_source(&previous).as_bytes()))
+            .map_err(|err| format!("could not write {}: {}", path.display(), err))
+    }
+
+    /**
+     * Returns the textual representation of the settings written over the
+     * given previous source: only the values of its settings are changed,
+     * keeping comments, and settings missing from it are added at the end
+     */
+    pub fn to_source(&self, previous: &str) -> String {
+        let mut source = String::new();
+        let mut written = Vec::new();
+        for line in previous.lines() {
+            let (setting, comment) = match line.find('#') {
+                Some(start) => line.split_at(start),
+                None => (line, ""),
+            };
+            let key = setting.split_whitespace().next().unwrap_or("");
+            match self.get(key) {
+                Some(value) => {
+                    let indent = &line[..line.len() - line.trim_left().len()];
+                    source.push_str(&format!("{}{} {}", indent, key, value));
+                    if !comment.is_empty() {
+                        source.push(' ');
+                        source.push_str(comment);
+                    }
+                    source.push('\n');
+                    written.push(key);
+                }
+                None => {
+                    source.push_str(line);
+                    source.push('\n');
+                }
+            }
+        }
+        for key in KEYS.iter().filter(|key| !written.contains(key)) {
+            source.push_str(&format!("{} {}\n", key, self.get(key).unwrap()));
+        }
+        source
+    }
+
+    /**
+     * Returns the speed of a shot with the longest aiming arrow
+     */
+    pub fn max_shot_speed(&self) -> f64 {
+        self.arrow_length * self.shot_speed
+    }
+
+    /**
+     * Returns the constant of the given name, if there is one
+     */
+    pub fn get(&self, key: &str) -> Option<f64> {
+        match key {
+            "friction" => Some(self.friction),
+            "gravity" => Some(self.gravity),
+            "stationary_speed" => Some(self.stationary_speed),
+            "goalzone_radius" => Some(self.goalzone_radius),
+            "shot_speed" => Some(self.shot_speed),
+            "aim_speed" => Some(self.aim_speed),
+            "arrow_length" => Some(self.arrow_length),
+            "restitution_scale" => Some(self.restitution_scale),
+            "blackhole_mass_scale" => Some(self.blackhole_mass_scale),
+            "blackhole_reach_scale" => Some(self.blackhole_reach_scale),
+            "time_scale" => Some(self.time_scale),
+            _ => None,
+        }
+    }
+
+    /**
+     * Sets the constant of the given name to the given value
+     */
+    pub fn set(&mut self, key: &str, value: f64) -> Result<(), String> {
+        if value < 0.0 || !value.is_finite() {
+            return Err(format!("{} can not be {}", key, value));
+        }
+        let field = match key {
+            "friction" => &mut self.friction,
+            "gravity" => &mut self.gravity,
+            "stationary_speed" => &mut self.stationary_speed,
+            "goalzone_radius" => &mut self.goalzone_radius,
+            "shot_speed" => &mut self.shot_speed,
+            "aim_speed" => &mut self.aim_speed,
+            "arrow_length" => &mut self.arrow_length,
+            "restitution_scale" => &mut self.restitution_scale,
+            "blackhole_mass_scale" => &mut self.blackhole_mass_scale,
+            "blackhole_reach_scale" => &mut self.blackhole_reach_scale,
+            "time_scale" => &mut self.time_scale,
+            _ => return Err(format!("unknown setting '{}'", key)),
+        };
+
+        // The game changes scaled values by the ratio of the new scale to the
+        // old one, which a zero scale would lose, and divides by the shot
+        // speed while aiming and multiplies by the arrow length when shooting
+        let divisor = key.ends_with("_scale") || key == "shot_speed" || key == "arrow_length";
+        if value == 0.0 && divisor {
+            return Err(format!("{} can not be 0", key));
+        }
+        *field = value;
+        Ok(())
+    }
+
+    /**
+     * Returns the settings with the given overrides of a level. The overrides
+     * are checked when the level is parsed, so unknown ones are skipped
+     */
+    pub fn overridden(&self, overrides: &[(String, f64)]) -> Settings {
+        let mut settings = self.clone();
+        for &(ref key, value) in overrides {
+            settings.set(key, value).ok();
+        }
+        settings
+    }
+}
+
+impl Watcher {
+    /**
+     * Watches the settings file at the given path, starting from its current
+     * contents
+     */
+    pub fn new(path: &Path) -> Watcher {
+        Watcher {
+            path: path.to_path_buf(),
+            modified: modified(path),
+            since_check: 0.0,
+        }
+    }
+
+    /**
+     * Lets the given time pass, returning the settings read anew if the file
+     * has changed since it was last read, or why they could not be read
+     */
+    pub fn poll(&mut self, delta_time: f64) -> Option<Result<Settings, String>> {
+        self.since_check += delta_time;
+        if self.since_check < CHECK_INTERVAL {
+            return None;
+        }
+        self.since_check = 0.0;
+
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(Settings::load_or_default(&self.path))
+    }
+}
+
+/**
+ * Parses a single `<key> <value>` line into the given settings
+ */
+fn parse_line(line: &parse::Line, settings: &mut Settings) -> Result<(), String> {
+    try!(line.expect_arguments(1));
+    try!(line.expect_no_options());
+    let value = try!(parse::parse_number(line.arguments[0]));
+    settings.set(line.kind, value)
+}
+
+/**
+ * Returns when the file at the given path was last changed, if it exists
+ */
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[test]
+fn test_parse() {
+    let settings = Settings::parse("
+        # Slower, floatier table
+        friction 0.05
+        gravity 1.5
+    ")
+        .unwrap();
+    assert_eq!(settings,
+               Settings {
+                   friction: 0.05,
+                   gravity: 1.5,
+                   ..Settings::new()
+               });
+
+    assert_eq!(Settings::parse("drag 0.1").unwrap_err(),
+               "line 1: unknown setting 'drag'");
+    assert!(Settings::parse("friction -0.1").is_err());
+    assert!(Settings::parse("friction").is_err());
+    assert!(Settings::parse("friction 0.1 extra=1").is_err());
+    assert!(Settings::parse("time_scale 0").is_err());
+    assert!(Settings::parse("shot_speed 0").is_err());
+    assert!(Settings::parse("arrow_length 0").is_err());
+    assert!(Settings::parse("friction 0").is_ok());
+}
+
+#[test]
+fn test_round_trip() {
+    let mut settings = Settings::new();
+    settings.set("blackhole_reach_scale", 1.5).unwrap();
+    assert_eq!(settings.get("blackhole_reach_scale"), Some(1.5));
+    assert_eq!(settings.get("drag"), None);
+    assert_eq!(Settings::parse(&settings.to_source("")).unwrap(), settings);
+
+    // Writing over a file keeps its comments and order
+    let previous = "# Slower table\ngravity 2\n\n# Less friction\nfriction 0.05 # tuned\n";
+    let source = settings.to_source(previous);
+    assert!(source.starts_with("# Slower table\ngravity 1\n\n# Less friction\n\
+                                friction 0.1 # tuned\n"));
+    assert!(source.ends_with("blackhole_reach_scale 1.5\ntime_scale 1\n"));
+    assert_eq!(Settings::parse(&source).unwrap(), settings);
+}
+
+#[test]
+fn test_overridden() {
+    let overrides = vec![(String::from("shot_speed"), 3.0), (String::from("drag"), 1.0)];
+    let settings = Settings::new().overridden(&overrides);
+    assert_eq!(settings.shot_speed, 3.0);
+    assert_eq!(settings.friction, Settings::new().friction);
+}
+
+#[test]
+fn test_watcher() {
+    use std::env;
+    use std::io::Write;
+
+    let path = env::temp_dir().join("rymdbiljard-test-watcher.cfg");
+    File::create(&path).and_then(|mut file| file.write_all(b"friction 0.2\n")).unwrap();
+    let mut watcher = Watcher::new(&path);
+    assert!(watcher.poll(1.0).is_none());
+
+    // A file changed since it was watched is read again
+    watcher.modified = None;
+    assert!(watcher.poll(0.1).is_none());
+    let settings = watcher.poll(1.0).unwrap().unwrap();
+    assert_eq!(settings.friction, 0.2);
+
+    // Removing the file goes back to the defaults
+    fs::remove_file(&path).unwrap();
+    assert_eq!(watcher.poll(1.0), Some(Ok(Settings::new())));
+}
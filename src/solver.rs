@@ -0,0 +1,276 @@
+use piston::input::UpdateArgs;
+use std::cmp::Ordering;
+use std::path::Path;
+use ai;
+use catalogue;
+use export;
+use game;
+use level;
+use parse;
+use poolball;
+use replay;
+use settings;
+
+/**
+ * Settings of the search for the fewest shots clearing a level: the number of
+ * games kept after every shot, the most shots tried, and the directions and
+ * speeds (as shares of the highest speed) tried for every shot
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solver {
+    pub beam_width: usize,
+    pub max_shots: usize,
+    pub directions: usize,
+    pub speeds: Vec<f64>,
+}
+
+/**
+ * A game reached by the search and how far it is from being cleared
+ */
+struct Candidate {
+    game: game::Game,
+    cost: f64,
+}
+
+// Longest time a shot is followed for before the next one, in seconds
+const SETTLE_TIME: f64 = 10.0;
+
+// Cost of every scoring poolball left, more than any poolball can add to the
+// cost by being far from the goalzones
+const REMAINING_COST: f64 = 10.0;
+
+const USAGE: &'static str = "usage: solve <level> [output] [beam=<games>] [shots=<shots>] \
+                             [directions=<directions>]";
+
+impl Solver {
+    /**
+     * Creates a search keeping 8 games after every shot and trying up to 5
+     * shots, in 72 directions at three speeds
+     */
+    pub fn new() -> Solver {
+        Solver {
+            beam_width: 8,
+            max_shots: 5,
+            directions: 72,
+            speeds: vec![0.3, 0.6, 1.0],
+        }
+    }
+
+    /**
+     * Keeps the given number of games after every shot
+     */
+    pub fn with_beam_width(mut self, beam_width: usize) -> Solver {
+        self.beam_width = beam_width;
+        self
+    }
+
+    /**
+     * Tries at most the given number of shots
+     */
+    pub fn with_max_shots(mut self, max_shots: usize) -> Solver {
+        self.max_shots = max_shots;
+        self
+    }
+
+    /**
+     * Tries shots in the given number of directions
+     */
+    pub fn with_directions(mut self, directions: usize) -> Solver {
+        self.directions = directions;
+        self
+    }
+
+    /**
+     * Searches for the fewest shots potting every scoring poolball of the
+     * given game, trying every candidate shot from each of the most promising
+     * games after every shot. Returns the shots, each with the time it is
+     * taken, or why the level appears to be unsolvable
+     */
+    pub fn solve(&self, game: &game::Game) -> Result<Vec<replay::Shot>, String> {
+        try!(check_pottable(game));
+        if remaining(game) == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut beam = vec![Candidate {
+                                game: game.clone(),
+                                cost: cost(game),
+                            }];
+        for _ in 0..self.max_shots {
+            let mut next = Vec::new();
+            for candidate in &beam {
+                let shots = ai::candidate_shots(&candidate.game, self.directions, &self.speeds);
+                for shot in shots {
+                    let mut played = candidate.game.clone();
+                    if !played.shoot(&shot) || !settle(&mut played) {
+                        continue;
+                    }
+                    if remaining(&played) == 0 {
+                        return Ok(played.shots().clone());
+                    }
+                    let cost = cost(&played);
+                    next.push(Candidate {
+                        game: played,
+                        cost: cost,
+                    });
+                }
+            }
+
+            if next.is_empty() {
+                return Err(String::from("every shot loses the game"));
+            }
+            next.sort_by(|a, b| a.cost.partial_cmp(&b.cost).unwrap_or(Ordering::Equal));
+            next.truncate(self.beam_width);
+            beam = next;
+        }
+
+        Err(format!("no solution found within {} shots", self.max_shots))
+    }
+}
+
+/**
+ * Runs the solve command with the given arguments, writing the solution of
+ * the level as a replay and returning the number of shots needed and the path
+ * of the replay
+ */
+pub fn run(arguments: &[String],
+           catalogue: catalogue::Catalogue,
+           settings: &settings::Settings)
+           -> Result<(usize, String), String> {
+    if arguments.is_empty() {
+        return Err(String::from(USAGE));
+    }
+    let level_path = &arguments[0];
+    let (output, options) = match arguments.get(1) {
+        Some(output) if !output.contains('=') => (output.as_str(), &arguments[2..]),
+        _ => ("solution.rpl", &arguments[1..]),
+    };
+
+    let mut solver = Solver::new();
+    for option in options {
+        let mut parts = option.splitn(2, '=');
+        let (key, value) = (parts.next().unwrap(), parts.next().unwrap_or(""));
+        match key {
+            "beam" => solver = solver.with_beam_width(try!(parse_count(value))),
+            "shots" => solver = solver.with_max_shots(try!(parse_count(value))),
+            "directions" => solver = solver.with_directions(try!(parse_count(value))),
+            _ => return Err(format!("unknown option '{}'\n{}", key, USAGE)),
+        }
+    }
+
+    let level = try!(level::Level::load(Path::new(level_path), &catalogue));
+    let mut game = game::Game::new(level, catalogue);
+    game.set_settings(settings);
+    let shots = try!(solver.solve(&game)
+        .map_err(|err| format!("{} appears to be unsolvable: {}", level_path, err)));
+
+    let count = shots.len();
+    try!(replay::Replay::new(level_path, shots).save(Path::new(output)));
+    Ok((count, String::from(output)))
+}
+
+/**
+ * Parses a positive whole number of an option
+ */
+fn parse_count(value: &str) -> Result<usize, String> {
+    let count = try!(parse::parse_integer(value));
+    if count < 1 {
+        return Err(format!("'{}' is not a positive number", value));
+    }
+    Ok(count as usize)
+}
+
+/**
+ * Returns an error if a scoring poolball of the given game is not accepted by
+ * any goalzone, so that the level can never be cleared
+ */
+fn check_pottable(game: &game::Game) -> Result<(), String> {
+    for ball in game.balls() {
+        if is_scoring(ball) && !game.goalzones().iter().any(|zone| zone.accepts(ball)) {
+            return Err(format!("no goalzone accepts the {} ball at {}, {}",
+                               ball.ball_type.name(),
+                               ball.position.x,
+                               ball.position.y));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Returns `true` for poolballs worth potting
+ */
+fn is_scoring(ball: &poolball::Poolball) -> bool {
+    ball.ball_type != poolball::BallType::White && ball.get_value() > 0
+}
+
+/**
+ * Returns the number of scoring poolballs left on the table
+ */
+fn remaining(game: &game::Game) -> usize {
+    game.balls().iter().filter(|ball| is_scoring(ball)).count()
+}
+
+/**
+ * Returns how far the given game is from being cleared: the scoring poolballs
+ * left, then how far they are from the goalzones
+ */
+fn cost(game: &game::Game) -> f64 {
+    remaining(game) as f64 * REMAINING_COST + ai::spread(game)
+}
+
+/**
+ * Plays the given game until the table comes to rest, or for a while on
+ * tables that never do, returning `true` if the white ball can then be shot
+ */
+fn settle(game: &mut game::Game) -> bool {
+    let args = UpdateArgs { dt: 1.0 / export::UPDATES_PER_SECOND as f64 };
+    let updates = (SETTLE_TIME * export::UPDATES_PER_SECOND as f64) as usize;
+    for _ in 0..updates {
+        game.update(&args);
+        if game.is_over() {
+            return false;
+        }
+        if game.is_at_rest() {
+            break;
+        }
+    }
+    game.can_shoot()
+}
+
+#[test]
+fn test_solve() {
+    let game = game::test_game("
+        ball white 0.3 0.5
+        ball red 0.6 0.5
+        goalzone 0.9 0.5 accepts=red
+    ");
+    let solver = Solver { speeds: vec![1.0], ..Solver::new().with_directions(8) };
+
+    // Straight through the red ball, which is replayed the same way
+    let shots = solver.solve(&game).unwrap();
+    assert_eq!(shots.len(), 1);
+    assert_eq!(shots[0].time, Some(0.0));
+    let mut replayed = game.clone();
+    replayed.shoot(&shots[0]);
+    assert!(settle(&mut replayed));
+    assert_eq!(remaining(&replayed), 0);
+}
+
+#[test]
+fn test_unsolvable() {
+    let game = game::test_game("
+        ball white 0.3 0.5
+        ball red 0.6 0.5
+        goalzone 0.9 0.5 accepts=blue
+    ");
+    assert!(Solver::new().solve(&game).unwrap_err().contains("no goalzone accepts"));
+
+    // Shots that can not reach the goalzone are not enough
+    let game = game::test_game("
+        ball white 0.3 0.5
+        ball red 0.6 0.5
+        goalzone 0.9 0.5 accepts=red
+    ");
+    let solver = Solver { speeds: vec![0.01], ..Solver::new().with_directions(4) };
+    assert_eq!(solver.solve(&game).unwrap_err(), "no solution found within 5 shots");
+}
@@ -0,0 +1,277 @@
+use na::{Point2, Vector2};
+use std::f64;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use renderer::{Color, Renderer};
+use camera;
+use game;
+use replay;
+use trace;
+
+// Thickness and opacity of the traced paths of the poolballs
+const TRAIL_THICKNESS: f64 = 0.003;
+const TRAIL_OPACITY: f32 = 0.7;
+
+/**
+ * A renderer building an SVG document, for viewing and analysing a table in
+ * the browser. The table is drawn as large as fits in the middle of the image,
+ * in the same way as on a canvas
+ */
+pub struct SvgRenderer {
+    pub width: usize,
+    pub height: usize,
+    pub camera: camera::Camera,
+    elements: Vec<String>,
+}
+
+impl SvgRenderer {
+    /**
+     * Creates an empty image of the given size in pixels
+     */
+    pub fn new(width: usize, height: usize) -> SvgRenderer {
+        SvgRenderer {
+            width: width,
+            height: height,
+            camera: camera::Camera::new(width as f64, height as f64),
+            elements: Vec::new(),
+        }
+    }
+
+    /**
+     * Returns the SVG document of everything drawn so far
+     */
+    pub fn to_svg(&self) -> String {
+        let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" \
+                               height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n",
+                              self.width,
+                              self.height);
+        for element in &self.elements {
+            svg.push_str(element);
+            svg.push('\n');
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /**
+     * Converts a position on the table to a position in pixels
+     */
+    fn to_pixels(&self, point: &Point2<f64>) -> Point2<f64> {
+        self.camera.to_screen(point)
+    }
+
+    /**
+     * Returns the stroke attributes of a line of the given color and thickness
+     */
+    fn stroke(&self, color: Color, thickness: f64) -> String {
+        format!("fill=\"none\" {} stroke-width=\"{:.2}\" stroke-linecap=\"round\"",
+                paint("stroke", color),
+                (thickness * self.camera.scale()).max(1.0))
+    }
+}
+
+impl Renderer for SvgRenderer {
+    fn clear(&mut self, color: Color) {
+        self.elements.clear();
+        let background = format!("<rect width=\"{}\" height=\"{}\" {}/>",
+                                 self.width,
+                                 self.height,
+                                 paint("fill", color));
+        self.elements.push(background);
+    }
+
+    fn circle(&mut self, color: Color, center: Point2<f64>, radius: f64) {
+        let center = self.to_pixels(&center);
+        let element = format!("<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" {}/>",
+                              center.x,
+                              center.y,
+                              radius * self.camera.scale(),
+                              paint("fill", color));
+        self.elements.push(element);
+    }
+
+    fn arc(&mut self,
+           color: Color,
+           center: Point2<f64>,
+           radius: f64,
+           thickness: f64,
+           start: f64,
+           end: f64) {
+        let stroke = self.stroke(color, thickness);
+        let pixels = self.to_pixels(&center);
+        let radius_pixels = radius * self.camera.scale();
+
+        // A full circle cannot be drawn as a single arc of a path
+        let element = if end - start >= 2.0 * f64::consts::PI {
+            format!("<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" {}/>",
+                    pixels.x,
+                    pixels.y,
+                    radius_pixels,
+                    stroke)
+        } else {
+            let from = self.to_pixels(&(center + Vector2::new(start.cos(), start.sin()) * radius));
+            let to = self.to_pixels(&(center + Vector2::new(end.cos(), end.sin()) * radius));
+            let large = if end - start > f64::consts::PI { 1 } else { 0 };
+            format!("<path d=\"M {:.2} {:.2} A {:.2} {:.2} 0 {} 1 {:.2} {:.2}\" {}/>",
+                    from.x,
+                    from.y,
+                    radius_pixels,
+                    radius_pixels,
+                    large,
+                    to.x,
+                    to.y,
+                    stroke)
+        };
+        self.elements.push(element);
+    }
+
+    fn line(&mut self, color: Color, from: Point2<f64>, to: Point2<f64>, thickness: f64) {
+        let (from, to) = (self.to_pixels(&from), self.to_pixels(&to));
+        let element = format!("<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" {}/>",
+                              from.x,
+                              from.y,
+                              to.x,
+                              to.y,
+                              self.stroke(color, thickness));
+        self.elements.push(element);
+    }
+
+    fn path(&mut self, color: Color, points: &[Point2<f64>], thickness: f64) {
+        let coordinates: Vec<String> = points.iter()
+            .map(|point| {
+                let point = self.to_pixels(point);
+                format!("{:.2},{:.2}", point.x, point.y)
+            })
+            .collect();
+        let element = format!("<polyline points=\"{}\" {} stroke-linejoin=\"round\"/>",
+                              coordinates.join(" "),
+                              self.stroke(color, thickness));
+        self.elements.push(element);
+    }
+
+    fn text(&mut self, color: Color, size: u32, position: Point2<f64>, text: &str) {
+        let position = self.camera.view_to_screen(&position);
+        let element = format!("<text x=\"{:.2}\" y=\"{:.2}\" font-family=\"sans-serif\" \
+                               font-size=\"{}\" {}>{}</text>",
+                              position.x,
+                              position.y,
+                              size,
+                              paint("fill", color),
+                              escape(text));
+        self.elements.push(element);
+    }
+
+    fn to_view(&self, position: Point2<f64>) -> Point2<f64> {
+        self.camera.to_view(&self.camera.to_screen(&position))
+    }
+}
+
+/**
+ * Renders the table of the given game with the aiming arrow pointing out the
+ * given shot, and the paths the poolballs take during the shot
+ */
+pub fn render_shot(game: &game::Game,
+                   shot: Option<&replay::Shot>,
+                   width: usize,
+                   height: usize)
+                   -> String {
+    let mut game = game.clone();
+    let trails = match shot {
+        Some(shot) => {
+            game.aim(shot);
+            trace::trace_shot(&game, shot)
+        }
+        None => Vec::new(),
+    };
+
+    let mut svg = SvgRenderer::new(width, height);
+    game.render(&mut svg);
+    for trail in &trails {
+        let mut color = trail.color;
+        color[3] = TRAIL_OPACITY;
+        svg.path(color, &trail.points, TRAIL_THICKNESS);
+    }
+    svg.to_svg()
+}
+
+/**
+ * Writes the table and the paths of the given shot to an SVG file at the given
+ * path
+ */
+pub fn save(game: &game::Game,
+            shot: Option<&replay::Shot>,
+            path: &Path,
+            width: usize,
+            height: usize)
+            -> Result<(), String> {
+    let svg = render_shot(game, shot, width, height);
+    File::create(path)
+        .and_then(|mut file| file.write_all(svg.as_bytes()))
+        .map_err(|err| format!("could not write {}: {}", path.display(), err))
+}
+
+/**
+ * Returns the attributes painting the given property with the given color
+ */
+fn paint(property: &str, color: Color) -> String {
+    let channel = |value: f32| (value.max(0.0).min(1.0) * 255.0).round() as u8;
+    format!("{0}=\"rgb({1},{2},{3})\" {0}-opacity=\"{4:.3}\"",
+            property,
+            channel(color[0]),
+            channel(color[1]),
+            channel(color[2]),
+            color[3].max(0.0).min(1.0))
+}
+
+/**
+ * Escapes the characters with a special meaning in XML text
+ */
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[test]
+fn test_elements() {
+    let mut svg = SvgRenderer::new(200, 100);
+    svg.clear([0.0, 0.0, 0.0, 1.0]);
+    svg.circle([1.0, 0.0, 0.0, 0.5], Point2::new(0.5, 0.5), 0.1);
+    svg.line([1.0, 1.0, 1.0, 1.0], Point2::new(0.0, 0.0), Point2::new(1.0, 1.0), 0.01);
+    svg.ring([0.0, 1.0, 0.0, 1.0], Point2::new(0.5, 0.5), 0.2, 0.01);
+    svg.text([1.0, 1.0, 1.0, 1.0], 12, Point2::new(0.0, 0.5), "a < b & c");
+
+    // The table is letterboxed in the middle of the image
+    let document = svg.to_svg();
+    assert!(document.starts_with("<svg "));
+    assert!(document.contains("viewBox=\"0 0 200 100\""));
+    assert!(document.contains("<circle cx=\"100.00\" cy=\"50.00\" r=\"10.00\" \
+                               fill=\"rgb(255,0,0)\" fill-opacity=\"0.500\"/>"));
+    assert!(document.contains("<line x1=\"50.00\" y1=\"0.00\" x2=\"150.00\" y2=\"100.00\""));
+    assert!(document.contains("r=\"20.00\" fill=\"none\" stroke=\"rgb(0,255,0)\""));
+    assert!(document.contains(">a &lt; b &amp; c</text>"));
+    assert!(document.ends_with("</svg>\n"));
+}
+
+#[test]
+fn test_render_shot() {
+    let source = "
+        ball white 0.3 0.5
+        ball red 0.6 0.5
+        blackhole 0.5 0.9 0.0001 0.01 0.05
+        goalzone 0.9 0.1
+    ";
+    let game = game::test_game(source);
+    let shot = replay::Shot {
+        angle: 0.0,
+        speed: 0.5,
+        time: None,
+    };
+
+    let still = render_shot(&game, None, 100, 100);
+    assert!(!still.contains("<polyline"));
+
+    // Both poolballs leave a trail
+    let traced = render_shot(&game, Some(&shot), 100, 100);
+    assert_eq!(traced.matches("<polyline").count(), 2);
+    assert!(traced.len() > still.len());
+}
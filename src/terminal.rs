@@ -0,0 +1,470 @@
+use piston::input::UpdateArgs;
+use na::Point2;
+use std::cmp;
+use std::f64;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use renderer::{Color, Renderer};
+use canvas;
+use catalogue;
+use export;
+use game;
+use level;
+use replay;
+use settings;
+
+/**
+ * A renderer drawing the table as coloured characters for a terminal. Every
+ * character cell shows two pixels of a canvas stacked with a half block, and
+ * text is written as plain characters on top
+ */
+pub struct Screen {
+    pub columns: usize,
+    pub rows: usize,
+    canvas: canvas::Canvas,
+    texts: Vec<(Color, Point2<f64>, String)>,
+}
+
+/**
+ * Commands given with the keyboard in the terminal
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    RotateLeft,
+    RotateRight,
+    MorePower,
+    LessPower,
+    Shoot,
+    Integrator,
+    Diagnostics,
+    Field,
+    Quit,
+}
+
+/**
+ * A single character cell with its foreground and background colours
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Cell {
+    character: char,
+    foreground: [u8; 3],
+    background: [u8; 3],
+}
+
+const FRAMES_PER_SECOND: u64 = 30;
+
+// Change of the aim per key press, in radians and in speed
+const ANGLE_STEP: f64 = 0.05;
+const SPEED_STEP: f64 = 0.05;
+const MIN_SPEED: f64 = 0.05;
+
+const USAGE: &'static str = "usage: terminal [level] [columns=<n>] [rows=<n>]";
+
+const HELP: &'static str = "left/right: aim  up/down: power  space: shoot  i: integrator  \
+                            d: diagnostics  f: field  q: quit";
+
+impl Screen {
+    /**
+     * Creates a blank screen of the given number of character cells
+     */
+    pub fn new(columns: usize, rows: usize) -> Screen {
+        Screen {
+            columns: columns,
+            rows: rows,
+            canvas: canvas::Canvas::new(columns, rows * 2),
+            texts: Vec::new(),
+        }
+    }
+
+    /**
+     * Returns the screen as text with ANSI escape codes, drawn from the top
+     * left corner of the terminal
+     */
+    pub fn to_ansi(&self) -> String {
+        let cells = self.cells();
+        let mut output = String::from("\x1b[H");
+        for (row, line) in cells.chunks(self.columns).enumerate() {
+            let mut previous: Option<([u8; 3], [u8; 3])> = None;
+            for cell in line {
+                let colors = (cell.foreground, cell.background);
+                if previous != Some(colors) {
+                    output.push_str(&format!("\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                                             cell.foreground[0],
+                                             cell.foreground[1],
+                                             cell.foreground[2],
+                                             cell.background[0],
+                                             cell.background[1],
+                                             cell.background[2]));
+                    previous = Some(colors);
+                }
+                output.push(cell.character);
+            }
+            output.push_str("\x1b[0m");
+            if row + 1 < self.rows {
+                output.push_str("\r\n");
+            }
+        }
+        output
+    }
+
+    /**
+     * Returns the character cells of the screen, row by row
+     */
+    fn cells(&self) -> Vec<Cell> {
+        let mut cells = Vec::with_capacity(self.columns * self.rows);
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                cells.push(Cell {
+                    character: '▀',
+                    foreground: opaque(self.canvas.pixel(column, row * 2)),
+                    background: opaque(self.canvas.pixel(column, row * 2 + 1)),
+                });
+            }
+        }
+
+        // Text stands on its position like on the other renderers
+        for &(color, position, ref text) in &self.texts {
+            let origin = self.canvas.camera.view_to_screen(&position);
+            if origin.x < 0.0 || origin.y < 0.0 {
+                continue;
+            }
+            let row = cmp::min(((origin.y / 2.0).ceil() as usize).saturating_sub(1),
+                               self.rows - 1);
+            let start = origin.x.round() as usize;
+            for (index, character) in text.chars().enumerate() {
+                if start + index >= self.columns {
+                    break;
+                }
+                let cell = &mut cells[row * self.columns + start + index];
+                cell.character = character;
+                cell.foreground = opaque([(color[0].max(0.0).min(1.0) * 255.0) as u8,
+                                          (color[1].max(0.0).min(1.0) * 255.0) as u8,
+                                          (color[2].max(0.0).min(1.0) * 255.0) as u8,
+                                          255]);
+            }
+        }
+        cells
+    }
+
+    /**
+     * Returns the smallest radius, in table units, drawn as at least a pixel
+     * so that poolballs stay visible on small terminals
+     */
+    fn min_radius(&self) -> f64 {
+        0.75 / self.canvas.camera.scale()
+    }
+}
+
+impl Renderer for Screen {
+    fn clear(&mut self, color: Color) {
+        self.canvas.clear(color);
+        self.texts.clear();
+    }
+
+    fn circle(&mut self, color: Color, center: Point2<f64>, radius: f64) {
+        let radius = radius.max(self.min_radius());
+        self.canvas.circle(color, center, radius);
+    }
+
+    fn arc(&mut self,
+           color: Color,
+           center: Point2<f64>,
+           radius: f64,
+           thickness: f64,
+           start: f64,
+           end: f64) {
+        self.canvas.arc(color, center, radius, thickness, start, end);
+    }
+
+    fn line(&mut self, color: Color, from: Point2<f64>, to: Point2<f64>, thickness: f64) {
+        self.canvas.line(color, from, to, thickness);
+    }
+
+    fn text(&mut self, color: Color, _size: u32, position: Point2<f64>, text: &str) {
+        self.texts.push((color, position, String::from(text)));
+    }
+
+    fn to_view(&self, position: Point2<f64>) -> Point2<f64> {
+        self.canvas.to_view(position)
+    }
+}
+
+/**
+ * Plays the level given in the arguments, or the classic table, in the
+ * terminal until the game is over or the player quits, returning the score
+ */
+pub fn run(arguments: &[String],
+           catalogue: catalogue::Catalogue,
+           settings: &settings::Settings)
+           -> Result<i32, String> {
+    let mut level_path = "levels/classic.lvl";
+    let (mut columns, mut rows) = terminal_size();
+    for argument in arguments {
+        let mut parts = argument.splitn(2, '=');
+        let (key, value) = (parts.next().unwrap(), parts.next());
+        match (key, value) {
+            ("columns", Some(value)) => columns = try!(parse_cells(value)),
+            ("rows", Some(value)) => rows = try!(parse_cells(value)),
+            (path, None) => level_path = path,
+            _ => return Err(format!("unknown option '{}'\n{}", key, USAGE)),
+        }
+    }
+    if rows < 2 {
+        return Err(String::from("the terminal needs at least two rows"));
+    }
+
+    let level = try!(level::Level::load(Path::new(level_path), &catalogue));
+    let mut game = game::Game::new(level, catalogue);
+    game.set_settings(settings);
+    let mut aim = replay::Shot {
+        angle: -f64::consts::FRAC_PI_2,
+        speed: 0.5,
+        time: None,
+    };
+
+    let _raw_mode = try!(RawMode::enable());
+    let input = read_input();
+    let args = UpdateArgs { dt: 1.0 / export::UPDATES_PER_SECOND as f64 };
+    let frame_time = Duration::from_millis(1000 / FRAMES_PER_SECOND);
+    let mut stdout = io::stdout();
+
+    loop {
+        let start = Instant::now();
+
+        while let Ok(bytes) = input.try_recv() {
+            for command in parse_input(&bytes) {
+                match command {
+                    Input::RotateLeft => aim.angle -= ANGLE_STEP,
+                    Input::RotateRight => aim.angle += ANGLE_STEP,
+                    Input::MorePower => {
+                        let max_speed = game.settings().max_shot_speed();
+                        aim.speed = (aim.speed + SPEED_STEP).min(max_speed);
+                    }
+                    Input::LessPower => aim.speed = (aim.speed - SPEED_STEP).max(MIN_SPEED),
+                    Input::Shoot => {
+                        game.shoot(&aim);
+                    }
+                    Input::Integrator => {
+                        let next = game.integrator().next();
+                        game.set_integrator(next);
+                    }
+                    Input::Diagnostics => game.toggle_diagnostics(),
+                    Input::Field => {
+                        let next = game.field_view().next();
+                        game.set_field_view(next);
+                    }
+                    Input::Quit => return Ok(game.score()),
+                }
+            }
+        }
+
+        for _ in 0..export::UPDATES_PER_SECOND / FRAMES_PER_SECOND {
+            game.update(&args);
+        }
+        if game.is_over() {
+            return Ok(game.score());
+        }
+
+        // The arrow follows the aim given with the keyboard
+        game.aim(&aim);
+        let mut screen = Screen::new(columns, rows - 1);
+        game.render(&mut screen);
+        let status: String = format!("aim {:.2} power {:.2}  {}", aim.angle, aim.speed, HELP)
+            .chars()
+            .take(columns)
+            .collect();
+        try!(write!(stdout, "{}\r\n\x1b[2K{}", screen.to_ansi(), status)
+            .and_then(|_| stdout.flush())
+            .map_err(|err| format!("could not draw: {}", err)));
+
+        let elapsed = start.elapsed();
+        if elapsed < frame_time {
+            thread::sleep(frame_time - elapsed);
+        }
+    }
+}
+
+/**
+ * Turns the bytes read from the terminal into commands, ignoring unknown keys
+ */
+pub fn parse_input(bytes: &[u8]) -> Vec<Input> {
+    let mut commands = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        // Arrow keys are sent as escape sequences, a lone escape quits
+        if bytes[index] == 0x1b {
+            if index + 2 < bytes.len() && bytes[index + 1] == b'[' {
+                match bytes[index + 2] {
+                    b'A' => commands.push(Input::MorePower),
+                    b'B' => commands.push(Input::LessPower),
+                    b'C' => commands.push(Input::RotateRight),
+                    b'D' => commands.push(Input::RotateLeft),
+                    _ => {}
+                }
+                index += 3;
+            } else {
+                commands.push(Input::Quit);
+                index += 1;
+            }
+            continue;
+        }
+
+        match bytes[index] {
+            b' ' | b'\r' | b'\n' => commands.push(Input::Shoot),
+            b'i' => commands.push(Input::Integrator),
+            b'd' => commands.push(Input::Diagnostics),
+            b'f' => commands.push(Input::Field),
+            // Ctrl-C is read as a plain byte in raw mode
+            b'q' | 0x03 => commands.push(Input::Quit),
+            _ => {}
+        }
+        index += 1;
+    }
+    commands
+}
+
+/**
+ * Keeps the terminal in raw mode on the alternate screen, restoring it when
+ * dropped
+ */
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> Result<RawMode, String> {
+        let saved = try!(stty(&["-g"]));
+        try!(stty(&["raw", "-echo"]));
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        Ok(RawMode { saved: saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/**
+ * Runs stty on the terminal with the given arguments, returning its output
+ */
+fn stty(arguments: &[&str]) -> Result<String, String> {
+    let output = try!(Command::new("stty")
+        .args(arguments)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|err| format!("could not run stty: {}", err)));
+    if !output.status.success() {
+        return Err(String::from("standard input is not a terminal"));
+    }
+    Ok(String::from(String::from_utf8_lossy(&output.stdout).trim()))
+}
+
+/**
+ * Returns the number of columns and rows of the terminal, or the classic
+ * 80x24 if it cannot be found
+ */
+fn terminal_size() -> (usize, usize) {
+    if let Ok(size) = stty(&["size"]) {
+        let numbers: Vec<usize> = size.split_whitespace()
+            .filter_map(|word| word.parse().ok())
+            .collect();
+        if numbers.len() == 2 && numbers[0] > 0 && numbers[1] > 0 {
+            return (numbers[1], numbers[0]);
+        }
+    }
+    (80, 24)
+}
+
+/**
+ * Reads the terminal input on a separate thread, so that the game keeps
+ * running while no key is pressed
+ */
+fn read_input() -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut buffer = [0; 32];
+        loop {
+            match stdin.lock().read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => {
+                    if sender.send(buffer[..read].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+    receiver
+}
+
+/**
+ * Returns the given RGBA pixel drawn over black
+ */
+fn opaque(pixel: [u8; 4]) -> [u8; 3] {
+    let alpha = pixel[3] as u32;
+    [(pixel[0] as u32 * alpha / 255) as u8,
+     (pixel[1] as u32 * alpha / 255) as u8,
+     (pixel[2] as u32 * alpha / 255) as u8]
+}
+
+/**
+ * Parses a positive number of character cells
+ */
+fn parse_cells(word: &str) -> Result<usize, String> {
+    match word.parse::<usize>() {
+        Ok(cells) if cells > 0 => Ok(cells),
+        _ => Err(format!("'{}' is not a valid number of cells", word)),
+    }
+}
+
+#[test]
+fn test_parse_input() {
+    assert_eq!(parse_input(b"\x1b[A\x1b[D x"),
+               vec![Input::MorePower, Input::RotateLeft, Input::Shoot]);
+    assert_eq!(parse_input(b"id\x1b[C\x1b[B"),
+               vec![Input::Integrator, Input::Diagnostics, Input::RotateRight, Input::LessPower]);
+    assert_eq!(parse_input(b"\x1b"), vec![Input::Quit]);
+    assert_eq!(parse_input(b"q"), vec![Input::Quit]);
+}
+
+#[test]
+fn test_screen() {
+    let mut screen = Screen::new(20, 10);
+    screen.clear([0.0, 0.0, 0.0, 1.0]);
+    screen.circle([1.0, 0.0, 0.0, 1.0], Point2::new(0.5, 0.5), 0.2);
+    screen.text([0.0, 1.0, 0.0, 1.0], 18, Point2::new(0.25, 0.2), "Hi");
+
+    // The table is letterboxed with square pixels, two per cell
+    let cells = screen.cells();
+    let middle = cells[5 * 20 + 10];
+    assert_eq!(middle.character, '▀');
+    assert_eq!(middle.foreground, [255, 0, 0]);
+    assert_eq!(middle.background, [255, 0, 0]);
+    assert_eq!(cells[5 * 20 + 1].foreground, [0, 0, 0]);
+
+    let text = &cells[25..27];
+    assert_eq!(text[0].character, 'H');
+    assert_eq!(text[1].character, 'i');
+    assert_eq!(text[1].foreground, [0, 255, 0]);
+
+    // Tiny poolballs still light up a pixel
+    screen.clear([0.0, 0.0, 0.0, 1.0]);
+    screen.circle([1.0, 1.0, 1.0, 1.0], Point2::new(0.55, 0.55), 0.001);
+    let lit = screen.cells()
+        .iter()
+        .any(|cell| cell.foreground[0] > 128 || cell.background[0] > 128);
+    assert!(lit);
+
+    let ansi = screen.to_ansi();
+    assert!(ansi.starts_with("\x1b[H"));
+    assert_eq!(ansi.matches("\r\n").count(), 9);
+}
@@ -0,0 +1,105 @@
+use piston::input::UpdateArgs;
+use na::{Point2, FloatPoint};
+use std::collections::HashMap;
+use renderer::Color;
+use export;
+use game;
+use replay;
+
+/**
+ * The path travelled by a single poolball during a shot
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trail {
+    pub color: Color,
+    pub points: Vec<Point2<f64>>,
+}
+
+// Longest time a shot is followed for, in seconds
+pub const MAX_TIME: f64 = 30.0;
+
+// Shortest distance between two recorded points of a trail
+const MIN_STEP: f64 = 0.001;
+
+/**
+ * Plays the given shot on a copy of the game until the table comes to rest,
+ * returning the trails of the poolballs that moved. Nothing is traced if the
+ * shot cannot be taken
+ */
+pub fn trace_shot(game: &game::Game, shot: &replay::Shot) -> Vec<Trail> {
+    let mut game = game.clone();
+    if !game.shoot(shot) {
+        return Vec::new();
+    }
+
+    let args = UpdateArgs { dt: 1.0 / export::UPDATES_PER_SECOND as f64 };
+    let mut trails = Vec::new();
+    let mut indices: HashMap<usize, usize> = HashMap::new();
+    record(&game, &mut trails, &mut indices);
+
+    let updates = (MAX_TIME * export::UPDATES_PER_SECOND as f64) as usize;
+    for _ in 0..updates {
+        game.update(&args);
+        record(&game, &mut trails, &mut indices);
+        if game.is_over() || game.is_at_rest() {
+            break;
+        }
+    }
+
+    trails.into_iter().filter(|trail| trail.points.len() > 1).collect()
+}
+
+/**
+ * Adds the current position of every poolball to its trail, skipping
+ * positions too close to the previous one
+ */
+fn record(game: &game::Game, trails: &mut Vec<Trail>, indices: &mut HashMap<usize, usize>) {
+    for ball in game.balls() {
+        let index = *indices.entry(ball.id).or_insert_with(|| {
+            trails.push(Trail {
+                color: ball.color,
+                points: Vec::new(),
+            });
+            trails.len() - 1
+        });
+
+        let points = &mut trails[index].points;
+        let moved = match points.last() {
+            Some(last) => last.distance(&ball.position) >= MIN_STEP,
+            None => true,
+        };
+        if moved {
+            points.push(ball.position);
+        }
+    }
+}
+
+#[test]
+fn test_trace_shot() {
+    let source = "
+        ball white 0.3 0.5
+        ball red 0.6 0.5
+        ball red 0.6 0.8
+    ";
+    let game = game::test_game(source);
+    let shot = replay::Shot {
+        angle: 0.0,
+        speed: 0.5,
+        time: None,
+    };
+
+    // The white ball hits the first red ball, the other one stays put
+    let trails = trace_shot(&game, &shot);
+    assert_eq!(trails.len(), 2);
+    assert_eq!(trails[0].points[0], Point2::new(0.3, 0.5));
+    assert!(trails[1].points.last().unwrap().x > 0.6);
+    for trail in &trails {
+        for pair in trail.points.windows(2) {
+            assert!((pair[1].y - pair[0].y).abs() < 0.000001);
+        }
+    }
+
+    // The traced game is left untouched
+    assert!(game.shots().is_empty());
+    assert!(game.is_at_rest());
+}
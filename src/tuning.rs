@@ -0,0 +1,271 @@
+use na::Point2;
+use std::iter;
+use renderer::{Color, Renderer};
+use settings;
+
+/**
+ * Input for the tuning panel. Positions are given in view coordinates
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Up,
+    Down,
+    Left,
+    Right,
+    Point(Point2<f64>),
+    Export,
+}
+
+/**
+ * What the tuning panel asks of the game after some input
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Changed,
+    Export,
+}
+
+/**
+ * A setting adjusted by a slider between the given bounds
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Slider {
+    key: &'static str,
+    label: &'static str,
+    min: f64,
+    max: f64,
+}
+
+/**
+ * Sliders for tuning the physics of the running game. The sliders are
+ * selected with the arrow keys or by pointing, and moved a step at a time
+ * with the left and right arrow keys
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Panel {
+    pub open: bool,
+    pub message: Option<String>,
+    selected: usize,
+}
+
+const SLIDERS: [Slider; 7] = [Slider {
+                                  key: "friction",
+                                  label: "Friction",
+                                  min: 0.0,
+                                  max: 0.5,
+                              },
+                              Slider {
+                                  key: "restitution_scale",
+                                  label: "Restitution",
+                                  min: 0.1,
+                                  max: 1.5,
+                              },
+                              Slider {
+                                  key: "gravity",
+                                  label: "Gravity",
+                                  min: 0.0,
+                                  max: 5.0,
+                              },
+                              Slider {
+                                  key: "blackhole_mass_scale",
+                                  label: "Blackhole mass",
+                                  min: 0.1,
+                                  max: 5.0,
+                              },
+                              Slider {
+                                  key: "blackhole_reach_scale",
+                                  label: "Blackhole reach",
+                                  min: 0.1,
+                                  max: 3.0,
+                              },
+                              Slider {
+                                  key: "shot_speed",
+                                  label: "Shot speed",
+                                  min: 1.0,
+                                  max: 15.0,
+                              },
+                              Slider {
+                                  key: "time_scale",
+                                  label: "Time scale",
+                                  min: 0.1,
+                                  max: 3.0,
+                              }];
+
+// Steps from one end of a slider to the other, and the bars it is drawn with
+const STEPS: f64 = 50.0;
+const BARS: usize = 25;
+
+// Position of the first slider and the distance between sliders, in view
+// coordinates
+const LEFT: f64 = 0.02;
+const TOP: f64 = 0.12;
+const SPACING: f64 = 0.035;
+const WIDTH: f64 = 0.4;
+
+const WHITE: Color = [1.0, 1.0, 1.0, 1.0];
+const GRAY: Color = [0.6, 0.6, 0.6, 1.0];
+const YELLOW: Color = [1.0, 0.85, 0.2, 1.0];
+
+impl Panel {
+    /**
+     * Creates a closed panel with the first slider selected
+     */
+    pub fn new() -> Panel {
+        Panel {
+            open: false,
+            message: None,
+            selected: 0,
+        }
+    }
+
+    /**
+     * Shows or hides the panel
+     */
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.message = None;
+    }
+
+    /**
+     * Handles the given input, moving the selected slider of the given
+     * settings. Returns what the game should do about it
+     */
+    pub fn input(&mut self, input: Input, settings: &mut settings::Settings) -> Option<Action> {
+        let count = SLIDERS.len();
+        match input {
+            Input::Up => self.selected = (self.selected + count - 1) % count,
+            Input::Down => self.selected = (self.selected + 1) % count,
+            Input::Left => return self.step(settings, -1.0),
+            Input::Right => return self.step(settings, 1.0),
+            Input::Point(position) => {
+                if let Some(index) = slider_at(&position) {
+                    self.selected = index;
+                }
+            }
+            Input::Export => return Some(Action::Export),
+        }
+        None
+    }
+
+    /**
+     * Renders the sliders for the settings in use by a game. Settings
+     * overridden by its level are grayed out, as the sliders do not change them
+     */
+    pub fn render(&self,
+                  settings: &settings::Settings,
+                  overrides: &[(String, f64)],
+                  renderer: &mut Renderer) {
+        renderer.text(WHITE, 16, Point2::new(LEFT, TOP - SPACING), "Physics tuning");
+
+        for (i, slider) in SLIDERS.iter().enumerate() {
+            let value = settings.get(slider.key).unwrap();
+            let overridden = overrides.iter().any(|&(ref key, _)| key == slider.key);
+            let color = if overridden {
+                GRAY
+            } else if i == self.selected {
+                YELLOW
+            } else {
+                WHITE
+            };
+            let mut label = format!("{}: {:.3}", slider.label, value);
+            if overridden {
+                label.push_str(" (level)");
+            }
+
+            // The filled bars are drawn over the whole track, as in the power
+            // meter of the HUD
+            let y = TOP + SPACING * i as f64;
+            let fraction = (value - slider.min) / (slider.max - slider.min);
+            let (filled, track) = bars(fraction);
+            renderer.text(color, 12, Point2::new(LEFT, y), &label);
+            renderer.text(GRAY, 12, Point2::new(LEFT + 0.2, y), &track);
+            renderer.text(color, 12, Point2::new(LEFT + 0.2, y), &filled);
+        }
+
+        let y = TOP + SPACING * SLIDERS.len() as f64;
+        let help = match self.message {
+            Some(ref message) => message.as_str(),
+            None => "Arrow keys to tune, enter to export, tab to close",
+        };
+        renderer.text(GRAY, 12, Point2::new(LEFT, y), help);
+    }
+
+    /**
+     * Moves the selected slider the given number of steps, staying within
+     * its bounds
+     */
+    fn step(&mut self, settings: &mut settings::Settings, steps: f64) -> Option<Action> {
+        let slider = SLIDERS[self.selected];
+        let value = settings.get(slider.key).unwrap();
+        let step = (slider.max - slider.min) / STEPS;
+        let moved = ((value + steps * step - slider.min) / step).round() * step + slider.min;
+        let moved = moved.max(slider.min).min(slider.max);
+        if moved == value {
+            return None;
+        }
+        settings.set(slider.key, moved).unwrap();
+        self.message = None;
+        Some(Action::Changed)
+    }
+}
+
+/**
+ * Returns the index of the slider at the given position, if any
+ */
+fn slider_at(position: &Point2<f64>) -> Option<usize> {
+    if position.x < LEFT || position.x > LEFT + WIDTH {
+        return None;
+    }
+    (0..SLIDERS.len()).position(|index| {
+        let baseline = TOP + SPACING * index as f64;
+        position.y > baseline - 0.7 * SPACING && position.y <= baseline + 0.3 * SPACING
+    })
+}
+
+/**
+ * Returns the filled bars of a slider at the given fraction of its range, and
+ * the bars of the whole slider
+ */
+fn bars(fraction: f64) -> (String, String) {
+    let filled = (fraction.max(0.0).min(1.0) * BARS as f64).round() as usize;
+    (iter::repeat('|').take(filled).collect(), iter::repeat('|').take(BARS).collect())
+}
+
+#[test]
+fn test_sliders() {
+    let mut panel = Panel::new();
+    let mut settings = settings::Settings::new();
+    assert_eq!(panel.input(Input::Right, &mut settings), Some(Action::Changed));
+    assert!((settings.friction - 0.11).abs() < 1e-9);
+
+    // Sliders stop at their bounds, and the scales never reach zero
+    panel.input(Input::Down, &mut settings);
+    for _ in 0..100 {
+        panel.input(Input::Left, &mut settings);
+    }
+    assert!((settings.restitution_scale - 0.1).abs() < 1e-9);
+    assert_eq!(panel.input(Input::Left, &mut settings), None);
+
+    assert_eq!(panel.input(Input::Up, &mut settings), None);
+    assert_eq!(panel.input(Input::Up, &mut settings), None);
+    assert_eq!(panel.selected, SLIDERS.len() - 1);
+    assert_eq!(panel.input(Input::Export, &mut settings), Some(Action::Export));
+}
+
+#[test]
+fn test_pointing() {
+    let mut panel = Panel::new();
+    let mut settings = settings::Settings::new();
+    panel.input(Input::Point(Point2::new(0.1, TOP + 2.0 * SPACING)), &mut settings);
+    assert_eq!(panel.selected, 2);
+    panel.input(Input::Point(Point2::new(0.9, TOP)), &mut settings);
+    assert_eq!(panel.selected, 2);
+    assert_eq!(slider_at(&Point2::new(0.1, TOP + SPACING * 0.2)), Some(0));
+}
+
+#[test]
+fn test_bars() {
+    assert_eq!(bars(0.0).0, "");
+    assert_eq!(bars(2.0).0.len(), BARS);
+    assert_eq!(bars(0.5).1.len(), BARS);
+}
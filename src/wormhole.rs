@@ -0,0 +1,150 @@
+use na::{Norm, Point2, Vector1, Vector2, Rotation2, Rotate};
+use poolball;
+use renderer;
+use std::f64;
+
+/**
+ * A single end of a wormhole, facing in the direction given by its orientation
+ * (in radians)
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Portal {
+    pub position: Point2<f64>,
+    pub radius: f64,
+    pub orientation: f64,
+}
+
+/**
+ * Wormhole consisting of two linked portals. A poolball entering either portal
+ * emerges from the other with its velocity rotated by the relative orientation
+ * of the two portals and scaled by the speed multiplier
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wormhole {
+    pub portals: [Portal; 2],
+    pub speed_multiplier: f64,
+}
+
+const RADIUS: f64 = 0.03;
+
+// Extra distance put between an emerging poolball and the exit portal, so that
+// it is never considered to be entering the exit portal again
+const EXIT_MARGIN: f64 = 0.001;
+
+impl Portal {
+    /**
+     * Creates a new portal at the given position facing the given orientation
+     */
+    pub fn new(position: Point2<f64>, orientation: f64) -> Portal {
+        Portal {
+            position: position,
+            radius: RADIUS,
+            orientation: orientation,
+        }
+    }
+}
+
+impl Wormhole {
+    /**
+     * Constructs a new Wormhole linking the two given portals
+     */
+    pub fn new(first: Portal, second: Portal, speed_multiplier: f64) -> Wormhole {
+        Wormhole {
+            portals: [first, second],
+            speed_multiplier: speed_multiplier,
+        }
+    }
+
+    /**
+     * Sends the given poolball through the wormhole, entering through the portal
+     * with the given index and emerging just outside the other portal
+     */
+    pub fn teleport(&self, ball: &mut poolball::Poolball, entrance: usize) {
+        let ref from = self.portals[entrance];
+        let ref to = self.portals[1 - entrance];
+
+        // Rotate the velocity by the relative orientation of the portals
+        let angle = Vector1::new(to.orientation - from.orientation);
+        let rotation = Rotation2::new(angle);
+        let velocity = rotation.rotate(&ball.velocity) * self.speed_multiplier;
+
+        // Emerge in the direction of travel, or in the direction the exit
+        // portal faces if the ball has no velocity to speak of
+        let direction = if velocity == Vector2::new(0.0, 0.0) {
+            Vector2::new(to.orientation.cos(), to.orientation.sin())
+        } else {
+            velocity.normalize()
+        };
+
+        let distance = to.radius + ball.radius + EXIT_MARGIN;
+        let mut position = to.position + direction * distance;
+
+        // Keep the ball on the table
+        position.x = position.x.max(ball.radius).min(1.0 - ball.radius);
+        position.y = position.y.max(ball.radius).min(1.0 - ball.radius);
+
+        ball.position = position;
+        ball.set_velocity(velocity);
+    }
+
+    /**
+     * Renders itself using the given graphics
+     */
+    pub fn render(&self, renderer: &mut renderer::Renderer) {
+
+        const CYAN: [f32; 4] = [0.0, 1.0, 1.0, 1.0];
+
+        for portal in self.portals.iter() {
+            // Draw the portal ring
+            renderer.ring(CYAN, portal.position, portal.radius, 0.004);
+
+            // Draw a short line showing which way the portal faces
+            let facing = Vector2::new(portal.orientation.cos(), portal.orientation.sin()) *
+                         portal.radius;
+            renderer.line(CYAN, portal.position, portal.position + facing, 0.002);
+        }
+    }
+}
+
+#[test]
+fn test_teleport_keeps_velocity_for_equal_orientations() {
+    let wormhole = Wormhole::new(Portal::new(Point2::new(0.2, 0.5), 0.0),
+                                 Portal::new(Point2::new(0.8, 0.5), 0.0),
+                                 1.0);
+    let mut ball = poolball::Poolball::new(Point2::new(0.2, 0.5), poolball::BallType::Red);
+    ball.set_velocity(Vector2::new(1.0, 0.0));
+    wormhole.teleport(&mut ball, 0);
+
+    assert_eq!(ball.velocity, Vector2::new(1.0, 0.0));
+    assert!(ball.position.x > 0.8 + RADIUS);
+    assert_eq!(ball.position.y, 0.5);
+}
+
+#[test]
+fn test_teleport_rotates_and_scales_velocity() {
+    let wormhole = Wormhole::new(Portal::new(Point2::new(0.2, 0.5), 0.0),
+                                 Portal::new(Point2::new(0.5, 0.5), f64::consts::PI / 2.0),
+                                 2.0);
+    let mut ball = poolball::Poolball::new(Point2::new(0.5, 0.5), poolball::BallType::Red);
+    ball.set_velocity(Vector2::new(-1.0, 0.0));
+    wormhole.teleport(&mut ball, 1);
+
+    // Rotated a quarter turn and doubled in speed
+    assert!((ball.velocity.x - 0.0).abs() < 0.0001);
+    assert!((ball.velocity.y - 2.0).abs() < 0.0001);
+    assert!(ball.position.y > 0.5 + RADIUS);
+}
+
+#[test]
+fn test_teleport_emerges_outside_exit() {
+    let wormhole = Wormhole::new(Portal::new(Point2::new(0.2, 0.5), 0.0),
+                                 Portal::new(Point2::new(0.5, 0.5), 0.0),
+                                 1.0);
+    let mut ball = poolball::Poolball::new(Point2::new(0.2, 0.5), poolball::BallType::Red);
+    wormhole.teleport(&mut ball, 0);
+
+    let ref exit = wormhole.portals[1];
+    let dx = ball.position.x - exit.position.x;
+    let dy = ball.position.y - exit.position.y;
+    assert!((dx * dx + dy * dy).sqrt() > exit.radius + ball.radius);
+}